@@ -56,6 +56,8 @@ packer_startup(packer.use)
 
 vim.api.nvim_create_autocmd('BufWritePost', { command = 'lua Codex.update_word_count()' })
 vim.api.nvim_create_autocmd('BufWritePost', { command = 'lua Codex.tick_updated()' })
+vim.api.nvim_create_autocmd('BufWritePost',
+	{ pattern = 'codex.toml', command = 'lua Codex.reload_config()' })
 require('lualine').setup { sections = { lualine_c = { "g:word_count", "filename" } } }
 require('telescope').load_extension('codex')
 vim.cmd [[ autocmd BufEnter *.md hi nodelink ctermfg=cyan guifg=cyan cterm=bold,underline gui=bold ]]
@@ -43,6 +43,22 @@ if config.git_remote ~= nil then
     vim.fn.setenv("CODEX_GIT_REMOTE", config.git_remote)
 end
 
+-- rpcrequest that shows backend errors with vim.notify instead of raising,
+-- returns nil followed by the error code and message when the request fails
+function M.request(name, ...)
+    local ok, result = pcall(vim.rpcrequest, _t.job_id, name, ...)
+    if ok then
+        return result
+    end
+    local err = tostring(result)
+    local code, message = err:match("\n([%w%-]+): (.*)$")
+    if code == nil then
+        code, message = "unknown", err
+    end
+    vim.notify("codex " .. name .. " failed, " .. code .. ": " .. message, vim.log.levels.ERROR)
+    return nil, code, message
+end
+
 function M.start()
     if _t.job_id ~= nil then
         return
@@ -58,7 +74,7 @@ function M.stop()
     if _t.job_id == nil then
         return
     end
-    vim.rpcrequest(_t.job_id, "stop")
+    M.request("stop")
 
     -- vim.rpcnotify(_t.job_id, "stop")
     -- vim.fn.jobstop(_t.job_id)
@@ -76,11 +92,11 @@ end
 
 function M.chk(arg)
     print("sending chk ", arg)
-    vim.rpcrequest(_t.job_id, "chk", arg)
+    M.request("chk", arg)
 end
 
 function M.get_nodes()
-    return vim.rpcrequest(_t.job_id, "nodes")
+    return M.request("nodes")
 end
 
 function M.entry_maker(node)
@@ -283,9 +299,15 @@ function M.follow_link()
         print("No link under text")
         return
     end
-    local target = vim.rpcrequest(_t.job_id, "follow-link", curr_node, text)
-    if target ~= nil then
+    local target, code = M.request("follow-link", curr_node, text)
+    if code ~= nil then
+        return
+    end
+    if target ~= vim.NIL then
         vim.cmd("e +" .. target.line .. " " .. target.node .. "/_.md")
+        if target.line > 0 then
+            vim.api.nvim_win_set_cursor(0, { target.line, target.char })
+        end
         M._push_breadcrumb(curr_node)
     else
         print("Unable to retrieve link from backend")
@@ -393,8 +415,8 @@ function M.link_from_visual()
                             actions.close(prompt_bufnr)
                             local line = action_state.get_selected_entry()
                             -- M.debug(line)
-                            vim.rpcrequest(_t.job_id, "link", text, curr_node, ln, col, target.ordinal, line.ordinal, 0)
-                            -- vim.rpcrequest(_t.job_id, "debug", curr_node, ln, col, target.ordinal, line.ordinal, 0 )
+                            M.request("link", text, curr_node, ln, col, target.ordinal, line.ordinal, 0)
+                            -- M.request("debug", curr_node, ln, col, target.ordinal, line.ordinal, 0 )
                         end)
                         return true
                     end
@@ -442,7 +464,7 @@ function M.name_link()
                 text = string.gsub(text, "^%d+-", "")
                 text = string.gsub(text, "-", " ")
                 -- M.debug(text)
-                vim.rpcrequest(_t.job_id, "link", text, curr_node, ln_num, col, target.ordinal, 0, 0)
+                M.request("link", text, curr_node, ln_num, col, target.ordinal, 0, 0)
                 local nline = ln:sub(0, col) .. '[[' .. text .. ']]' .. ln:sub(col + 1)
                 vim.api.nvim_set_current_line(nline)
             end)
@@ -476,6 +498,13 @@ end
 map('n', '<leader>t', ':lua Codex["todo"]()<CR>', opt)
 map('i', '<C-t>', ':lua Codex["todo"]()<CR>', opt)
 map('n', '<leader>f', ":lua Codex.nodes() <CR>", opt)
+map('n', '<leader>s', ":lua Codex.search() <CR>", opt)
+map('n', '<leader>T', ":lua Codex.tasks() <CR>", opt)
+map('n', '<leader>#', ":lua Codex.tags() <CR>", opt)
+map('n', '<leader>M', ":lua Codex.mentions() <CR>", opt)
+map('n', '<leader><leader>t', ":lua Codex.add_tag() <CR>", opt)
+map('n', '<leader>m', ":lua Codex.move_node() <CR>", opt)
+map('n', '<leader>a', ":lua Codex.archive_node() <CR>", opt)
 map('n', '<leader>c', ":lua Codex.children() <CR>", opt)
 map('n', '<leader>p', ":lua Codex.parent() <CR>", opt)
 map('n', '<leader>n', ":lua Codex.new_node() <CR>", opt)
@@ -509,30 +538,62 @@ function M.tick_updated()
     vim.rpcnotify(_t.job_id, "tick-updated", curr_node)
 end
 
+-- called by the backend when nodes change on disk outside of neovim,
+-- changes: { { change = "added"|"removed"|"updated", id = "2-desk/1-a" }, ... }
+function M.tree_changed(changes)
+    vim.cmd("checktime")
+    local added, removed = 0, 0
+    for _, change in ipairs(changes) do
+        if change.change == "added" then
+            added = added + 1
+        elseif change.change == "removed" then
+            removed = removed + 1
+        end
+    end
+    if added > 0 or removed > 0 then
+        vim.notify("codex: " .. added .. " node(s) added, " .. removed .. " removed on disk", vim.log.levels.INFO)
+    end
+end
+
 function M.parent()
     local curr_node = M.current_node()
-    local parent = vim.rpcrequest(_t.job_id, "parent", curr_node)
+    local parent = M.request("parent", curr_node)
+    if parent == nil then
+        return
+    end
     vim.cmd("e " .. parent .. "/_.md")
 end
 
 function M.latest_journal()
-    local latest = vim.rpcrequest(_t.job_id, "latest-journal")
+    local latest = M.request("latest-journal")
+    if latest == nil then
+        return
+    end
     vim.cmd("e " .. latest .. "/_.md")
 end
 
 function M.prev_sibling()
-    local sibling = vim.rpcrequest(_t.job_id, "prev-sibling", M.current_node())
+    local sibling = M.request("prev-sibling", M.current_node())
+    if sibling == nil then
+        return
+    end
     vim.cmd("e " .. sibling .. "/_.md")
 end
 
 function M.next_sibling()
-    local sibling = vim.rpcrequest(_t.job_id, "next-sibling", M.current_node())
+    local sibling = M.request("next-sibling", M.current_node())
+    if sibling == nil then
+        return
+    end
     vim.cmd("e " .. sibling .. "/_.md")
 end
 
 function M.children()
     local curr_node = string.gsub(vim.fn.expand("%"), "/_.md", "")
-    local nodes = vim.rpcrequest(_t.job_id, "children", curr_node)
+    local nodes = M.request("children", curr_node)
+    if nodes == nil then
+        return
+    end
     local finder_fn = Finder.new_table({
         results = nodes,
         entry_maker = M.entry_maker
@@ -552,26 +613,653 @@ function M.children()
     return picker:find()
 end
 
-function M.article_note()
-    vim.ui.input({ prompt = "ARTICLE Note:" },
-        function(name)
-            if name ~= nil and name ~= "" then
-                M["create"]("5-notes/1-articles", name)
+function M.move_node()
+    local curr_node = M.current_node()
+    vim.cmd("w")
+    local nodes = M.get_nodes()
+    local picker = Picker:new({
+        prompt_title = 'move ' .. curr_node .. ' under',
+        finder = Finder.new_table({
+            results = nodes,
+            entry_maker = function(node)
+                return { value = node.id, display = node.display, ordinal = node.display }
             end
+        }),
+        sorter = Sorter.get_generic_fuzzy_sorter(),
+        attach_mappings = function(prompt_bufnr, map)
+            actions.select_default:replace(function()
+                actions.close(prompt_bufnr)
+                local parent = action_state.get_selected_entry()
+                local moved = M.request("move", curr_node, parent.value)
+                if moved == nil then
+                    return
+                end
+                vim.cmd("bwipeout")
+                vim.cmd("e " .. moved .. "/_.md")
+            end)
+            return true
+        end
+    })
+    picker:find()
+end
+
+function M.delete_node()
+    local curr_node = M.current_node()
+    local choice = vim.fn.confirm("Delete " .. curr_node .. "?", "&Yes\n&With children\n&No", 3)
+    if choice == 3 or choice == 0 then
+        return
+    end
+    local removal = M.request("delete", curr_node, choice == 2)
+    if removal == nil then
+        return
+    end
+    vim.cmd("bwipeout!")
+    if #removal.dangling > 0 then
+        local items = {}
+        for _, link in ipairs(removal.dangling) do
+            table.insert(items, {
+                filename = link.id .. '/_.md',
+                lnum = link.line,
+                col = link.char,
+                text = "dangling [[" .. link.text .. "]] -> " .. link.target,
+            })
+        end
+        vim.fn.setqflist(items)
+        vim.cmd("copen")
+    end
+    print("deleted " .. #removal.removed .. " node(s), " .. #removal.dangling .. " dangling link(s)")
+end
+
+function M.archive_node()
+    local curr_node = M.current_node()
+    vim.cmd("w")
+    local archived = M.request("archive", curr_node, true)
+    if archived == nil then
+        return
+    end
+    vim.cmd("bwipeout")
+    vim.cmd("e " .. archived .. "/_.md")
+end
+
+function M.fsck(repair)
+    local problems = M.request("fsck", repair == true)
+    if problems == nil then
+        return
+    end
+    if #problems == 0 then
+        print("codex fsck: no problems")
+        return
+    end
+    local items = {}
+    for _, problem in ipairs(problems) do
+        local item = { text = problem.display }
+        if problem.id ~= nil and problem.id ~= vim.NIL then
+            item.filename = problem.id .. '/_.md'
+        end
+        table.insert(items, item)
+    end
+    vim.fn.setqflist(items)
+    vim.cmd("copen")
+end
+
+-- read codex.toml again, an invalid config is reported and the last
+-- good one kept
+function M.reload_config()
+    local _, code = M.request("reload-config")
+    if code == nil then
+        print("codex.toml reloaded")
+    end
+end
+
+function M.search_entry_maker(hit)
+    return {
+        value = hit.id .. '/_.md',
+        display = hit.display .. ':' .. hit.line .. ' ' .. hit.snippet,
+        ordinal = hit.display .. ' ' .. hit.snippet,
+        lnum = hit.line,
+    }
+end
+
+function M.search()
+    vim.ui.input({ prompt = "Search codex:" },
+        function(query)
+            if query == nil or query == "" then
+                return
+            end
+            local hits = M.request("search", query)
+            if hits == nil then
+                return
+            end
+            local picker = Picker:new({
+                prompt_title = 'search: ' .. query,
+                finder = Finder.new_table({
+                    results = hits,
+                    entry_maker = M.search_entry_maker
+                }),
+                sorter = Sorter.get_generic_fuzzy_sorter(),
+                previewer = require('telescope.previewers').new_termopen_previewer({
+                    get_command = function(entry)
+                        return { 'bat', '--style=plain', '--highlight-line', entry.lnum, entry.value }
+                    end,
+                }),
+                attach_mappings = function(prompt_bufnr, map)
+                    actions.select_default:replace(function()
+                        actions.close(prompt_bufnr)
+                        local hit = action_state.get_selected_entry()
+                        vim.cmd("e +" .. hit.lnum .. " " .. hit.value)
+                    end)
+                    return true
+                end
+            })
+            picker:find()
         end
     )
 end
 
-function M.idea_note()
-    vim.ui.input({ prompt = "IDEA Note:" },
-        function(name)
-            if name ~= nil and name ~= "" then
-                M["create"]("5-notes/2-ideas", name)
+function M.task_entry_maker(task)
+    local due = ""
+    if task.due ~= nil then
+        due = " @" .. task.due
+    end
+    local marker = "[]"
+    if task.state == "done" then
+        marker = "✅"
+    elseif task.state == "migrated" then
+        marker = "[>]"
+    end
+    return {
+        value = task.id .. '/_.md',
+        display = marker .. " " .. task.text .. due .. "  (" .. task.display .. ":" .. task.line .. ")",
+        ordinal = task.text .. ' ' .. task.display,
+        lnum = task.line,
+    }
+end
+
+-- filter: { state = "open"|"done"|"migrated"|"all", node = "2-desk",
+--           due_before = "2026-10-20", has_due = true, priority = 1..3, text = "..." }
+function M.tasks(filter)
+    local tasks = M.request("tasks", filter or vim.empty_dict())
+    if tasks == nil then
+        return
+    end
+    local picker = Picker:new({
+        prompt_title = 'tasks',
+        finder = Finder.new_table({
+            results = tasks,
+            entry_maker = M.task_entry_maker
+        }),
+        sorter = Sorter.get_generic_fuzzy_sorter(),
+        previewer = require('telescope.previewers').new_termopen_previewer({
+            get_command = function(entry)
+                return { 'bat', '--style=plain', '--highlight-line', entry.lnum, entry.value }
+            end,
+        }),
+        attach_mappings = function(prompt_bufnr, map)
+            actions.select_default:replace(function()
+                actions.close(prompt_bufnr)
+                local task = action_state.get_selected_entry()
+                vim.cmd("e +" .. task.lnum .. " " .. task.value)
+            end)
+            return true
+        end
+    })
+    picker:find()
+end
+
+function M.nodes_by_tag(tag)
+    local nodes = M.request("nodes-by-tag", tag)
+    if nodes == nil then
+        return
+    end
+    local picker = Picker:new({
+        prompt_title = '#' .. tag,
+        finder = Finder.new_table({
+            results = nodes,
+            entry_maker = M.entry_maker
+        }),
+        sorter = Sorter.get_generic_fuzzy_sorter(),
+        previewer = require('telescope.previewers').new_termopen_previewer({
+            get_command = function(entry)
+                return { 'bat', '--style=plain', entry.value }
+            end,
+        }),
+    })
+    picker:find()
+end
+
+-- pick a tag, then one of the nodes carrying it or a tag below it
+function M.tags()
+    local tags = M.request("tags")
+    if tags == nil then
+        return
+    end
+    local picker = Picker:new({
+        prompt_title = 'tags',
+        finder = Finder.new_table({
+            results = tags,
+            entry_maker = function(tag)
+                return { value = tag.id, display = tag.display, ordinal = tag.id }
+            end
+        }),
+        sorter = Sorter.get_generic_fuzzy_sorter(),
+        attach_mappings = function(prompt_bufnr, map)
+            actions.select_default:replace(function()
+                actions.close(prompt_bufnr)
+                M.nodes_by_tag(action_state.get_selected_entry().value)
+            end)
+            return true
+        end
+    })
+    picker:find()
+end
+
+function M.add_tag()
+    local curr_node = M.current_node()
+    vim.ui.input({ prompt = "Tag " .. curr_node .. ":" },
+        function(tag)
+            if tag ~= nil and tag ~= "" then
+                local added = M.request("add-tag", curr_node, tag)
+                if added ~= nil then
+                    print("tagged #" .. added)
+                end
+            end
+        end
+    )
+end
+
+function M.remove_tag()
+    local curr_node = M.current_node()
+    vim.ui.input({ prompt = "Untag " .. curr_node .. ":" },
+        function(tag)
+            if tag ~= nil and tag ~= "" then
+                M.request("remove-tag", curr_node, tag)
             end
         end
     )
 end
 
+function M.rename_tag()
+    vim.ui.input({ prompt = "Rename tag:" },
+        function(from)
+            if from == nil or from == "" then
+                return
+            end
+            vim.ui.input({ prompt = "Rename #" .. from .. " to:" },
+                function(to)
+                    if to == nil or to == "" then
+                        return
+                    end
+                    local renamed = M.request("rename-tag", from, to)
+                    if renamed ~= nil then
+                        vim.cmd("checktime")
+                        print("renamed #" .. from .. " in " .. #renamed .. " node(s)")
+                    end
+                end
+            )
+        end
+    )
+end
+
+-- unlinked mentions of the current node, <CR> jumps to one and <C-l>
+-- links the selected mentions, or all of them when none are selected
+function M.mentions()
+    local curr_node = M.current_node()
+    vim.cmd("w")
+    local mentions = M.request("mentions", curr_node)
+    if mentions == nil then
+        return
+    end
+    if #mentions == 0 then
+        print("no unlinked mentions of " .. curr_node)
+        return
+    end
+    local picker = Picker:new({
+        prompt_title = 'mentions of ' .. curr_node,
+        finder = Finder.new_table({
+            results = mentions,
+            entry_maker = function(mention)
+                return {
+                    value = mention.id .. '/_.md',
+                    display = mention.display,
+                    ordinal = mention.display,
+                    lnum = mention.line,
+                    mention = mention,
+                }
+            end
+        }),
+        sorter = Sorter.get_generic_fuzzy_sorter(),
+        previewer = require('telescope.previewers').new_termopen_previewer({
+            get_command = function(entry)
+                return { 'bat', '--style=plain', '--highlight-line', entry.lnum, entry.value }
+            end,
+        }),
+        attach_mappings = function(prompt_bufnr, map)
+            actions.select_default:replace(function()
+                actions.close(prompt_bufnr)
+                local entry = action_state.get_selected_entry()
+                vim.cmd("e +" .. entry.lnum .. " " .. entry.value)
+                vim.api.nvim_win_set_cursor(0, { entry.lnum, entry.mention.char })
+            end)
+            local link_mentions = function()
+                local picked = {}
+                for _, entry in ipairs(action_state.get_current_picker(prompt_bufnr):get_multi_selection()) do
+                    table.insert(picked, entry.mention)
+                end
+                if #picked == 0 then
+                    picked = mentions
+                end
+                actions.close(prompt_bufnr)
+                local linked = M.request("link-mentions", curr_node, picked)
+                if linked ~= nil then
+                    vim.cmd("checktime")
+                    print("linked " .. linked .. " mention(s) of " .. curr_node)
+                end
+            end
+            map('i', '<C-l>', link_mentions)
+            map('n', '<C-l>', link_mentions)
+            return true
+        end
+    })
+    picker:find()
+end
+
+-- the node graph as "dot", "json" or "graphml" in a new buffer, options are
+-- { edges = "hierarchy"|"links"|"both", root = <node>, tag = <tag> }
+function M.graph(format, options)
+    format = format or "dot"
+    local graph = M.request("graph", format, options or vim.empty_dict())
+    if graph == nil then
+        return
+    end
+    local filetypes = { dot = "dot", json = "json", graphml = "xml" }
+    vim.cmd("enew")
+    vim.bo.filetype = filetypes[format]
+    vim.api.nvim_buf_set_lines(0, 0, -1, false, vim.split(graph, "\n", { trimempty = true }))
+end
+
+-- a picker over node entries ({ id, display }) that opens the picked node
+function M.pick_nodes(title, nodes)
+    local picker = Picker:new({
+        prompt_title = title,
+        finder = Finder.new_table({
+            results = nodes,
+            entry_maker = M.entry_maker
+        }),
+        sorter = Sorter.get_generic_fuzzy_sorter(),
+        previewer = require('telescope.previewers').new_termopen_previewer({
+            get_command = function(entry)
+                return { 'bat', '--style=plain', entry.value }
+            end,
+        }),
+    })
+    picker:find()
+end
+
+-- weekly review: nodes without any links
+function M.orphans()
+    local orphans = M.request("orphans")
+    if orphans ~= nil then
+        M.pick_nodes('orphans', orphans)
+    end
+end
+
+-- weekly review: the most linked nodes
+function M.hubs(count)
+    local hubs = M.request("hubs", count or 10)
+    if hubs ~= nil then
+        M.pick_nodes('hubs', hubs)
+    end
+end
+
+-- weekly review: old nodes linked with what was worked on today
+function M.stale(days)
+    local stale = M.request("stale", days or 30)
+    if stale ~= nil then
+        M.pick_nodes('untouched in ' .. (days or 30) .. ' days, linked with today', stale)
+    end
+end
+
+-- pick a cluster of linked nodes, then a node within it
+function M.clusters()
+    local clusters = M.request("clusters")
+    if clusters == nil then
+        return
+    end
+    local picker = Picker:new({
+        prompt_title = 'link clusters',
+        finder = Finder.new_table({
+            results = clusters,
+            entry_maker = function(cluster)
+                local names = {}
+                for _, node in ipairs(cluster) do
+                    table.insert(names, node.display)
+                end
+                local display = #cluster .. " nodes: " .. table.concat(names, ", ")
+                return { value = cluster, display = display, ordinal = display }
+            end
+        }),
+        sorter = Sorter.get_generic_fuzzy_sorter(),
+        attach_mappings = function(prompt_bufnr, map)
+            actions.select_default:replace(function()
+                actions.close(prompt_bufnr)
+                M.pick_nodes('cluster', action_state.get_selected_entry().value)
+            end)
+            return true
+        end
+    })
+    picker:find()
+end
+
+-- the shortest chain of links from the current node to a picked node
+function M.shortest_path()
+    local curr_node = M.current_node()
+    local picker = Picker:new({
+        prompt_title = 'path from ' .. curr_node .. ' to',
+        finder = Finder.new_table({
+            results = M.get_nodes(),
+            entry_maker = function(node)
+                return { value = node.id, display = node.display, ordinal = node.display }
+            end
+        }),
+        sorter = Sorter.get_generic_fuzzy_sorter(),
+        attach_mappings = function(prompt_bufnr, map)
+            actions.select_default:replace(function()
+                actions.close(prompt_bufnr)
+                local target = action_state.get_selected_entry().value
+                local path, code = M.request("shortest-path", curr_node, target)
+                if code ~= nil then
+                    return
+                end
+                if path == vim.NIL then
+                    print(curr_node .. " and " .. target .. " are not linked")
+                    return
+                end
+                M.pick_nodes('path to ' .. target, path)
+            end)
+            return true
+        end
+    })
+    picker:find()
+end
+
+-- render the codex as a static html site, options are
+-- { roots = { <node>, ... }, tags = { <tag>, ... } }, everything without either
+function M.export_site(options)
+    vim.ui.input({ prompt = "Export site to:", completion = "dir" },
+        function(out)
+            if out == nil or out == "" then
+                return
+            end
+            local pages = M.request("export-site", vim.fn.fnamemodify(out, ":p"), options or vim.empty_dict())
+            if pages ~= nil then
+                print(pages .. " pages written to " .. out)
+            end
+        end
+    )
+end
+
+function M.export_vault(options)
+    vim.ui.input({ prompt = "Export vault to:", completion = "dir" },
+        function(out)
+            if out == nil or out == "" then
+                return
+            end
+            local notes = M.request("export-vault", vim.fn.fnamemodify(out, ":p"), options or vim.empty_dict())
+            if notes ~= nil then
+                print(notes .. " notes written to " .. out)
+            end
+        end
+    )
+end
+
+function M.import_obsidian(parent)
+    vim.ui.input({ prompt = "Obsidian vault:", completion = "dir" },
+        function(vault)
+            if vault == nil or vault == "" then
+                return
+            end
+            vault = vim.fn.fnamemodify(vault, ":p")
+            local stats
+            if parent ~= nil then
+                stats = M.request("import-obsidian", vault, parent)
+            else
+                stats = M.request("import-obsidian", vault)
+            end
+            if stats == nil then
+                return
+            end
+            vim.cmd("e " .. stats.id .. "/_.md")
+            print(string.format("imported %d notes, %d folders, %d links (%d unresolved), %d attachments",
+                stats.notes, stats.folders, stats.links, stats.unresolved, stats.attachments))
+        end
+    )
+end
+
+-- ask each of a template's prompts in turn, then call done with the answers
+local function ask_prompts(prompts, done, answers, i)
+    answers = answers or vim.empty_dict()
+    i = i or 1
+    if i > #prompts then
+        done(answers)
+        return
+    end
+    vim.ui.input({ prompt = prompts[i] .. ":" },
+        function(answer)
+            if answer == nil then
+                return
+            end
+            answers[prompts[i]] = answer
+            ask_prompts(prompts, done, answers, i + 1)
+        end
+    )
+end
+
+-- create a child of parent from a template, picking one from
+-- <codex>/templates when none is given
+function M.new_from_template(parent, template)
+    local templates = M.request("templates")
+    if templates == nil then
+        return
+    end
+    local create = function(picked)
+        vim.ui.input({ prompt = picked.id .. " under " .. parent .. ":" },
+            function(name)
+                if name == nil or name == "" then
+                    return
+                end
+                ask_prompts(picked.prompts, function(answers)
+                    M["create"](parent, name, picked.id, answers)
+                end)
+            end
+        )
+    end
+    if template ~= nil then
+        for _, found in ipairs(templates) do
+            if found.id == template then
+                return create(found)
+            end
+        end
+        vim.notify("codex has no template named " .. template, vim.log.levels.ERROR)
+        return
+    end
+    local picker = Picker:new({
+        prompt_title = 'templates',
+        finder = Finder.new_table({
+            results = templates,
+            entry_maker = function(found)
+                return { value = found, display = found.display, ordinal = found.display }
+            end
+        }),
+        sorter = Sorter.get_generic_fuzzy_sorter(),
+        attach_mappings = function(prompt_bufnr, map)
+            actions.select_default:replace(function()
+                actions.close(prompt_bufnr)
+                create(action_state.get_selected_entry().value)
+            end)
+            return true
+        end
+    })
+    picker:find()
+end
+
+-- create a node from a template under the node, or the tag, that uses it,
+-- picking between them when several do and the current node when none does
+function M.template_note(template)
+    local parents = M.request("template-parents", template)
+    if parents == nil then
+        return
+    end
+    if #parents == 0 then
+        return M.new_from_template(M.current_node(), template)
+    end
+    if #parents == 1 then
+        return M.new_from_template(parents[1].id, template)
+    end
+    local picker = Picker:new({
+        prompt_title = template .. ' under',
+        finder = Finder.new_table({
+            results = parents,
+            entry_maker = function(node)
+                return { value = node.id, display = node.display, ordinal = node.display }
+            end
+        }),
+        sorter = Sorter.get_generic_fuzzy_sorter(),
+        attach_mappings = function(prompt_bufnr, map)
+            actions.select_default:replace(function()
+                actions.close(prompt_bufnr)
+                M.new_from_template(action_state.get_selected_entry().value, template)
+            end)
+            return true
+        end
+    })
+    picker:find()
+end
+
+-- the template new children of the current node start from, empty to unset
+function M.set_template()
+    local curr_node = M.current_node()
+    vim.ui.input({ prompt = "Template for children of " .. curr_node .. ":" },
+        function(template)
+            if template == nil then
+                return
+            end
+            if template == "" then
+                M.request("set-template", curr_node)
+            else
+                M.request("set-template", curr_node, template)
+            end
+        end
+    )
+end
+
+function M.article_note()
+    M.template_note("article")
+end
+
+function M.idea_note()
+    M.template_note("idea")
+end
+
 setmetatable(M, {
     __index = function(t, k)
         if _t.job_id == nil then
@@ -0,0 +1,677 @@
+use crate::config::Config;
+use crate::error::Error;
+use crate::export::graph::{render, GraphFormat};
+use crate::export::obsidian::{export_vault, Layout, VaultOptions};
+use crate::export::site::{export_site, SiteOptions};
+use crate::git::diff::diff_w_commit;
+use crate::git::sync::{pull_branch, push_all};
+use crate::git::{commit_all, get_ancestor_with_main_branch, repo, stage_all};
+use crate::import::obsidian::import_vault;
+use crate::node::format_display_name;
+use crate::node::markdown::extract_links;
+use crate::tree::graph::{Edges, GraphOptions};
+use crate::tree::{fsck, Tree};
+use chrono::Local;
+use serde_json::{json, Value};
+use std::env;
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
+
+pub const USAGE: &str = "usage: codex [--json] [--dir <codex>] <command>
+
+commands:
+    new [<parent>] <name>     create a node (a root node without a parent)
+    ls [<node>]               list root nodes or the children of a node
+    today                     print (and create if needed) today's journal node
+    link <text> <from> <to> [<from line>] [<to line>]
+                              link two nodes
+    search <query>            full text search over node bodies
+    sync                      pull the main branch, commit and push
+    stats                     node, link and word counts
+    fsck [--repair]           check (and repair) the codex
+    graph [--format dot|json|graphml] [--edges hierarchy|links|both]
+          [--root <node>] [--tag <tag>]
+                              print the node hierarchy and link network
+    site <dir> [--root <node>]... [--tag <tag>]...
+                              render the nodes below the roots and with the
+                              tags (all nodes without either) as html
+    vault <dir> [--flat] [--root <node>]
+                              write the nodes as markdown notes with yaml
+                              frontmatter that obsidian can open, in folders
+                              following the hierarchy unless flat
+    import <vault> [<parent>] import an obsidian vault as a node under parent
+                              (the desk by default)
+
+without a command codex runs as a neovim RPC backend";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    New {
+        parent: Option<String>,
+        name: String,
+    },
+    Ls {
+        node: Option<String>,
+    },
+    Today,
+    Link {
+        text: String,
+        from: String,
+        to: String,
+        from_line: u64,
+        to_line: u64,
+    },
+    Search {
+        query: String,
+    },
+    Sync,
+    Stats,
+    Fsck {
+        repair: bool,
+    },
+    Graph {
+        format: GraphFormat,
+        options: GraphOptions,
+    },
+    Site {
+        out: PathBuf,
+        options: SiteOptions,
+    },
+    Vault {
+        out: PathBuf,
+        options: VaultOptions,
+    },
+    Import {
+        vault: PathBuf,
+        parent: Option<String>,
+    },
+    Help,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Cli {
+    pub command: Command,
+    pub json: bool,
+    pub dir: Option<PathBuf>,
+}
+
+/// Flags only some commands take, checked once the command is known
+const COMMAND_FLAGS: [&str; 6] = [
+    "--repair", "--flat", "--format", "--edges", "--root", "--tag",
+];
+
+impl Command {
+    /// Flags besides `--json` and `--dir` the command takes
+    fn flags(&self) -> &'static [&'static str] {
+        match self {
+            Command::Fsck { .. } => &["--repair"],
+            Command::Graph { .. } => &["--format", "--edges", "--root", "--tag"],
+            Command::Site { .. } => &["--root", "--tag"],
+            Command::Vault { .. } => &["--flat", "--root"],
+            _ => &[],
+        }
+    }
+}
+
+fn line_arg(arg: Option<&String>) -> Result<u64, String> {
+    match arg {
+        None => Ok(0),
+        Some(line) => line
+            .parse::<u64>()
+            .map_err(|_| format!("not a line number: {}", line)),
+    }
+}
+
+/// Parse the arguments after the binary name, `None` means no command
+/// was given and codex should run as a neovim RPC backend
+pub fn parse(args: Vec<String>) -> Result<Option<Cli>, String> {
+    let mut json = false;
+    let mut repair = false;
+    let mut flat = false;
+    let mut dir = None;
+    let mut format = GraphFormat::Dot;
+    let mut edges = Edges::Both;
+    let mut roots = vec![];
+    let mut tags = vec![];
+    let mut positional = vec![];
+    let mut flags = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| args.next().ok_or(format!("{} needs a value", flag));
+        if COMMAND_FLAGS.contains(&arg.as_str()) {
+            flags.push(arg.clone());
+        }
+        match arg.as_str() {
+            "--json" => json = true,
+            "--repair" => repair = true,
+            "--flat" => flat = true,
+            "--dir" => match args.next() {
+                Some(path) => dir = Some(PathBuf::from(path)),
+                None => return Err("--dir needs a path".to_string()),
+            },
+            "--format" => {
+                let value = value("--format")?;
+                format =
+                    GraphFormat::parse(&value).ok_or(format!("not a graph format: {}", value))?;
+            }
+            "--edges" => {
+                let value = value("--edges")?;
+                edges = Edges::parse(&value).ok_or(format!("not an edge kind: {}", value))?;
+            }
+            "--root" => roots.push(value("--root")?),
+            "--tag" => tags.push(value("--tag")?),
+            "-h" | "--help" => positional.insert(0, "help".to_string()),
+            _ => positional.push(arg),
+        }
+    }
+    let (command, rest) = match positional.split_first() {
+        None => return Ok(None),
+        Some((command, rest)) => (command.as_str(), rest),
+    };
+    let name = command;
+    let command = match (command, rest) {
+        ("new", [name]) => Command::New {
+            parent: None,
+            name: name.clone(),
+        },
+        ("new", [parent, name]) => Command::New {
+            parent: Some(parent.clone()),
+            name: name.clone(),
+        },
+        ("ls", []) => Command::Ls { node: None },
+        ("ls", [node]) => Command::Ls {
+            node: Some(node.clone()),
+        },
+        ("today", []) => Command::Today,
+        ("link", [text, from, to, lines @ ..]) if lines.len() <= 2 => Command::Link {
+            text: text.clone(),
+            from: from.clone(),
+            to: to.clone(),
+            from_line: line_arg(lines.first())?,
+            to_line: line_arg(lines.get(1))?,
+        },
+        ("search", query) if !query.is_empty() => Command::Search {
+            query: query.join(" "),
+        },
+        ("sync", []) => Command::Sync,
+        ("stats", []) => Command::Stats,
+        ("fsck", []) => Command::Fsck { repair },
+        ("graph", []) if roots.len() <= 1 && tags.len() <= 1 => Command::Graph {
+            format,
+            options: GraphOptions {
+                edges,
+                root: roots.pop(),
+                tag: tags.pop(),
+            },
+        },
+        ("site", [out]) => Command::Site {
+            out: PathBuf::from(out),
+            options: SiteOptions { roots, tags },
+        },
+        ("vault", [out]) if roots.len() <= 1 => Command::Vault {
+            out: PathBuf::from(out),
+            options: VaultOptions {
+                layout: if flat { Layout::Flat } else { Layout::Nested },
+                root: roots.pop(),
+            },
+        },
+        ("import", [vault, parent @ ..]) if parent.len() <= 1 => Command::Import {
+            vault: PathBuf::from(vault),
+            parent: parent.first().cloned(),
+        },
+        ("help", _) => Command::Help,
+        (command, rest) => {
+            return Err(format!("invalid command: {} {}", command, rest.join(" ")));
+        }
+    };
+    if command != Command::Help {
+        if let Some(flag) = flags
+            .iter()
+            .find(|flag| !command.flags().contains(&flag.as_str()))
+        {
+            return Err(format!("{} does not take {}", name, flag));
+        }
+    }
+    Ok(Some(Cli { command, json, dir }))
+}
+
+fn load_tree() -> crate::tree::Result<Tree> {
+    let pwd = env::current_dir()?;
+    let mut tree = Tree::build(pwd.to_str().unwrap())?;
+    tree.load();
+    if tree.journal.is_empty() || tree.desk.is_empty() {
+        return Err(Error::Tree(format!(
+            "{} is not a codex, no journal and desk root nodes",
+            pwd.display()
+        )));
+    }
+    Ok(tree)
+}
+
+fn node_entries(tree: &Tree, keys: Vec<String>) -> Vec<Value> {
+    keys.into_iter()
+        .map(|key| {
+            let updated = tree.nodes.get(&key).map(|n| n.updated.to_rfc3339());
+            json!({
+                "id": key,
+                "display": format_display_name(&key),
+                "updated": updated,
+            })
+        })
+        .collect()
+}
+
+fn print_nodes(nodes: &[Value], json: bool) {
+    if json {
+        println!("{}", Value::Array(nodes.to_vec()));
+    } else {
+        for node in nodes {
+            println!(
+                "{}\t{}",
+                node["id"].as_str().unwrap(),
+                node["display"].as_str().unwrap()
+            );
+        }
+    }
+}
+
+fn print_key(key: &str, json: bool) {
+    if json {
+        println!(
+            "{}",
+            json!({ "id": key, "display": format_display_name(key) })
+        );
+    } else {
+        println!("{}", key);
+    }
+}
+
+/// Make sure `[[text]]` is in the body at `path`, adding it to the end of
+/// `line` (or of the body when the line is 0 or past the end) when missing.
+/// Returns where the link sits so the body and metadata agree.
+fn place_link(path: &Path, text: &str, line: u64) -> crate::tree::Result<(u64, u64)> {
+    let body = read_to_string(path)?;
+    if let Some(found) = extract_links(&body).into_iter().find(|r| r.text == text) {
+        return Ok((found.line, found.char));
+    }
+    let mut lines: Vec<String> = body.lines().map(String::from).collect();
+    let idx = match line as usize {
+        0 => None,
+        line if line <= lines.len() => Some(line - 1),
+        _ => None,
+    };
+    let (line, char) = match idx {
+        Some(idx) => {
+            if !lines[idx].is_empty() {
+                lines[idx].push(' ');
+            }
+            let char = lines[idx].len();
+            lines[idx].push_str(&format!("[[{}]]", text));
+            (idx + 1, char)
+        }
+        None => {
+            lines.push(format!("[[{}]]", text));
+            (lines.len(), 0)
+        }
+    };
+    write(path, lines.join("\n") + "\n")?;
+    Ok((line as u64, char as u64))
+}
+
+fn stats(tree: &Tree) -> Value {
+    let today = Local::now().date_naive();
+    let words_added = repo().ok().and_then(|repo| {
+        let commit = get_ancestor_with_main_branch(&repo, &tree.config.git.branch).ok()?;
+        diff_w_commit(&repo, &commit).ok()
+    });
+    json!({
+        "nodes": tree.nodes.len(),
+        "journal_days": tree.nodes.get(&tree.journal).map_or(0, |j| j.children.len()),
+        "links": tree.nodes.values().map(|n| n.links.len()).sum::<usize>(),
+        "updated_today": tree
+            .nodes
+            .values()
+            .filter(|n| n.updated.date_naive() == today)
+            .count(),
+        "words_added": words_added,
+    })
+}
+
+fn execute(command: Command, json: bool) -> crate::tree::Result<i32> {
+    match command {
+        Command::Help => println!("{}", USAGE),
+        Command::New { parent, name } => {
+            let mut tree = load_tree()?;
+            let key = tree.create_node(parent.as_deref(), Some(&name))?;
+            stage_all()?;
+            print_key(&key, json);
+        }
+        Command::Ls { node } => {
+            let tree = load_tree()?;
+            let keys = match node {
+                Some(node) => match tree.nodes.get(&node) {
+                    Some(node) => node.children.clone(),
+                    None => {
+                        eprintln!("no node in tree named: {}", node);
+                        return Ok(1);
+                    }
+                },
+                None => tree
+                    .nodes
+                    .values()
+                    .filter(|n| n.parent.is_none())
+                    .map(|n| n.id.clone())
+                    .collect(),
+            };
+            print_nodes(&node_entries(&tree, keys), json);
+        }
+        Command::Today => {
+            let mut tree = load_tree()?;
+            let key = tree.today_node()?;
+            stage_all()?;
+            print_key(&key, json);
+        }
+        Command::Link {
+            text,
+            from,
+            to,
+            from_line,
+            to_line,
+        } => {
+            let mut tree = load_tree()?;
+            for key in [&from, &to] {
+                if !tree.nodes.contains_key(key) {
+                    eprintln!("no node in tree named: {}", key);
+                    return Ok(1);
+                }
+            }
+            let content = tree.nodes[&from].content_path();
+            let (from_line, from_char) = place_link(&content, &text, from_line)?;
+            tree.link(&text, &from, from_line, from_char, &to, to_line, 0);
+            tree.sync_links(&from)?;
+            stage_all()?;
+            if json {
+                println!("{}", json!({ "text": text, "from": from, "to": to }));
+            } else {
+                println!("[[{}]] {} -> {}", text, from, to);
+            }
+        }
+        Command::Search { query } => {
+            let tree = load_tree()?;
+            let hits = tree.search(&query);
+            if json {
+                let hits: Vec<Value> = hits
+                    .iter()
+                    .map(|hit| {
+                        json!({
+                            "id": hit.node,
+                            "display": format_display_name(&hit.node),
+                            "line": hit.line,
+                            "snippet": hit.snippet,
+                            "score": hit.score,
+                        })
+                    })
+                    .collect();
+                println!("{}", Value::Array(hits));
+            } else {
+                for hit in hits {
+                    println!("{}/_.md:{}: {}", hit.node, hit.line, hit.snippet);
+                }
+            }
+        }
+        Command::Sync => {
+            let git = Config::from_dir(&env::current_dir()?)?.git;
+            let pulled = pull_branch(&git).is_ok();
+            commit_all(Some("codex sync"))?;
+            let pushed = push_all(&git).is_ok();
+            if json {
+                println!("{}", json!({ "pulled": pulled, "pushed": pushed }));
+            } else {
+                println!("pulled: {} pushed: {}", pulled, pushed);
+            }
+            if !(pulled && pushed) {
+                return Ok(1);
+            }
+        }
+        Command::Stats => {
+            let tree = load_tree()?;
+            let stats = stats(&tree);
+            if json {
+                println!("{}", stats);
+            } else if let Value::Object(stats) = stats {
+                for (name, value) in stats {
+                    println!("{}: {}", name, value);
+                }
+            }
+        }
+        Command::Fsck { repair } => {
+            let pwd = env::current_dir()?;
+            let repaired = if repair {
+                fsck::repair(&pwd)?.problems
+            } else {
+                vec![]
+            };
+            let report = fsck::check(&pwd);
+            if json {
+                let strings = |problems: &[fsck::Problem]| {
+                    problems
+                        .iter()
+                        .map(|p| p.to_string())
+                        .collect::<Vec<String>>()
+                };
+                println!(
+                    "{}",
+                    json!({
+                        "repaired": strings(&repaired),
+                        "problems": strings(&report.problems),
+                    })
+                );
+            } else {
+                for problem in repaired {
+                    println!("repaired: {}", problem);
+                }
+                print!("{}", report);
+            }
+            if !report.is_clean() {
+                return Ok(1);
+            }
+        }
+        Command::Graph { format, options } => {
+            let tree = load_tree()?;
+            print!("{}", render(&tree.graph(&options)?, format));
+        }
+        Command::Site { out, options } => {
+            let tree = load_tree()?;
+            let pages = export_site(&tree, &options, &out)?;
+            if json {
+                println!("{}", json!({ "out": out, "pages": pages }));
+            } else {
+                println!("{} pages written to {}", pages, out.display());
+            }
+        }
+        Command::Vault { out, options } => {
+            let tree = load_tree()?;
+            let notes = export_vault(&tree, &options, &out)?;
+            if json {
+                println!("{}", json!({ "out": out, "notes": notes }));
+            } else {
+                println!("{} notes written to {}", notes, out.display());
+            }
+        }
+        Command::Import { vault, parent } => {
+            let mut tree = load_tree()?;
+            let parent = parent.unwrap_or_else(|| tree.desk.clone());
+            let stats = import_vault(&mut tree, &vault, &parent)?;
+            stage_all()?;
+            if json {
+                println!(
+                    "{}",
+                    json!({
+                        "id": stats.root,
+                        "notes": stats.notes,
+                        "folders": stats.folders,
+                        "links": stats.links,
+                        "attachments": stats.attachments,
+                        "unresolved": stats.unresolved,
+                    })
+                );
+            } else {
+                println!(
+                    "{}: {} notes, {} folders, {} links ({} unresolved), {} attachments",
+                    stats.root,
+                    stats.notes,
+                    stats.folders,
+                    stats.links,
+                    stats.unresolved,
+                    stats.attachments
+                );
+            }
+        }
+    }
+    Ok(0)
+}
+
+/// Run a command against the codex in `--dir`, `CODEX_RUNTIME_DIR`
+/// or the current directory, returning the process exit code
+pub fn run(cli: Cli) -> i32 {
+    let dir = cli
+        .dir
+        .or_else(|| env::var("CODEX_RUNTIME_DIR").ok().map(PathBuf::from));
+    if let Some(dir) = dir {
+        if let Err(e) = env::set_current_dir(&dir) {
+            eprintln!("unable to enter {:?}: {}", dir, e);
+            return 2;
+        }
+    }
+    match execute(cli.command, cli.json) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("codex: {}", e);
+            2
+        }
+    }
+}
+
+#[test]
+fn test_parse() {
+    let args = |s: &str| {
+        s.split_whitespace()
+            .map(String::from)
+            .collect::<Vec<String>>()
+    };
+    assert_eq!(parse(args("")), Ok(None));
+    assert_eq!(
+        parse(args("new 2-desk jazz --json")),
+        Ok(Some(Cli {
+            command: Command::New {
+                parent: Some("2-desk".to_string()),
+                name: "jazz".to_string()
+            },
+            json: true,
+            dir: None,
+        }))
+    );
+    assert_eq!(
+        parse(args("--dir /tmp/codex search giant steps"))
+            .unwrap()
+            .unwrap(),
+        Cli {
+            command: Command::Search {
+                query: "giant steps".to_string()
+            },
+            json: false,
+            dir: Some(PathBuf::from("/tmp/codex")),
+        }
+    );
+    assert_eq!(
+        parse(args("link a 2-desk/1-a 2-desk/2-b 4"))
+            .unwrap()
+            .unwrap()
+            .command,
+        Command::Link {
+            text: "a".to_string(),
+            from: "2-desk/1-a".to_string(),
+            to: "2-desk/2-b".to_string(),
+            from_line: 4,
+            to_line: 0,
+        }
+    );
+    assert_eq!(
+        parse(args("fsck --repair")).unwrap().unwrap().command,
+        Command::Fsck { repair: true }
+    );
+    assert_eq!(
+        parse(args(
+            "graph --format graphml --edges links --tag project/codex"
+        ))
+        .unwrap()
+        .unwrap()
+        .command,
+        Command::Graph {
+            format: GraphFormat::GraphMl,
+            options: GraphOptions {
+                edges: Edges::Links,
+                root: None,
+                tag: Some("project/codex".to_string()),
+            },
+        }
+    );
+    assert_eq!(
+        parse(args(
+            "site /tmp/site --root 2-desk/1-a --tag public --root 2-desk/3-b"
+        ))
+        .unwrap()
+        .unwrap()
+        .command,
+        Command::Site {
+            out: PathBuf::from("/tmp/site"),
+            options: SiteOptions {
+                roots: vec!["2-desk/1-a".to_string(), "2-desk/3-b".to_string()],
+                tags: vec!["public".to_string()],
+            },
+        }
+    );
+    assert_eq!(
+        parse(args("import ~/vault 2-desk/1-a"))
+            .unwrap()
+            .unwrap()
+            .command,
+        Command::Import {
+            vault: PathBuf::from("~/vault"),
+            parent: Some("2-desk/1-a".to_string()),
+        }
+    );
+    assert!(parse(args("import")).is_err());
+    assert_eq!(
+        parse(args("vault /tmp/vault --flat --root 2-desk"))
+            .unwrap()
+            .unwrap()
+            .command,
+        Command::Vault {
+            out: PathBuf::from("/tmp/vault"),
+            options: VaultOptions {
+                layout: Layout::Flat,
+                root: Some("2-desk".to_string()),
+            },
+        }
+    );
+    assert!(parse(args("graph --root 2-desk --root 1-journal")).is_err());
+    assert!(parse(args("graph --format svg")).is_err());
+    assert!(parse(args("graph --root")).is_err());
+    assert!(parse(args("link a b c x")).is_err());
+    assert!(parse(args("today now")).is_err());
+    assert_eq!(
+        parse(args("today --repair")),
+        Err("today does not take --repair".to_string())
+    );
+    assert!(parse(args("fsck --flat")).is_err());
+    assert!(parse(args("site /tmp/site --format json")).is_err());
+    assert!(parse(args("vault /tmp/vault --tag public")).is_err());
+    assert_eq!(
+        parse(args("fsck --repair --help"))
+            .unwrap()
+            .unwrap()
+            .command,
+        Command::Help
+    );
+}
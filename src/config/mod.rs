@@ -0,0 +1,524 @@
+use crate::node::markdown::normalize_tag;
+use chrono::format::{Item, StrftimeItems};
+use chrono::NaiveDate;
+use log::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs::read_to_string;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Name of the config file at the root of a codex
+pub const CONFIG_FILE: &str = "codex.toml";
+
+pub struct ConfigError {
+    err_text: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.err_text)
+    }
+}
+
+impl fmt::Debug for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ConfigError( {} )", self.err_text)
+    }
+}
+
+impl error::Error for ConfigError {}
+
+/// `[journal]` in `codex.toml`, every value is a strftime string.
+///
+/// ```toml
+/// [journal]
+/// format = "%a %b %d %Y"
+/// year = "%Y"
+/// month = "%m %B"
+/// week = "Week %V"
+/// ```
+///
+/// `year`, `month` and `week` are optional levels between the journal
+/// and the day nodes, in that order. Without them days are flat children
+/// of the journal.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JournalConfig {
+    pub format: String,
+    pub year: Option<String>,
+    pub month: Option<String>,
+    pub week: Option<String>,
+}
+
+impl Default for JournalConfig {
+    fn default() -> Self {
+        JournalConfig {
+            format: "%a %b %d %Y".to_string(),
+            year: None,
+            month: None,
+            week: None,
+        }
+    }
+}
+
+impl JournalConfig {
+    /// strftime strings of the levels from the journal down to the day
+    pub fn formats(&self) -> Vec<&str> {
+        [self.year.as_ref(), self.month.as_ref(), self.week.as_ref()]
+            .iter()
+            .flatten()
+            .copied()
+            .chain(Some(&self.format))
+            .map(String::as_str)
+            .collect()
+    }
+    /// Node names from the journal down to the day node for `date`
+    pub fn path_for(&self, date: NaiveDate) -> Vec<String> {
+        self.formats()
+            .into_iter()
+            .map(|format| date.format(format).to_string())
+            .collect()
+    }
+    fn validate(&self) -> Result<(), ConfigError> {
+        let formats = [
+            ("format", Some(&self.format)),
+            ("year", self.year.as_ref()),
+            ("month", self.month.as_ref()),
+            ("week", self.week.as_ref()),
+        ];
+        for (key, format) in formats.iter() {
+            let format = match format {
+                Some(format) => format,
+                None => continue,
+            };
+            let invalid = format.trim().is_empty()
+                || format.contains('/')
+                || StrftimeItems::new(format).any(|item| item == Item::Error);
+            if invalid {
+                return Err(ConfigError {
+                    err_text: format!(
+                        "journal.{} is not a usable strftime string: {:?}",
+                        key, format
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `[templates]` in `codex.toml`, which template from `templates/` new
+/// nodes start from when their parent doesn't name one in its `meta.toml`.
+///
+/// ```toml
+/// [templates]
+/// default = "note"
+///
+/// [templates.tags]
+/// articles = "article"
+/// ```
+///
+/// Children of a node tagged `#articles`, or a tag below it, start from
+/// `templates/article.md`. Nodes with neither get `default`, if any.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TemplatesConfig {
+    pub default: Option<String>,
+    pub tags: BTreeMap<String, String>,
+}
+
+/// A template name is the file name of a template without `.md`
+pub fn valid_template_name(name: &str) -> bool {
+    !name.trim().is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
+
+impl TemplatesConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        let names = self.default.iter().chain(self.tags.values());
+        if let Some(name) = names.into_iter().find(|name| !valid_template_name(name)) {
+            return Err(ConfigError {
+                err_text: format!("templates: {:?} is not a template name", name),
+            });
+        }
+        if let Some(tag) = self.tags.keys().find(|tag| normalize_tag(tag).is_none()) {
+            return Err(ConfigError {
+                err_text: format!("templates.tags: {:?} is not a tag", tag),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// `[git]` in `codex.toml`, where the codex is synced to.
+///
+/// ```toml
+/// [git]
+/// remote = "git@githost.net:user/codex.git"
+/// remote_name = "origin"
+/// branch = "main"
+/// ```
+///
+/// `remote` is cloned when the codex directory isn't a repository yet,
+/// `branch` is the branch day branches are merged into and pulled from.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitConfig {
+    pub remote: Option<String>,
+    pub remote_name: String,
+    pub branch: String,
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        GitConfig {
+            remote: None,
+            remote_name: "origin".to_string(),
+            branch: "main".to_string(),
+        }
+    }
+}
+
+impl GitConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if !git2::Reference::is_valid_name(&format!("refs/heads/{}", self.branch)) {
+            return Err(ConfigError {
+                err_text: format!("git.branch is not a branch name: {:?}", self.branch),
+            });
+        }
+        if !git2::Remote::is_valid_name(&self.remote_name) {
+            return Err(ConfigError {
+                err_text: format!(
+                    "git.remote_name is not a remote name: {:?}",
+                    self.remote_name
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// `[credentials]` in `codex.toml`, how to authenticate with the remote.
+///
+/// ```toml
+/// [credentials]
+/// ssh_key = "~/.ssh/id_ed25519"
+/// ssh_agent = false
+/// ```
+///
+/// With `ssh_agent` the key is asked of the running ssh agent and
+/// `ssh_key` is ignored. `username` defaults to the one in the remote url.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CredentialsConfig {
+    pub ssh_key: String,
+    pub ssh_agent: bool,
+    pub username: Option<String>,
+}
+
+impl Default for CredentialsConfig {
+    fn default() -> Self {
+        CredentialsConfig {
+            ssh_key: "~/.ssh/id_ghub".to_string(),
+            ssh_agent: false,
+            username: None,
+        }
+    }
+}
+
+impl CredentialsConfig {
+    /// `ssh_key` with a leading `~` made the home directory
+    pub fn ssh_key_path(&self) -> PathBuf {
+        expand_home(&self.ssh_key)
+    }
+    fn validate(&self) -> Result<(), ConfigError> {
+        if !self.ssh_agent && self.ssh_key.trim().is_empty() {
+            return Err(ConfigError {
+                err_text: "credentials.ssh_key is empty, set it or use ssh_agent".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// `[sync]` in `codex.toml`, when the codex is pulled and pushed.
+///
+/// ```toml
+/// [sync]
+/// pull_on_start = true
+/// push_on_stop = true
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyncConfig {
+    pub pull_on_start: bool,
+    pub push_on_stop: bool,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        SyncConfig {
+            pull_on_start: true,
+            push_on_stop: true,
+        }
+    }
+}
+
+/// `[autosave]` in `codex.toml`, how often changes are committed while
+/// Neovim is open.
+///
+/// ```toml
+/// [autosave]
+/// commit_minutes = 15
+/// message = "codex autosave"
+/// ```
+///
+/// `commit_minutes = 0`, the default, leaves commits to `sync` and stop.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutosaveConfig {
+    pub commit_minutes: u64,
+    pub message: String,
+}
+
+impl Default for AutosaveConfig {
+    fn default() -> Self {
+        AutosaveConfig {
+            commit_minutes: 0,
+            message: "codex autosave".to_string(),
+        }
+    }
+}
+
+/// Longest autosave interval, a year
+const MAX_AUTOSAVE_MINUTES: u64 = 60 * 24 * 365;
+
+impl AutosaveConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.commit_minutes > MAX_AUTOSAVE_MINUTES {
+            return Err(ConfigError {
+                err_text: format!(
+                    "autosave.commit_minutes is over a year: {}",
+                    self.commit_minutes
+                ),
+            });
+        }
+        if self.message.trim().is_empty() {
+            return Err(ConfigError {
+                err_text: "autosave.message is empty".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// `[log]` in `codex.toml`, where the backend logs to and how much.
+///
+/// ```toml
+/// [log]
+/// level = "info"
+/// file = "~/.local/state/codex/codex.log"
+/// ```
+///
+/// Without a `file` logging is set up by `codex-log.toml` in `CODEX_HOME`,
+/// `level` then only lowers what it logs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub level: Option<String>,
+    pub file: Option<String>,
+}
+
+impl LogConfig {
+    pub fn level_filter(&self) -> Option<LevelFilter> {
+        self.level
+            .as_ref()
+            .and_then(|level| LevelFilter::from_str(level).ok())
+    }
+    pub fn file_path(&self) -> Option<PathBuf> {
+        self.file.as_deref().map(expand_home)
+    }
+    fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(level) = &self.level {
+            if LevelFilter::from_str(level).is_err() {
+                return Err(ConfigError {
+                    err_text: format!(
+                        "log.level should be one of off, error, warn, info, debug or trace: {:?}",
+                        level
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A path with a leading `~` made the home directory
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// `codex/codex.toml` in the user's config directory, the settings every
+/// codex starts from
+pub fn user_config_path() -> Option<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("codex").join(CONFIG_FILE))
+}
+
+/// Lay the tables of `over` on those of `base`, values in `over` win
+fn merge(base: &mut toml::Value, over: toml::Value) {
+    match (base, over) {
+        (toml::Value::Table(base), toml::Value::Table(over)) => {
+            for (key, value) in over {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
+/// Settings read from `codex.toml` at the root of a codex
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub journal: JournalConfig,
+    pub templates: TemplatesConfig,
+    pub git: GitConfig,
+    pub credentials: CredentialsConfig,
+    pub sync: SyncConfig,
+    pub autosave: AutosaveConfig,
+    pub log: LogConfig,
+}
+
+impl Config {
+    /// A single `codex.toml` without the user's settings
+    #[cfg(test)]
+    fn parse(toml_string: &str) -> Result<Config, ConfigError> {
+        Config::from_value(Config::parse_value(toml_string, Path::new(CONFIG_FILE))?)
+    }
+    fn parse_value(toml_string: &str, path: &Path) -> Result<toml::Value, ConfigError> {
+        toml::from_str(toml_string).map_err(|e| ConfigError {
+            err_text: format!("{} is invalid: {}", path.display(), e),
+        })
+    }
+    fn from_value(value: toml::Value) -> Result<Config, ConfigError> {
+        let config: Config = value.try_into().map_err(|e| ConfigError {
+            err_text: format!("{} is invalid: {}", CONFIG_FILE, e),
+        })?;
+        config.journal.validate()?;
+        config.templates.validate()?;
+        config.git.validate()?;
+        config.credentials.validate()?;
+        config.autosave.validate()?;
+        config.log.validate()?;
+        Ok(config)
+    }
+    /// Read config files in order, each one overriding the keys of those
+    /// before it. Missing files are skipped, defaults when there are none.
+    pub fn from_files(paths: &[PathBuf]) -> Result<Config, ConfigError> {
+        let mut merged = toml::Value::Table(toml::map::Map::new());
+        for path in paths {
+            match read_to_string(path) {
+                Ok(toml_string) => merge(&mut merged, Config::parse_value(&toml_string, path)?),
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    debug!("no {:?}, skipping it", path);
+                }
+                Err(e) => {
+                    return Err(ConfigError {
+                        err_text: format!("unable to read {:?}: {}", path, e),
+                    })
+                }
+            }
+        }
+        Config::from_value(merged)
+    }
+    /// Read the user's `codex.toml`, see [`user_config_path`], overridden
+    /// by the `codex.toml` of a codex directory
+    pub fn from_dir(dir: &Path) -> Result<Config, ConfigError> {
+        let paths: Vec<PathBuf> = user_config_path()
+            .into_iter()
+            .chain(Some(dir.join(CONFIG_FILE)))
+            .collect();
+        Config::from_files(&paths)
+    }
+}
+
+#[test]
+fn test_journal_config() {
+    let config = Config::parse("[journal]\nyear = \"%Y\"\nweek = \"Week %V\"\n").unwrap();
+    let date = NaiveDate::from_ymd_opt(2023, 1, 2).unwrap();
+    assert_eq!(
+        config.journal.path_for(date),
+        vec!["2023", "Week 01", "Mon Jan 02 2023"]
+    );
+    assert_eq!(
+        Config::default().journal.path_for(date),
+        vec!["Mon Jan 02 2023"]
+    );
+    assert!(Config::parse("[journal]\nformat = \"%Q\"\n").is_err());
+    assert!(Config::parse("[journal]\nmonth = \"%Y/%m\"\n").is_err());
+    assert!(Config::parse("[journal]\nfromat = \"%Y\"\n").is_err());
+}
+
+#[test]
+fn test_templates_config() {
+    let toml =
+        "[templates]\ndefault = \"note\"\n[templates.tags]\n\"project/codex\" = \"project\"\n";
+    let config = Config::parse(toml).unwrap();
+    assert_eq!(config.templates.default, Some("note".to_string()));
+    assert_eq!(config.templates.tags["project/codex"], "project");
+    assert!(Config::parse("[templates]\ndefault = \"../note\"\n").is_err());
+    assert!(Config::parse("[templates.tags]\n\"a b\" = \"note\"\n").is_err());
+}
+
+#[test]
+fn test_layered_config() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let (user, codex) = (dir.path().join("user.toml"), dir.path().join("codex.toml"));
+    std::fs::write(
+        &user,
+        "[git]\nremote = \"git@host:me/codex.git\"\nbranch = \"trunk\"\n[log]\nlevel = \"warn\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        &codex,
+        "[git]\nbranch = \"notes\"\n[autosave]\ncommit_minutes = 5\n",
+    )
+    .unwrap();
+    let config = Config::from_files(&[user.clone(), codex.clone(), dir.path().join("no")]).unwrap();
+    assert_eq!(config.git.remote.as_deref(), Some("git@host:me/codex.git"));
+    assert_eq!(config.git.branch, "notes");
+    assert_eq!(config.git.remote_name, "origin");
+    assert_eq!(config.autosave.commit_minutes, 5);
+    assert_eq!(config.log.level_filter(), Some(LevelFilter::Warn));
+    assert!(config.sync.pull_on_start && config.sync.push_on_stop);
+    assert_eq!(Config::from_files(&[]).unwrap(), Config::default());
+
+    std::fs::write(&codex, "[git]\nbranch = \"a..b\"\n").unwrap();
+    let err = Config::from_files(&[user.clone(), codex.clone()]).unwrap_err();
+    assert!(err.to_string().starts_with("git.branch"));
+    std::fs::write(&codex, "[git\n").unwrap();
+    let err = Config::from_files(&[user, codex.clone()]).unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with(&format!("{} is invalid", codex.display())));
+    assert!(Config::parse("[log]\nlevel = \"loud\"\n").is_err());
+    assert!(Config::parse("[sync]\npull = true\n").is_err());
+    assert!(Config::parse("[credentials]\nssh_key = \"\"\n").is_err());
+    assert!(Config::parse("[credentials]\nssh_key = \"\"\nssh_agent = true\n").is_ok());
+}
@@ -0,0 +1,165 @@
+use crate::config::ConfigError;
+use crate::node::NodeKey;
+use nvim_rs::error::CallError;
+use rmpv::Value;
+use std::error;
+use std::fmt;
+use std::io;
+use std::process::ExitStatusError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A node directory without its `_.md` and/or `meta.toml`
+#[derive(Clone, PartialEq, Eq)]
+pub struct NodeFilesMissing {
+    pub content_file_exists: bool,
+    pub metadata_file_exists: bool,
+    pub node: NodeKey,
+}
+
+impl fmt::Display for NodeFilesMissing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let missing = match (self.content_file_exists, self.metadata_file_exists) {
+            (false, true) => "Missing `_.md` for",
+            (true, false) => "Missing `meta.toml` for",
+            _ => "Missing `_.md` and `meta.toml` for",
+        };
+        write!(f, "{} {}", missing, self.node)
+    }
+}
+
+impl fmt::Debug for NodeFilesMissing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NodeFilesMissing( {} )", self)
+    }
+}
+
+/// Everything that can go wrong within codex
+pub enum Error {
+    /// A tree operation that is not allowed, like moving a root node
+    Tree(String),
+    NodeNotFound(NodeKey),
+    /// A name that could refer to any of several nodes
+    Ambiguous(String, Vec<NodeKey>),
+    NodeFilesMissing(NodeFilesMissing),
+    /// A node on disk that can't be read, like a malformed `meta.toml`
+    Node(String),
+    /// Missing or mistyped arguments to an RPC or command
+    Args(String),
+    Git(String),
+    /// A call back into neovim failed
+    Neovim(String),
+    Io(io::Error),
+    Config(ConfigError),
+}
+
+impl Error {
+    /// Machine readable kind of error, sent to neovim with the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Tree(_) => "tree",
+            Error::NodeNotFound(_) => "node-not-found",
+            Error::Ambiguous(..) => "ambiguous",
+            Error::NodeFilesMissing(_) => "node-files-missing",
+            Error::Node(_) => "node",
+            Error::Args(_) => "invalid-args",
+            Error::Git(_) => "git",
+            Error::Neovim(_) => "neovim",
+            Error::Io(_) => "io",
+            Error::Config(_) => "config",
+        }
+    }
+    /// RPC error reply, `"<code>: <message>"`.
+    ///
+    /// Neovim only passes string errors on to the caller of `rpcrequest`,
+    /// anything else is reported as "unknown error".
+    pub fn to_value(&self) -> Value {
+        Value::from(format!("{}: {}", self.code(), self))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Tree(text)
+            | Error::Node(text)
+            | Error::Args(text)
+            | Error::Git(text)
+            | Error::Neovim(text) => write!(f, "{}", text),
+            Error::NodeNotFound(key) => write!(f, "no node in tree named: {}", key),
+            Error::Ambiguous(name, nodes) => {
+                write!(f, "{:?} could be any of: {}", name, nodes.join(", "))
+            }
+            Error::NodeFilesMissing(missing) => write!(f, "{}", missing),
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Config(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error( {}: {} )", self.code(), self)
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Config(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<git2::Error> for Error {
+    fn from(e: git2::Error) -> Self {
+        Error::Git(e.message().to_string())
+    }
+}
+
+impl From<ExitStatusError> for Error {
+    fn from(e: ExitStatusError) -> Self {
+        Error::Git(format!("git {}", e))
+    }
+}
+
+impl From<Box<CallError>> for Error {
+    fn from(e: Box<CallError>) -> Self {
+        Error::Neovim(e.to_string())
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Error::Node(e.to_string())
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(e: ConfigError) -> Self {
+        Error::Config(e)
+    }
+}
+
+#[test]
+fn test_error_value() {
+    let error = Error::NodeNotFound("2-desk/9-nope".to_string());
+    assert_eq!(
+        error.to_value(),
+        Value::from("node-not-found: no node in tree named: 2-desk/9-nope")
+    );
+    let missing = Error::NodeFilesMissing(NodeFilesMissing {
+        content_file_exists: false,
+        metadata_file_exists: true,
+        node: "2-desk/1-a".to_string(),
+    });
+    assert_eq!(missing.to_string(), "Missing `_.md` for 2-desk/1-a");
+}
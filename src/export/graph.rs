@@ -0,0 +1,152 @@
+use crate::tree::graph::{EdgeKind, Graph};
+use serde_json::json;
+
+/// File formats a graph can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz
+    Dot,
+    /// A node-link document as read by d3 or networkx
+    Json,
+    GraphMl,
+}
+
+impl GraphFormat {
+    pub fn parse(format: &str) -> Option<GraphFormat> {
+        match format {
+            "dot" => Some(GraphFormat::Dot),
+            "json" => Some(GraphFormat::Json),
+            "graphml" => Some(GraphFormat::GraphMl),
+            _ => None,
+        }
+    }
+}
+
+pub fn render(graph: &Graph, format: GraphFormat) -> String {
+    match format {
+        GraphFormat::Dot => to_dot(graph),
+        GraphFormat::Json => to_json(graph),
+        GraphFormat::GraphMl => to_graphml(graph),
+    }
+}
+
+fn dot_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Hierarchy edges are drawn solid and link edges dashed
+pub fn to_dot(graph: &Graph) -> String {
+    let mut dot = String::from("digraph codex {\n");
+    for node in &graph.nodes {
+        dot.push_str(&format!(
+            "    {} [label={}];\n",
+            dot_string(&node.id),
+            dot_string(&node.name)
+        ));
+    }
+    for edge in &graph.edges {
+        let style = match edge.kind {
+            EdgeKind::Hierarchy => "solid",
+            EdgeKind::Link => "dashed",
+        };
+        dot.push_str(&format!(
+            "    {} -> {} [kind={}, style={}];\n",
+            dot_string(&edge.from),
+            dot_string(&edge.to),
+            edge.kind.as_str(),
+            style
+        ));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+pub fn to_json(graph: &Graph) -> String {
+    let nodes: Vec<_> = graph
+        .nodes
+        .iter()
+        .map(|node| json!({ "id": node.id, "name": node.name, "tags": node.tags }))
+        .collect();
+    let links: Vec<_> = graph
+        .edges
+        .iter()
+        .map(|edge| json!({ "source": edge.from, "target": edge.to, "kind": edge.kind.as_str() }))
+        .collect();
+    let document = json!({
+        "directed": true,
+        "multigraph": false,
+        "graph": { "name": "codex" },
+        "nodes": nodes,
+        "links": links,
+    });
+    serde_json::to_string_pretty(&document).unwrap()
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+pub fn to_graphml(graph: &Graph) -> String {
+    let mut xml = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+        "  <key id=\"name\" for=\"node\" attr.name=\"name\" attr.type=\"string\"/>\n",
+        "  <key id=\"tags\" for=\"node\" attr.name=\"tags\" attr.type=\"string\"/>\n",
+        "  <key id=\"kind\" for=\"edge\" attr.name=\"kind\" attr.type=\"string\"/>\n",
+        "  <graph id=\"codex\" edgedefault=\"directed\">\n",
+    ));
+    for node in &graph.nodes {
+        xml.push_str(&format!(
+            "    <node id=\"{}\">\n      <data key=\"name\">{}</data>\n      <data key=\"tags\">{}</data>\n    </node>\n",
+            xml_escape(&node.id),
+            xml_escape(&node.name),
+            xml_escape(&node.tags.join(" "))
+        ));
+    }
+    for edge in &graph.edges {
+        xml.push_str(&format!(
+            "    <edge source=\"{}\" target=\"{}\">\n      <data key=\"kind\">{}</data>\n    </edge>\n",
+            xml_escape(&edge.from),
+            xml_escape(&edge.to),
+            edge.kind.as_str()
+        ));
+    }
+    xml.push_str("  </graph>\n</graphml>\n");
+    xml
+}
+
+#[test]
+fn test_render_graph() {
+    use crate::tree::graph::{GraphEdge, GraphNode};
+    let node = |id: &str, name: &str| GraphNode {
+        id: id.to_string(),
+        name: name.to_string(),
+        tags: vec![],
+    };
+    let graph = Graph {
+        nodes: vec![
+            node("2-desk", "desk"),
+            node("2-desk/1-a", "say \"a\" & <b>"),
+        ],
+        edges: vec![GraphEdge {
+            from: "2-desk".to_string(),
+            to: "2-desk/1-a".to_string(),
+            kind: EdgeKind::Hierarchy,
+        }],
+    };
+    let dot = to_dot(&graph);
+    assert!(dot.contains("    \"2-desk/1-a\" [label=\"say \\\"a\\\" & <b>\"];\n"));
+    assert!(dot.contains("    \"2-desk\" -> \"2-desk/1-a\" [kind=hierarchy, style=solid];\n"));
+    let xml = to_graphml(&graph);
+    assert!(xml.contains("<data key=\"name\">say &quot;a&quot; &amp; &lt;b&gt;</data>"));
+    assert!(xml.contains("<edge source=\"2-desk\" target=\"2-desk/1-a\">"));
+    let json: serde_json::Value = serde_json::from_str(&to_json(&graph)).unwrap();
+    assert_eq!(json["links"][0]["source"], "2-desk");
+    assert_eq!(json["nodes"][1]["name"], "say \"a\" & <b>");
+    assert_eq!(GraphFormat::parse("graphml"), Some(GraphFormat::GraphMl));
+    assert_eq!(GraphFormat::parse("svg"), None);
+}
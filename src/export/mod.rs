@@ -0,0 +1,4 @@
+//! Writing a codex out in formats other tools read
+pub mod graph;
+pub mod obsidian;
+pub mod site;
@@ -0,0 +1,274 @@
+use crate::error::Result;
+use crate::node::markdown::{extract_links, split_link_text, LinkAnchor};
+use crate::node::{Node, NodeKey, NodeMeta};
+use crate::tree::{Error, Tree};
+use log::*;
+use serde_yaml::{Mapping, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{copy, create_dir_all, read_dir, read_to_string, write};
+use std::path::{Path, PathBuf};
+
+/// How nodes are laid out in the vault
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// A folder per node with children, holding its folder note
+    /// `Name/Name.md` and the notes of its children
+    Nested,
+    /// Every note in the top folder, each with a `parent` property
+    Flat,
+}
+
+impl Layout {
+    pub fn parse(layout: &str) -> Option<Layout> {
+        match layout {
+            "nested" => Some(Layout::Nested),
+            "flat" => Some(Layout::Flat),
+            _ => None,
+        }
+    }
+}
+
+/// Which nodes go into the vault and how
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VaultOptions {
+    pub layout: Layout,
+    /// Only this node and the nodes below it, the whole codex when None
+    pub root: Option<NodeKey>,
+}
+
+impl Default for VaultOptions {
+    fn default() -> Self {
+        VaultOptions {
+            layout: Layout::Nested,
+            root: None,
+        }
+    }
+}
+
+/// Characters Obsidian refuses in file names or that break `[[...]]`
+const UNSAFE: [char; 13] = [
+    '*', '"', '\\', '/', '<', '>', ':', '|', '?', '#', '^', '[', ']',
+];
+
+/// A node name made safe as a note file name
+fn note_name(name: &str) -> String {
+    let name: String = name.chars().filter(|c| !UNSAFE.contains(c)).collect();
+    match name.trim().trim_start_matches('.') {
+        "" => "untitled".to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// Obsidian wikilink for codex link `text` to the note `name`, keeping
+/// the text as the display text when it reads differently
+fn wikilink(text: &str, name: &str) -> String {
+    let (note, anchor) = split_link_text(text);
+    let anchor = match anchor {
+        Some(LinkAnchor::Heading(heading)) => format!("#{}", heading),
+        Some(LinkAnchor::Block(id)) => format!("#^{}", id),
+        None => String::new(),
+    };
+    match note {
+        // `[[#Section]]` stays within the note
+        "" => format!("[[{}]]", anchor),
+        note if note == name => format!("[[{}{}]]", name, anchor),
+        note => format!("[[{}{}|{}]]", name, anchor, note),
+    }
+}
+
+/// YAML frontmatter of a note from the node's metadata
+fn frontmatter(meta: &NodeMeta, parent: Option<&str>) -> String {
+    let strings = |values: &[String]| {
+        Value::Sequence(
+            values
+                .iter()
+                .map(|value| Value::from(value.as_str()))
+                .collect(),
+        )
+    };
+    let date = |date: &chrono::DateTime<chrono::Local>| {
+        Value::from(date.format("%Y-%m-%dT%H:%M:%S").to_string())
+    };
+    let mut properties = Mapping::new();
+    if !meta.tags.is_empty() {
+        properties.insert(Value::from("tags"), strings(&meta.tags));
+    }
+    if !meta.aliases.is_empty() {
+        properties.insert(Value::from("aliases"), strings(&meta.aliases));
+    }
+    if let Some(parent) = parent {
+        properties.insert(
+            Value::from("parent"),
+            Value::from(format!("[[{}]]", parent)),
+        );
+    }
+    properties.insert(Value::from("created"), date(&meta.created));
+    properties.insert(Value::from("updated"), date(&meta.updated));
+    properties.insert(Value::from("updates"), Value::from(meta.updates));
+    format!("---\n{}---\n", serde_yaml::to_string(&properties).unwrap())
+}
+
+struct Vault<'a> {
+    tree: &'a Tree,
+    options: &'a VaultOptions,
+    /// Exported nodes and their note names, unique within the vault
+    names: BTreeMap<&'a NodeKey, String>,
+}
+
+impl<'a> Vault<'a> {
+    fn new(tree: &'a Tree, options: &'a VaultOptions) -> Result<Vault<'a>> {
+        let within = |key: &str| match &options.root {
+            Some(root) => key == root || key.starts_with(&format!("{}/", root)),
+            None => true,
+        };
+        if let Some(root) = &options.root {
+            if !tree.nodes.contains_key(root) {
+                return Err(Error::NodeNotFound(root.clone()));
+            }
+        }
+        // Obsidian finds notes by name whatever the folder or case, so
+        // a name taken earlier in the tree gets a number
+        let mut taken = HashSet::new();
+        let mut names = BTreeMap::new();
+        for node in tree.nodes.values().filter(|node| within(&node.id)) {
+            let name = note_name(&node.name);
+            let mut unique = name.clone();
+            let mut count = 1;
+            while !taken.insert(unique.to_lowercase()) {
+                count += 1;
+                unique = format!("{} {}", name, count);
+            }
+            names.insert(&node.id, unique);
+        }
+        Ok(Vault {
+            tree,
+            options,
+            names,
+        })
+    }
+    fn exported_parent(&self, node: &Node) -> Option<&'a NodeKey> {
+        let parent = node.parent.as_ref()?;
+        self.names.get_key_value(parent).map(|(key, _)| *key)
+    }
+    fn has_children(&self, node: &Node) -> bool {
+        node.children
+            .iter()
+            .any(|child| self.names.contains_key(child))
+    }
+    /// Folder, relative to the vault, holding the notes of `node`'s children
+    fn folder(&self, node: &Node) -> PathBuf {
+        let mut folder = match self.exported_parent(node) {
+            Some(parent) => self.folder(&self.tree.nodes[parent]),
+            None => PathBuf::new(),
+        };
+        folder.push(&self.names[&node.id]);
+        folder
+    }
+    /// Note of a node relative to the vault
+    fn path(&self, node: &Node) -> PathBuf {
+        let name = format!("{}.md", self.names[&node.id]);
+        match self.options.layout {
+            Layout::Flat => PathBuf::from(name),
+            Layout::Nested if self.has_children(node) => self.folder(node).join(name),
+            Layout::Nested => match self.exported_parent(node) {
+                Some(parent) => self.folder(&self.tree.nodes[parent]).join(name),
+                None => PathBuf::from(name),
+            },
+        }
+    }
+    /// `[[...]]` links of a body pointing at the note names of their
+    /// targets, links to nodes left out of the vault are kept as written
+    fn rewrite_links(&self, node: &Node, body: &str) -> String {
+        let mut lines: Vec<String> = body.split_inclusive('\n').map(String::from).collect();
+        let mut refs = extract_links(body);
+        refs.reverse();
+        for found in refs {
+            let name = match node.links.get(&found.text) {
+                Some(link) => match self.names.get(&link.node) {
+                    Some(name) => name,
+                    None => continue,
+                },
+                None => continue,
+            };
+            let line = &mut lines[found.line as usize - 1];
+            let start = found.char as usize;
+            if let Some(end) = line[start..].find("]]") {
+                line.replace_range(start..start + end + 2, &wikilink(&found.text, name));
+            }
+        }
+        lines.concat()
+    }
+    fn note(&self, node: &Node) -> Result<String> {
+        let parent = match self.options.layout {
+            Layout::Flat => self
+                .exported_parent(node)
+                .map(|parent| self.names[parent].as_str()),
+            Layout::Nested => None,
+        };
+        let body = read_to_string(node.content_path())?;
+        Ok(format!(
+            "{}{}",
+            frontmatter(&NodeMeta::from(node), parent),
+            self.rewrite_links(node, &body)
+        ))
+    }
+}
+
+/// Files kept next to a node's `_.md` copied next to its note, a file
+/// of the same name already there is kept
+fn copy_attachments(from: &Path, to: &Path) -> Result<()> {
+    for entry in read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if !entry.file_type()?.is_file() || name == "_.md" || name == "meta.toml" {
+            continue;
+        }
+        if to.join(&name).exists() {
+            warn!(
+                "{:?} is already in {:?}, skipping {:?}",
+                name,
+                to,
+                entry.path()
+            );
+            continue;
+        }
+        copy(entry.path(), to.join(name))?;
+    }
+    Ok(())
+}
+
+/// Write nodes out as a folder of `Name.md` notes Obsidian, or any other
+/// Markdown editor, can open. Notes carry their `meta.toml` as YAML
+/// frontmatter and links use note names instead of node keys.
+/// Returns how many notes were written.
+pub fn export_vault(tree: &Tree, options: &VaultOptions, out: &Path) -> Result<usize> {
+    let vault = Vault::new(tree, options)?;
+    for key in vault.names.keys() {
+        let node = &tree.nodes[*key];
+        let path = out.join(vault.path(node));
+        let dir = path.parent().unwrap();
+        create_dir_all(dir)?;
+        write(&path, vault.note(node)?)?;
+        copy_attachments(&tree.dir.join(key), dir)?;
+    }
+    debug!("exported {} notes to {:?}", vault.names.len(), out);
+    Ok(vault.names.len())
+}
+
+#[test]
+fn test_obsidian_names_and_links() {
+    assert_eq!(note_name("a/b: c?"), "ab c");
+    assert_eq!(note_name(".hidden"), "hidden");
+    assert_eq!(note_name("#"), "untitled");
+    assert_eq!(wikilink("Giant Steps", "Giant Steps"), "[[Giant Steps]]");
+    assert_eq!(
+        wikilink("giant steps#Bridge", "Giant Steps"),
+        "[[Giant Steps#Bridge|giant steps]]"
+    );
+    assert_eq!(
+        wikilink("GS^abc123", "Giant Steps"),
+        "[[Giant Steps#^abc123|GS]]"
+    );
+    assert_eq!(wikilink("#Intro", "Giant Steps"), "[[#Intro]]");
+    assert_eq!(wikilink("^abc123", "Giant Steps"), "[[#^abc123]]");
+}
@@ -0,0 +1,354 @@
+use crate::error::Result;
+use crate::node::markdown::extract_links;
+use crate::node::{Node, NodeKey};
+use crate::tree::{get_parent, Error, Tree};
+use log::*;
+use pulldown_cmark::{html, Options, Parser};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{copy, create_dir_all, read_dir, read_to_string, write};
+use std::path::{Path, PathBuf};
+
+const STYLE: &str =
+    "body{max-width:46em;margin:2em auto;padding:0 1em;font-family:sans-serif;line-height:1.5}\
+nav,footer{font-size:.9em;color:#555}a.missing{color:#999}ul.inline{list-style:none;padding:0}\
+ul.inline li{display:inline;margin-right:1em}";
+
+/// Which part of a codex gets published
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SiteOptions {
+    /// Nodes published along with everything below them
+    pub roots: Vec<NodeKey>,
+    /// Nodes published for carrying one of these tags, or a tag below one
+    pub tags: Vec<String>,
+}
+
+impl SiteOptions {
+    /// Keys of the nodes to publish, the whole codex when no roots or
+    /// tags are given
+    fn public<'a>(&self, tree: &'a Tree) -> Result<BTreeSet<&'a NodeKey>> {
+        if self.roots.is_empty() && self.tags.is_empty() {
+            return Ok(tree.nodes.keys().collect());
+        }
+        let mut public = BTreeSet::new();
+        for root in &self.roots {
+            if !tree.nodes.contains_key(root) {
+                return Err(Error::NodeNotFound(root.clone()));
+            }
+            let prefix = format!("{}/", root);
+            public.extend(
+                tree.nodes
+                    .keys()
+                    .filter(|key| *key == root || key.starts_with(&prefix)),
+            );
+        }
+        for tag in &self.tags {
+            public.extend(tree.nodes_by_tag(tag)?.into_iter().map(|node| &node.id));
+        }
+        Ok(public)
+    }
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A node key as a URL path, each level percent-encoded so names such
+/// as `C#` or `why?` stay part of the path
+fn encode_key(key: &str) -> String {
+    let mut encoded = String::new();
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Path from the page of `from` to the page of `to`, pages are
+/// `<key>/index.html` so every level of `from` is one `..`
+fn page_href(from: &str, to: &str) -> String {
+    let up = "../".repeat(from.split('/').count());
+    format!("{}{}/index.html", up, encode_key(to))
+}
+
+fn root_href(from: &str, path: &str) -> String {
+    format!("{}{}", "../".repeat(from.split('/').count()), path)
+}
+
+/// Escape text for use within a Markdown link label
+fn escape_label(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+}
+
+struct Site<'a> {
+    tree: &'a Tree,
+    public: BTreeSet<&'a NodeKey>,
+}
+
+impl<'a> Site<'a> {
+    fn is_public(&self, key: &str) -> bool {
+        self.public.contains(&key.to_string())
+    }
+    fn node_link(&self, from: &str, key: &str) -> String {
+        format!(
+            "<a href=\"{}\">{}</a>",
+            escape_html(&page_href(from, key)),
+            escape_html(&self.tree.nodes[key].name)
+        )
+    }
+    /// `[[...]]` references as Markdown links to the page of the node they
+    /// point at, or plain text when that node is not published
+    fn resolve_links(&self, node: &Node, body: &str) -> String {
+        let mut lines: Vec<String> = body.split_inclusive('\n').map(String::from).collect();
+        let mut refs = extract_links(body);
+        refs.reverse();
+        for found in refs {
+            let line = &mut lines[found.line as usize - 1];
+            let start = found.char as usize;
+            let end = match line[start..].find("]]") {
+                Some(end) => start + end + 2,
+                None => continue,
+            };
+            let label = escape_label(&found.text);
+            let replaced = match node.links.get(&found.text) {
+                Some(link) if self.is_public(&link.node) => {
+                    format!("[{}]({})", label, page_href(&node.id, &link.node))
+                }
+                _ => format!("<a class=\"missing\">{}</a>", escape_html(&found.text)),
+            };
+            line.replace_range(start..end, &replaced);
+        }
+        lines.concat()
+    }
+    fn breadcrumbs(&self, node: &Node) -> String {
+        let home = root_href(&node.id, "index.html");
+        let mut crumbs = vec![format!("<a href=\"{}\">codex</a>", home)];
+        let mut ancestors = vec![];
+        let mut parent = get_parent(&node.id);
+        while let Some(key) = parent {
+            parent = get_parent(&key);
+            ancestors.push(key);
+        }
+        for key in ancestors.iter().rev().filter(|key| self.is_public(key)) {
+            crumbs.push(self.node_link(&node.id, key));
+        }
+        crumbs.push(escape_html(&node.name));
+        crumbs.join(" / ")
+    }
+    /// Published siblings before and after a node, in tree order
+    fn siblings(&self, node: &Node) -> (Option<&'a NodeKey>, Option<&'a NodeKey>) {
+        let parent = node
+            .parent
+            .as_ref()
+            .and_then(|key| self.tree.nodes.get(key));
+        let siblings: Vec<&NodeKey> = match parent {
+            Some(parent) => parent
+                .children
+                .iter()
+                .filter(|key| self.is_public(key))
+                .collect(),
+            None => return (None, None),
+        };
+        let idx = match siblings.iter().position(|key| **key == node.id) {
+            Some(idx) => idx,
+            None => return (None, None),
+        };
+        let at = |idx: usize| {
+            siblings
+                .get(idx)
+                .and_then(|key| self.tree.nodes.get_key_value(*key))
+        };
+        (
+            idx.checked_sub(1).and_then(at).map(|(key, _)| key),
+            at(idx + 1).map(|(key, _)| key),
+        )
+    }
+    /// Published nodes with a link to `key`, counted from links since
+    /// backlinks of the same text and timestamp share a key
+    fn backlinks(&self, key: &str) -> Vec<&'a NodeKey> {
+        self.public
+            .iter()
+            .filter(|from| {
+                let links = &self.tree.nodes[**from].links;
+                ***from != key && links.values().any(|link| link.node == key)
+            })
+            .copied()
+            .collect()
+    }
+    fn tags(&self, key: &str) -> Vec<&'a String> {
+        self.tree
+            .tags
+            .iter()
+            .filter(|(_, nodes)| nodes.contains(key))
+            .map(|(tag, _)| tag)
+            .collect()
+    }
+    fn list(&self, from: &str, title: &str, keys: &[&NodeKey]) -> String {
+        if keys.is_empty() {
+            return String::new();
+        }
+        let items: Vec<String> = keys
+            .iter()
+            .map(|key| format!("<li>{}</li>", self.node_link(from, key)))
+            .collect();
+        format!(
+            "<section><h2>{}</h2><ul>{}</ul></section>\n",
+            title,
+            items.join("")
+        )
+    }
+    fn page(&self, node: &Node) -> Result<String> {
+        let body = read_to_string(node.content_path())?;
+        let markdown = self.resolve_links(node, &body);
+        let mut content = String::new();
+        let options =
+            Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+        html::push_html(&mut content, Parser::new_ext(&markdown, options));
+
+        let children: Vec<&NodeKey> = node
+            .children
+            .iter()
+            .filter(|key| self.is_public(key))
+            .collect();
+        let (previous, next) = self.siblings(node);
+        let mut siblings = vec![];
+        if let Some(previous) = previous {
+            siblings.push(format!("&larr; {}", self.node_link(&node.id, previous)));
+        }
+        if let Some(next) = next {
+            siblings.push(format!("{} &rarr;", self.node_link(&node.id, next)));
+        }
+        let tags: Vec<String> = self
+            .tags(&node.id)
+            .iter()
+            .map(|tag| {
+                let href = root_href(&node.id, &format!("tags/index.html#{}", tag));
+                format!(
+                    "<li><a href=\"{}\">#{}</a></li>",
+                    escape_html(&href),
+                    escape_html(tag)
+                )
+            })
+            .collect();
+        let tags = if tags.is_empty() {
+            String::new()
+        } else {
+            format!("<ul class=\"inline tags\">{}</ul>\n", tags.join(""))
+        };
+        Ok(format!(
+            "{head}<nav>{crumbs}</nav>\n{tags}<main>\n{content}</main>\n{children}{backlinks}<footer>{siblings}</footer>\n</body>\n</html>\n",
+            head = head(&node.name, &root_href(&node.id, "style.css")),
+            crumbs = self.breadcrumbs(node),
+            tags = tags,
+            content = content,
+            children = self.list(&node.id, "Children", &children),
+            backlinks = self.list(&node.id, "Backlinks", &self.backlinks(&node.id)),
+            siblings = siblings.join(" | "),
+        ))
+    }
+    fn index(&self) -> String {
+        let roots: Vec<String> = self
+            .public
+            .iter()
+            // published nodes whose parent is not, the tops of the site
+            .filter(|key| get_parent(key).is_none_or(|parent| !self.is_public(&parent)))
+            .map(|key| {
+                format!(
+                    "<li><a href=\"{}/index.html\">{}</a></li>",
+                    escape_html(&encode_key(key)),
+                    escape_html(&self.tree.nodes[*key].name)
+                )
+            })
+            .collect();
+        format!(
+            "{}<main>\n<h1>codex</h1>\n<ul>{}</ul>\n<p><a href=\"tags/index.html\">tags</a></p>\n</main>\n</body>\n</html>\n",
+            head("codex", "style.css"),
+            roots.join("")
+        )
+    }
+    fn tag_index(&self) -> String {
+        let mut tagged: BTreeMap<&String, Vec<&NodeKey>> = BTreeMap::new();
+        for (tag, nodes) in &self.tree.tags {
+            let nodes: Vec<&NodeKey> = nodes.iter().filter(|key| self.is_public(key)).collect();
+            if !nodes.is_empty() {
+                tagged.insert(tag, nodes);
+            }
+        }
+        let sections: Vec<String> = tagged
+            .into_iter()
+            .map(|(tag, nodes)| {
+                let items: Vec<String> = nodes
+                    .iter()
+                    .map(|key| {
+                        format!(
+                            "<li><a href=\"../{}/index.html\">{}</a></li>",
+                            escape_html(&encode_key(key)),
+                            escape_html(&self.tree.nodes[*key].name)
+                        )
+                    })
+                    .collect();
+                format!(
+                    "<section id=\"{tag}\"><h2>#{tag}</h2><ul>{items}</ul></section>\n",
+                    tag = escape_html(tag),
+                    items = items.join("")
+                )
+            })
+            .collect();
+        format!(
+            "{}<nav><a href=\"../index.html\">codex</a> / tags</nav>\n<main>\n<h1>Tags</h1>\n{}</main>\n</body>\n</html>\n",
+            head("tags", "../style.css"),
+            sections.join("")
+        )
+    }
+}
+
+fn head(title: &str, style: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<link rel=\"stylesheet\" href=\"{}\">\n</head>\n<body>\n",
+        escape_html(title),
+        style
+    )
+}
+
+/// Files kept next to a node's `_.md`, like images it embeds
+fn copy_attachments(from: &Path, to: &Path) -> Result<()> {
+    for entry in read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if entry.file_type()?.is_file() && name != "_.md" && name != "meta.toml" {
+            copy(entry.path(), to.join(name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Render the published part of a codex as a static HTML site in `out`,
+/// a page per node at `<key>/index.html` plus a site and a tag index.
+/// Returns how many node pages were written.
+pub fn export_site(tree: &Tree, options: &SiteOptions, out: &Path) -> Result<usize> {
+    let site = Site {
+        tree,
+        public: options.public(tree)?,
+    };
+    create_dir_all(out.join("tags"))?;
+    write(out.join("style.css"), STYLE)?;
+    write(out.join("index.html"), site.index())?;
+    write(out.join("tags").join("index.html"), site.tag_index())?;
+    for key in &site.public {
+        let node = &tree.nodes[*key];
+        let dir: PathBuf = out.join(key);
+        create_dir_all(&dir)?;
+        write(dir.join("index.html"), site.page(node)?)?;
+        copy_attachments(&tree.dir.join(key), &dir)?;
+    }
+    debug!("exported {} pages to {:?}", site.public.len(), out);
+    Ok(site.public.len())
+}
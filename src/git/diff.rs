@@ -88,9 +88,10 @@ fn diff<'a>(repo: &'a Repository, commit: &'a Commit) -> Result<Diff<'a>, git2::
     repo.diff_tree_to_workdir_with_index(Some(&commit.tree().unwrap()), Some(&mut opts))
 }
 
-pub fn diff_w_main() -> Result<u64, git2::Error> {
+/// Words added since the working tree forked from `branch`
+pub fn diff_w_main(branch: &str) -> Result<u64, git2::Error> {
     let repo = repo()?;
-    let commit = get_ancestor_with_main_branch(&repo).unwrap();
+    let commit = get_ancestor_with_main_branch(&repo, branch)?;
     debug!("ancestor w main sha1 {:?}", &commit);
     diff_w_commit(&repo, &commit)
 }
@@ -170,9 +171,9 @@ pub fn diff_report(repo: &Repository, commit: &Commit) -> Result<String, git2::E
     Ok(output)
 }
 
-pub fn diff_w_main_report() -> Result<String, git2::Error> {
+pub fn diff_w_main_report(branch: &str) -> Result<String, git2::Error> {
     let repo = repo()?;
-    let commit = get_ancestor_with_main_branch(&repo).unwrap();
+    let commit = get_ancestor_with_main_branch(&repo, branch)?;
     diff_report(&repo, &commit)
 }
 
@@ -16,7 +16,10 @@ pub fn repo() -> Result<Repository, git2::Error> {
 }
 
 pub fn stage_paths(paths: Vec<&Path>) -> Result<(), git2::Error> {
-    let repo = repo()?;
+    stage_paths_in(&repo()?, paths)
+}
+
+pub fn stage_paths_in(repo: &Repository, paths: Vec<&Path>) -> Result<(), git2::Error> {
     let mut index = repo.index()?;
     index.add_all(&paths, git2::IndexAddOption::DEFAULT, None)?;
     index.update_all(paths, None)?;
@@ -26,6 +29,23 @@ pub fn stage_paths(paths: Vec<&Path>) -> Result<(), git2::Error> {
     Ok(())
 }
 
+/// Stage a directory rename so git sees it as a move of the same files
+pub fn stage_move(repo: &Repository, from: &Path, to: &Path) -> Result<(), git2::Error> {
+    let mut index = repo.index()?;
+    index.remove_dir(from, 0)?;
+    index.add_all([to], git2::IndexAddOption::DEFAULT, None)?;
+    index.write()?;
+    Ok(())
+}
+
+/// Stage the removal of a directory
+pub fn stage_removal(repo: &Repository, path: &Path) -> Result<(), git2::Error> {
+    let mut index = repo.index()?;
+    index.remove_dir(path, 0)?;
+    index.write()?;
+    Ok(())
+}
+
 pub fn stage_all() -> Result<(), git2::Error> {
     stage_paths(vec![Path::new("*")])?;
     Ok(())
@@ -153,7 +173,9 @@ fn repo_has_uncommitted_changes(repo: &Repository) -> Result<bool, git2::Error>
         != 0)
 }
 
-pub fn handle_git_branching() -> Result<(), git2::Error> {
+/// Move to today's day branch, first merging the previous day branch into
+/// `branch`
+pub fn handle_git_branching(branch: &str) -> Result<(), git2::Error> {
     let repo = repo()?;
     let today_branch_name = Local::now().format("%Y%m%d").to_string();
     let current_branch = repo.head()?.name().unwrap_or("").to_string();
@@ -161,12 +183,11 @@ pub fn handle_git_branching() -> Result<(), git2::Error> {
     if current_branch != format!("refs/heads/{}", today_branch_name) {
         commit_any(None)?;
         // what if current branch is main? shouldn't be ever yea?
-        // TODO need to get default branch from git2::Config::open_global()
         let last_commit = find_last_commit(&repo)?;
-        let main_commit = get_last_commit_of_branch(&repo, "main")?;
+        let main_commit = get_last_commit_of_branch(&repo, branch)?;
 
         if last_commit.id() != main_commit.id() {
-            checkout_branch(&repo, "main")?;
+            checkout_branch(&repo, branch)?;
             // do i need to find annotated commits?
             let main = repo.find_annotated_commit(main_commit.id())?;
             let other = repo.find_annotated_commit(last_commit.id())?;
@@ -184,7 +205,7 @@ pub fn handle_git_branching() -> Result<(), git2::Error> {
                 Some("HEAD"),
                 &sig,
                 &sig,
-                &format!("merge day branch {} into main", current_branch),
+                &format!("merge day branch {} into {}", current_branch, branch),
                 &result_tree,
                 &[&main_commit, &last_commit],
             )?;
@@ -196,15 +217,17 @@ pub fn handle_git_branching() -> Result<(), git2::Error> {
     Ok(())
 }
 
-pub fn get_ancestor_with_main_branch(repo: &Repository) -> Result<Commit, git2::Error> {
+pub fn get_ancestor_with_main_branch<'repo>(
+    repo: &'repo Repository,
+    branch: &str,
+) -> Result<Commit<'repo>, git2::Error> {
     // Ok i should make this module have a
     // Repo struct and some helper functions
     // any func that takes repo should go on
     // the Repo struct which will wrap
     // git2::Repository
     let last_commit = find_last_commit(repo)?;
-    // TODO need to get default branch from git2::Config::open_global()
-    let main_commit = get_last_commit_of_branch(repo, "main")?;
+    let main_commit = get_last_commit_of_branch(repo, branch)?;
     // do i need to find annotated commits?
     let main = repo.find_annotated_commit(main_commit.id())?;
     let other = repo.find_annotated_commit(last_commit.id())?;
@@ -1,86 +1,104 @@
+use crate::config::{CredentialsConfig, GitConfig};
+use crate::error::{self, Error};
 use crate::git::{checkout_branch, commit_all, repo};
 use git2::build::RepoBuilder;
-use git2::{Cred, FetchOptions, PushOptions, RemoteCallbacks, Repository};
+use git2::{Cred, CredentialType, FetchOptions, PushOptions, RemoteCallbacks, Repository};
 use log::*;
 use regex::Regex;
 use std::path::Path;
-use std::process::{Command, ExitStatusError};
-use std::{env, fs};
+use std::process::Command;
 
-pub async fn push_all_to_git_remote_cmd() {
-    commit_all(None).unwrap();
+/// Commit everything and push every branch, off of the async runtime
+pub async fn push_all_to_git_remote_cmd(git: GitConfig) -> error::Result<()> {
+    tokio::task::spawn_blocking(move || {
+        commit_all(None)?;
+        push_all(&git)
+    })
+    .await
+    .map_err(|e| Error::Git(format!("push task failed: {}", e)))?
+}
+
+pub fn push_all(git: &GitConfig) -> error::Result<()> {
     let push_all = Command::new("git")
         .arg("push")
+        .arg(&git.remote_name)
         .arg("--all")
         .output()
-        .expect("push all command fail");
-    debug!("GIT PUSH: {}", String::from_utf8(push_all.stdout).unwrap());
+        .map_err(|e| Error::Git(format!("unable to run git push: {}", e)))?;
+    debug!("GIT PUSH: {}", String::from_utf8_lossy(&push_all.stdout));
     debug!(
         "GIT PUSH STDERR: {}",
-        String::from_utf8(push_all.stderr).unwrap()
+        String::from_utf8_lossy(&push_all.stderr)
     );
-    push_all
-        .status
-        .exit_ok()
-        .expect("push all non zero exit code");
+    Ok(push_all.status.exit_ok()?)
+}
+
+pub async fn push_to_git_remote(
+    git: GitConfig,
+    credentials: CredentialsConfig,
+) -> error::Result<()> {
+    tokio::task::spawn_blocking(move || push_main_to_git_remote(&git, &credentials))
+        .await
+        .map_err(|e| Error::Git(format!("push task failed: {}", e)))?
 }
 
-pub async fn push_to_git_remote() -> Result<(), git2::Error> {
+fn push_main_to_git_remote(git: &GitConfig, credentials: &CredentialsConfig) -> error::Result<()> {
     // commit_any(None)?; -- not currently working
-    commit_all(None).unwrap();
+    commit_all(None)?;
     let mut push_opts = PushOptions::default();
-    push_opts.remote_callbacks(callback());
+    push_opts.remote_callbacks(callback(credentials));
     let repo = repo()?;
-    let mut remote = repo.find_remote("origin")?;
-    // TODO need to get default branch from git2::Config::open_global()
+    let mut remote = repo.find_remote(&git.remote_name)?;
     remote.push(
-        &[
-            "refs/heads/main:refs/heads/main".to_owned(),
-        ],
+        &[format!("refs/heads/{0}:refs/heads/{0}", git.branch)],
         Some(&mut push_opts),
     )?;
-    debug!("main branch pushed");
+    debug!("{} branch pushed", git.branch);
     Ok(())
 }
 
-fn callback() -> RemoteCallbacks<'static> {
+/// Authenticate with the remote as set in `[credentials]`
+fn callback(credentials: &CredentialsConfig) -> RemoteCallbacks<'static> {
+    let credentials = credentials.clone();
     let mut cb = RemoteCallbacks::new();
-    cb.credentials(|_url, username, _allowed_types| {
+    cb.credentials(move |_url, username, _allowed_types| {
         debug!(
             "CB\nurl: {:?}\nusername: {:?}\nallowed types: {:?}",
             _url, &username, &_allowed_types
         );
-        // Cred::ssh_key(
-        //     username.unwrap(),
-        //     None,
-        //     std::path::Path::new(&format!("{}/.ssh/id_rsa", env::var("HOME").unwrap())),
-        //     None,
-        // )
-        Cred::ssh_key_from_memory(
-            username.unwrap(),
-            None,
-            &fs::read_to_string(std::path::Path::new(&format!(
-                "{}/.ssh/id_ghub",
-                env::var("HOME").unwrap()
-            )))
-            .unwrap(),
-            None,
-        )
+        let username = credentials
+            .username
+            .as_deref()
+            .or(username)
+            .unwrap_or("git");
+        if _allowed_types.contains(CredentialType::USERNAME) {
+            return Cred::username(username);
+        }
+        if credentials.ssh_agent {
+            Cred::ssh_key_from_agent(username)
+        } else {
+            Cred::ssh_key(username, None, &credentials.ssh_key_path(), None)
+        }
     });
     cb
 }
 
-pub fn git_clone(url: &str) -> Result<(), git2::Error> {
+pub fn git_clone(
+    url: &str,
+    git: &GitConfig,
+    credentials: &CredentialsConfig,
+) -> Result<(), git2::Error> {
     let mut opts = FetchOptions::new();
-    opts.remote_callbacks(callback());
+    opts.remote_callbacks(callback(credentials));
     opts.download_tags(git2::AutotagOption::All);
     let mut builder = RepoBuilder::new();
     builder.fetch_options(opts);
+    builder.remote_create(|repo, _name, url| repo.remote(&git.remote_name, url));
     let repo = builder.clone(url, Path::new("./"))?;
     let latest_oid = repo.refname_to_id("refs/tags/latest")?;
     let latest = &repo.find_tag(latest_oid)?;
     let most_recent_active_branch = latest.message().unwrap().trim().to_string();
-    let mut remote = repo.find_remote("origin")?;
+    let mut remote = repo.find_remote(&git.remote_name)?;
     do_fetch(
         &repo,
         &[&format!(
@@ -88,29 +106,30 @@ pub fn git_clone(url: &str) -> Result<(), git2::Error> {
             &most_recent_active_branch, &most_recent_active_branch
         )],
         &mut remote,
+        credentials,
     )?;
     checkout_branch(&repo, &most_recent_active_branch)?;
     Ok(())
 }
 
-pub fn fetch_and_pull() -> Result<(), git2::Error> {
+pub fn fetch_and_pull(git: &GitConfig, credentials: &CredentialsConfig) -> Result<(), git2::Error> {
     let repo = repo().unwrap();
-    let mut remote = repo.find_remote("origin").unwrap();
+    let mut remote = repo.find_remote(&git.remote_name).unwrap();
     // let today_branch_name = Local::now().format("%Y%m%d").to_string();
-    // TODO need to get default branch from git2::Config::open_global()
     // let gox_repo = Repository::open("./").expect("unable to open repo | gitoxide");
     // gox_repo.fe
 
     let main_commit = do_fetch(
         &repo,
         &[
-            "+refs/heads/main:refs/heads/main",
+            &format!("+refs/heads/{0}:refs/heads/{0}", git.branch),
             "+refs/tags/latest:refs/tags/latest",
         ],
         &mut remote,
+        credentials,
     )
     .unwrap();
-    let mut remote = repo.find_remote("origin").unwrap();
+    let mut remote = repo.find_remote(&git.remote_name).unwrap();
     // let mut opts = git2::FetchOptions::new();
     // opts.remote_callbacks(callback());
     // opts.download_tags(git2::AutotagOption::All);
@@ -127,12 +146,13 @@ pub fn fetch_and_pull() -> Result<(), git2::Error> {
             &most_recent_active_branch.trim()
         )],
         &mut remote,
+        credentials,
     )
     .unwrap();
     // probably need to wrtie the tree o ftshi commit to the repo
     // let commit_id = &today_branch_commit.id();
 
-    do_merge(&repo, "main", main_commit).unwrap();
+    do_merge(&repo, &git.branch, main_commit).unwrap();
     do_merge(&repo, most_recent_active_branch, today_branch_commit).unwrap();
     // checkout_branch(&repo, most_recent_active_branch)?;
     // let tree = repo.head()?.peel_to_tree()?;
@@ -144,18 +164,19 @@ pub fn fetch_and_pull() -> Result<(), git2::Error> {
     Ok(())
 }
 
-pub fn pull_origin_main() -> std::result::Result<(), ExitStatusError> {
+/// Pull the configured branch from the configured remote
+pub fn pull_branch(git: &GitConfig) -> error::Result<()> {
     let pull_main = Command::new("git")
         .arg("pull")
-        .arg("origin")
-        .arg("main")
+        .arg(&git.remote_name)
+        .arg(&git.branch)
         .arg("--ff")
         .output()
-        .expect("pull main command failed");
-    pull_main.status.exit_ok()
+        .map_err(|e| Error::Git(format!("unable to run git pull: {}", e)))?;
+    Ok(pull_main.status.exit_ok()?)
 }
 
-pub fn cmdline_fetch_and_pull() {
+pub fn cmdline_fetch_and_pull(git: &GitConfig) {
     let fetch = Command::new("git")
         .arg("fetch")
         .arg("--all")
@@ -169,7 +190,7 @@ pub fn cmdline_fetch_and_pull() {
     fetch.status.exit_ok().expect("fetch non zero exit code");
     let chkout_main = Command::new("git")
         .arg("checkout")
-        .arg("main")
+        .arg(&git.branch)
         .output()
         .expect("checkout main command failed");
     debug!(
@@ -186,8 +207,8 @@ pub fn cmdline_fetch_and_pull() {
         .expect("check out of main failed");
     let pull_main = Command::new("git")
         .arg("pull")
-        .arg("origin")
-        .arg("main")
+        .arg(&git.remote_name)
+        .arg(&git.branch)
         .arg("--ff")
         .output()
         .expect("pull main command failed");
@@ -198,7 +219,11 @@ pub fn cmdline_fetch_and_pull() {
         .output()
         .expect("branch -a command failed");
     ls_branches.status.exit_ok().expect("branch ls failed");
-    let remote_yyyymmdd_branch_patter = Regex::new(r"^\s*remotes/origin/(\d{8})").unwrap();
+    let remote_yyyymmdd_branch_patter = Regex::new(&format!(
+        r"^\s*remotes/{}/(\d{{8}})",
+        regex::escape(&git.remote_name)
+    ))
+    .unwrap();
     let latest = String::from_utf8(ls_branches.stdout)
         .unwrap()
         .lines()
@@ -229,7 +254,7 @@ pub fn cmdline_fetch_and_pull() {
         .expect("check out of latest failed");
     let pull_latest = Command::new("git")
         .arg("pull")
-        .arg("origin")
+        .arg(&git.remote_name)
         .arg(latest)
         .arg("--ff")
         .output()
@@ -241,9 +266,10 @@ fn do_fetch<'a>(
     repo: &'a git2::Repository,
     refs: &[&str],
     remote: &'a mut git2::Remote,
+    credentials: &CredentialsConfig,
 ) -> Result<git2::AnnotatedCommit<'a>, git2::Error> {
     let mut opts = git2::FetchOptions::new();
-    opts.remote_callbacks(callback());
+    opts.remote_callbacks(callback(credentials));
     opts.download_tags(git2::AutotagOption::All);
     debug!("Fetching {:?} for repo", refs);
     remote.fetch(refs, Some(&mut opts), None).unwrap();
@@ -0,0 +1,2 @@
+//! Reading notes kept by other tools into a codex
+pub mod obsidian;
@@ -0,0 +1,659 @@
+use crate::error::Result;
+use crate::node::markdown::{extract_links, LinkAnchor};
+use crate::node::{NodeKey, NodeLink};
+use crate::tree::tags::unreserved_tag;
+use crate::tree::{Error, Tree};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use log::*;
+use serde_yaml::{Mapping, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{copy, metadata, read_dir, read_to_string, write};
+use std::path::{Path, PathBuf};
+
+/// What importing a vault brought into the codex
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportStats {
+    /// Node holding the vault, named after its folder
+    pub root: NodeKey,
+    pub notes: usize,
+    /// Folders without a folder note, imported as nodes of their own
+    pub folders: usize,
+    pub links: usize,
+    pub attachments: usize,
+    /// Links and embeds to notes or files that are not in the vault
+    pub unresolved: usize,
+}
+
+/// Tags, aliases and any other properties of a note's YAML frontmatter
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Frontmatter {
+    tags: Vec<String>,
+    aliases: Vec<String>,
+    created: Option<DateTime<Local>>,
+    updated: Option<DateTime<Local>>,
+    updates: Option<u64>,
+    /// Properties codex has no place for, as YAML
+    rest: Option<String>,
+}
+
+/// Split `---` fenced YAML frontmatter off the top of a note
+fn split_frontmatter(note: &str) -> Option<(&str, &str)> {
+    let rest = note
+        .strip_prefix("---\n")
+        .or_else(|| note.strip_prefix("---\r\n"))?;
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Strings of a list property, or of a single string split on `split`
+fn strings(value: Option<Value>, split: &[char]) -> Vec<String> {
+    let text = |value: &Value| match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    };
+    match value {
+        Some(Value::Sequence(values)) => values.iter().filter_map(text).collect(),
+        Some(value) => text(&value)
+            .map(|text| text.split(split).map(String::from).collect())
+            .unwrap_or_default(),
+        None => vec![],
+    }
+    .into_iter()
+    .map(|text| text.trim().to_string())
+    .filter(|text| !text.is_empty())
+    .collect()
+}
+
+/// A date property as Obsidian or a codex export writes it, with or
+/// without a time
+fn date_property(value: &Value) -> Option<DateTime<Local>> {
+    let text = value.as_str()?;
+    if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        return Some(date.with_timezone(&Local));
+    }
+    let date = NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .map(|date| date.and_hms_opt(0, 0, 0).unwrap())
+        })
+        .ok()?;
+    Local.from_local_datetime(&date).single()
+}
+
+/// None when the frontmatter isn't a YAML mapping
+fn parse_frontmatter(yaml: &str) -> Option<Frontmatter> {
+    let mut properties: Mapping = match serde_yaml::from_str(yaml).ok()? {
+        Value::Mapping(properties) => properties,
+        Value::Null => Mapping::new(),
+        _ => return None,
+    };
+    let mut frontmatter = Frontmatter::default();
+    for key in ["tags", "tag"].iter() {
+        let tags = strings(properties.remove(*key), &[',', ' ']);
+        frontmatter
+            .tags
+            .extend(tags.iter().filter_map(|tag| match unreserved_tag(tag) {
+                Ok(tag) => Some(tag),
+                Err(e) => {
+                    warn!("skipping tag {:?}: {}", tag, e);
+                    None
+                }
+            }));
+    }
+    for key in ["aliases", "alias"].iter() {
+        frontmatter
+            .aliases
+            .extend(strings(properties.remove(*key), &[',']));
+    }
+    let mut date = |key: &str| {
+        let date = properties.get(key).and_then(date_property)?;
+        properties.remove(key);
+        Some(date)
+    };
+    frontmatter.created = date("created");
+    frontmatter.updated = date("updated");
+    frontmatter.updates = properties.get("updates").and_then(Value::as_u64);
+    if frontmatter.updates.is_some() {
+        properties.remove("updates");
+    }
+    if !properties.is_empty() {
+        frontmatter.rest = serde_yaml::to_string(&properties).ok();
+    }
+    Some(frontmatter)
+}
+
+/// An Obsidian `[[note#heading|shown text]]` reference
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct WikiLink {
+    /// Note name or vault path, empty for a heading of the same note
+    note: String,
+    anchor: Option<LinkAnchor>,
+    display: Option<String>,
+}
+
+fn parse_wikilink(text: &str) -> WikiLink {
+    let (target, display) = match text.split_once('|') {
+        // `\|` separates the display text within tables
+        Some((target, display)) => (target.trim_end_matches('\\'), Some(display.trim())),
+        None => (text, None),
+    };
+    let (note, anchor) = match target.split_once('#') {
+        Some((note, anchor)) => {
+            // `note#Section#Subsection` lands on the last heading
+            let anchor = anchor.rsplit('#').next().unwrap_or(anchor).trim();
+            let anchor = match anchor.strip_prefix('^') {
+                Some(id) => LinkAnchor::Block(id.to_string()),
+                None => LinkAnchor::Heading(anchor.to_string()),
+            };
+            (note, Some(anchor))
+        }
+        None => (target, None),
+    };
+    let note = note.trim();
+    let note = match note.len().checked_sub(3) {
+        Some(idx) if note.is_char_boundary(idx) && note[idx..].eq_ignore_ascii_case(".md") => {
+            &note[..idx]
+        }
+        _ => note,
+    };
+    WikiLink {
+        note: note.to_string(),
+        anchor,
+        display: display
+            .filter(|display| !display.is_empty())
+            .map(String::from),
+    }
+}
+
+impl WikiLink {
+    /// Codex link text reading `name`, keeping the anchor
+    fn text(&self, name: &str) -> String {
+        match &self.anchor {
+            Some(LinkAnchor::Heading(heading)) => format!("{}#{}", name, heading),
+            Some(LinkAnchor::Block(id)) => format!("{}^{}", name, id),
+            None => name.to_string(),
+        }
+    }
+    /// The text Obsidian shows for the link
+    fn shown(&self) -> String {
+        self.text(self.display.as_ref().unwrap_or(&self.note))
+    }
+}
+
+fn is_note(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("md"))
+}
+
+/// Created and last modified time of a file, created falls back to
+/// modified where the filesystem doesn't keep it
+fn file_times(path: &Path) -> (DateTime<Local>, DateTime<Local>) {
+    let metadata = match metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            warn!("no times for {:?}: {}", path, e);
+            return (Local::now(), Local::now());
+        }
+    };
+    let updated = metadata
+        .modified()
+        .map(DateTime::from)
+        .unwrap_or_else(|_| Local::now());
+    let created = metadata.created().map(DateTime::from).unwrap_or(updated);
+    (created.min(updated), updated)
+}
+
+/// Markdown link destination for an attachment copied next to `_.md`
+fn destination(name: &str) -> String {
+    if name.contains(|c: char| c.is_whitespace() || c == '(' || c == ')') {
+        format!("<{}>", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// A folder or note of the vault that becomes a node
+struct Entry {
+    name: String,
+    /// Markdown file of the note, for a folder its folder note if any
+    note: Option<PathBuf>,
+    /// Where the times of the node come from, the note when there is one
+    source: PathBuf,
+    /// Index of the parent folder in the entries, None at the top
+    parent: Option<usize>,
+    frontmatter: Frontmatter,
+    /// Note without its frontmatter
+    body: String,
+}
+
+/// A note body in codex form and the nodes its links point at
+#[derive(Debug, Default)]
+struct Converted {
+    body: String,
+    links: HashMap<String, usize>,
+    attachments: usize,
+    unresolved: usize,
+}
+
+struct Vault {
+    dir: PathBuf,
+    /// Folders and notes, parents before their children
+    entries: Vec<Entry>,
+    /// Files other than notes by file name, embeds find them anywhere
+    attachments: BTreeMap<String, PathBuf>,
+    /// Notes by lowercase vault path without `.md`
+    by_path: HashMap<String, usize>,
+    /// Notes by lowercase file name
+    by_name: HashMap<String, Vec<usize>>,
+    by_alias: HashMap<String, Vec<usize>>,
+}
+
+impl Vault {
+    fn scan(dir: &Path) -> Result<Vault> {
+        let mut vault = Vault {
+            dir: dir.to_path_buf(),
+            entries: vec![],
+            attachments: BTreeMap::new(),
+            by_path: HashMap::new(),
+            by_name: HashMap::new(),
+            by_alias: HashMap::new(),
+        };
+        vault.scan_dir(dir, None)?;
+        for idx in 0..vault.entries.len() {
+            vault.read_note(idx)?;
+        }
+        Ok(vault)
+    }
+    /// Add the folders and notes within `dir`, skipping hidden ones like
+    /// `.obsidian`. Folders without any note are left out.
+    /// Returns whether anything was added.
+    fn scan_dir(&mut self, dir: &Path, parent: Option<usize>) -> Result<bool> {
+        let (mut folders, mut notes) = (vec![], vec![]);
+        for entry in read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                folders.push(entry.path());
+            } else if is_note(&entry.path()) {
+                notes.push(entry.path());
+            } else {
+                self.attachments.entry(name).or_insert_with(|| entry.path());
+            }
+        }
+        folders.sort();
+        notes.sort();
+        let mut added = false;
+        // a note named after its folder is the folder's own note
+        if let Some(parent) = parent {
+            let folder_note = format!("{}.md", self.entries[parent].name);
+            if let Some(idx) = notes.iter().position(|note| note.ends_with(&folder_note)) {
+                let note = notes.remove(idx);
+                self.entries[parent].source = note.clone();
+                self.entries[parent].note = Some(note);
+                added = true;
+            }
+        }
+        for folder in folders {
+            let idx = self.entries.len();
+            self.entries.push(Entry {
+                name: folder.file_name().unwrap().to_string_lossy().to_string(),
+                note: None,
+                source: folder.clone(),
+                parent,
+                frontmatter: Frontmatter::default(),
+                body: String::new(),
+            });
+            if self.scan_dir(&folder, Some(idx))? {
+                added = true;
+            } else {
+                self.entries.truncate(idx);
+            }
+        }
+        for note in notes {
+            self.entries.push(Entry {
+                name: note.file_stem().unwrap().to_string_lossy().to_string(),
+                note: Some(note.clone()),
+                source: note,
+                parent,
+                frontmatter: Frontmatter::default(),
+                body: String::new(),
+            });
+            added = true;
+        }
+        Ok(added)
+    }
+    /// Read the frontmatter and body of a note and index it for links
+    fn read_note(&mut self, idx: usize) -> Result<()> {
+        let path = match &self.entries[idx].note {
+            Some(path) => path.clone(),
+            None => return Ok(()),
+        };
+        let note = read_to_string(&path)?;
+        let (frontmatter, body) = match split_frontmatter(&note) {
+            Some((yaml, body)) => match parse_frontmatter(yaml) {
+                Some(frontmatter) => (frontmatter, body),
+                None => {
+                    warn!("{:?} has frontmatter that isn't a YAML mapping", path);
+                    (Frontmatter::default(), note.as_str())
+                }
+            },
+            None => (Frontmatter::default(), note.as_str()),
+        };
+        let relative = path
+            .strip_prefix(&self.dir)
+            .unwrap_or(&path)
+            .with_extension("");
+        let relative: Vec<String> = relative
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy().to_lowercase())
+            .collect();
+        self.by_path.insert(relative.join("/"), idx);
+        let stem = path.file_stem().unwrap().to_string_lossy().to_lowercase();
+        self.by_name.entry(stem).or_default().push(idx);
+        for alias in &frontmatter.aliases {
+            self.by_alias
+                .entry(alias.to_lowercase())
+                .or_default()
+                .push(idx);
+        }
+        let entry = &mut self.entries[idx];
+        entry.body = body.to_string();
+        entry.frontmatter = frontmatter;
+        Ok(())
+    }
+    fn depth(&self, mut idx: usize) -> usize {
+        let mut depth = 0;
+        while let Some(parent) = self.entries[idx].parent {
+            depth += 1;
+            idx = parent;
+        }
+        depth
+    }
+    /// The note a link points at the way Obsidian finds it, by vault path,
+    /// then by the shallowest note of that name, then by alias
+    fn resolve(&self, note: &str) -> Option<usize> {
+        let note = note.to_lowercase();
+        if let Some(idx) = self.by_path.get(&note) {
+            return Some(*idx);
+        }
+        let name = note.rsplit('/').next().unwrap_or(&note);
+        let found = self
+            .by_name
+            .get(name)
+            .or_else(|| self.by_alias.get(&note))?;
+        found.iter().copied().min_by_key(|idx| self.depth(*idx))
+    }
+    /// Body of a node in codex form, titled by a heading and with any
+    /// frontmatter codex has no place for kept in a `yaml` block
+    fn assemble(&self, idx: usize) -> String {
+        let entry = &self.entries[idx];
+        let body = entry.body.trim_start_matches(['\n', '\r']);
+        let (title, rest) = if body.starts_with("# ") {
+            body.split_once('\n').unwrap_or((body, ""))
+        } else {
+            ("", body)
+        };
+        let mut assembled = match title {
+            "" => format!("# {}\n", entry.name),
+            title => format!("{}\n", title.trim_end()),
+        };
+        if let Some(properties) = &entry.frontmatter.rest {
+            assembled.push_str(&format!("\n```yaml\n{}```\n", properties));
+        }
+        let rest = rest.trim_start_matches(['\n', '\r']);
+        if !rest.is_empty() {
+            assembled.push('\n');
+            assembled.push_str(rest);
+        }
+        assembled
+    }
+    /// Turn the wikilinks of a note into codex links and its embedded
+    /// files into Markdown images, copied into `dir`.
+    /// A link keeps the text Obsidian shows unless another link of the
+    /// note already uses it for a different note.
+    fn convert(&self, idx: usize, dir: &Path) -> Result<Converted> {
+        let body = self.assemble(idx);
+        let mut converted = Converted::default();
+        let mut lines: Vec<String> = body.split_inclusive('\n').map(String::from).collect();
+        let mut replacements = vec![];
+        for found in extract_links(&body) {
+            let line = &lines[found.line as usize - 1];
+            let mut start = found.char as usize;
+            let end = match line[start..].find("]]") {
+                Some(end) => start + end + 2,
+                None => continue,
+            };
+            let embed = line[..start].ends_with('!');
+            let link = parse_wikilink(&found.text);
+            let file = link.note.rsplit('/').next().unwrap_or(&link.note);
+            if let Some(path) = self.attachments.get(file).filter(|_| embed) {
+                copy(path, dir.join(file))?;
+                converted.attachments += 1;
+                let image = format!("![{}]({})", file, destination(file));
+                replacements.push((found.line, start - 1, end, image));
+                continue;
+            }
+            let target = match link.note.as_str() {
+                "" => Some(idx),
+                note => self.resolve(note),
+            };
+            let text = match target {
+                Some(target) => {
+                    let mut text = link.shown();
+                    if converted
+                        .links
+                        .get(&text)
+                        .is_some_and(|linked| *linked != target)
+                    {
+                        text = link.text(&self.entries[target].name);
+                    }
+                    converted.links.entry(text.clone()).or_insert(target);
+                    text
+                }
+                None => {
+                    debug!("no note in the vault for [[{}]]", found.text);
+                    converted.unresolved += 1;
+                    if embed {
+                        continue;
+                    }
+                    link.shown()
+                }
+            };
+            // embedded notes become plain links
+            if embed {
+                start -= 1;
+            }
+            replacements.push((found.line, start, end, format!("[[{}]]", text)));
+        }
+        for (line, start, end, replaced) in replacements.into_iter().rev() {
+            lines[line as usize - 1].replace_range(start..end, &replaced);
+        }
+        converted.body = lines.concat();
+        Ok(converted)
+    }
+}
+
+/// Import an Obsidian vault as a new node under `parent`, named after the
+/// vault folder. Folders and notes become nodes in the vault's order,
+/// a note named after its folder becomes the folder node.
+///
+/// Frontmatter tags and aliases go into `meta.toml`, `[[wikilinks]]` are
+/// linked both ways and embedded files are copied next to the note that
+/// embeds them. Nodes keep their `created` and `updated` properties, or
+/// else the created and modified times of their files.
+pub fn import_vault(tree: &mut Tree, vault: &Path, parent: &str) -> Result<ImportStats> {
+    if !vault.is_dir() {
+        return Err(Error::Args(format!(
+            "{} is not an Obsidian vault",
+            vault.display()
+        )));
+    }
+    if !tree.nodes.contains_key(parent) {
+        return Err(Error::NodeNotFound(parent.to_string()));
+    }
+    let dir = vault.canonicalize()?;
+    let vault = Vault::scan(&dir)?;
+    let name = dir.file_name().map_or("vault".to_string(), |name| {
+        name.to_string_lossy().to_string()
+    });
+    let root = tree.create_node(Some(parent), Some(&name))?;
+    // parents are created first, a power of ten sibling renumbers the
+    // earlier ones so the keys made so far follow its renames
+    let mut keys: Vec<NodeKey> = vec![];
+    for entry in &vault.entries {
+        let parent = entry.parent.map_or(&root, |parent| &keys[parent]).clone();
+        let (key, renames) = tree.create_child(&parent, &entry.name)?;
+        for key in keys.iter_mut() {
+            if let Some(renamed) = renames.get(key) {
+                *key = renamed.clone();
+            }
+        }
+        keys.push(key);
+    }
+
+    let mut stats = ImportStats {
+        root: root.clone(),
+        ..ImportStats::default()
+    };
+    let mut linked: Vec<(String, HashMap<String, usize>)> = vec![];
+    for (idx, entry) in vault.entries.iter().enumerate() {
+        let key = &keys[idx];
+        let converted = match entry.note {
+            Some(_) => vault.convert(idx, &tree.dir.join(key))?,
+            None => Converted {
+                body: format!("# {}\n", entry.name),
+                ..Converted::default()
+            },
+        };
+        match entry.note {
+            Some(_) => stats.notes += 1,
+            None => stats.folders += 1,
+        }
+        stats.attachments += converted.attachments;
+        stats.unresolved += converted.unresolved;
+        let (created, updated) = file_times(&entry.source);
+        let node = tree
+            .nodes
+            .get_mut(key)
+            .ok_or_else(|| Error::NodeNotFound(key.to_string()))?;
+        write(node.content_path(), &converted.body)?;
+        node.tags.extend(entry.frontmatter.tags.iter().cloned());
+        node.aliases = entry.frontmatter.aliases.clone();
+        node.created = entry.frontmatter.created.unwrap_or(created);
+        node.updated = entry.frontmatter.updated.unwrap_or(updated);
+        node.updates = entry.frontmatter.updates.unwrap_or(node.updates);
+        linked.push((converted.body, converted.links));
+    }
+
+    for (idx, (body, links)) in linked.iter().enumerate() {
+        let from = &keys[idx];
+        let mut seen = HashSet::new();
+        for found in extract_links(body) {
+            let target = match links.get(&found.text) {
+                Some(target) if seen.insert(found.text.clone()) => &keys[*target],
+                _ => continue,
+            };
+            let (mut link, mut backlink) = NodeLink::pair(
+                found.text.clone(),
+                from.clone(),
+                found.line,
+                found.char,
+                target.clone(),
+                0,
+                0,
+            );
+            // backlinks are keyed by text and time and every link of the
+            // import is made within the same second
+            let to = tree
+                .nodes
+                .get_mut(target)
+                .ok_or_else(|| Error::NodeNotFound(target.to_string()))?;
+            while to
+                .backlinks
+                .contains_key(&(backlink.text.clone(), backlink.timestamp))
+            {
+                backlink.timestamp -= 1;
+            }
+            link.timestamp = backlink.timestamp;
+            to.backlinks
+                .insert((backlink.text.clone(), backlink.timestamp), backlink);
+            tree.nodes
+                .get_mut(from)
+                .ok_or_else(|| Error::NodeNotFound(from.to_string()))?
+                .links
+                .insert(found.text, link);
+            stats.links += 1;
+        }
+    }
+    for key in &keys {
+        tree.nodes[key].write_meta();
+        tree.reindex_node(key);
+    }
+    for key in &keys {
+        if let Err(e) = tree.sync_links(key) {
+            error!("unable to sync links of {}: {}", key, e);
+        }
+    }
+    info!("imported {:?} as {}: {:?}", dir, root, stats);
+    Ok(stats)
+}
+
+#[test]
+fn test_parse_obsidian_note() {
+    let note = "---\ntags: [Project/Codex, journal]\naliases: Codex, The Codex\nstatus: draft\n---\n# Codex\n";
+    let (yaml, body) = split_frontmatter(note).unwrap();
+    assert_eq!(body, "# Codex\n");
+    assert_eq!(
+        parse_frontmatter(yaml),
+        Some(Frontmatter {
+            tags: vec!["project/codex".to_string()],
+            aliases: vec!["Codex".to_string(), "The Codex".to_string()],
+            rest: Some("status: draft\n".to_string()),
+            ..Frontmatter::default()
+        })
+    );
+    assert_eq!(
+        parse_frontmatter("tag: a b\n").unwrap().tags,
+        vec!["a", "b"]
+    );
+    let dated = "created: 2021-03-04\nupdated: 2021-03-05T06:07:08\nupdates: 3\n";
+    let dated = parse_frontmatter(dated).unwrap();
+    assert_eq!(
+        dated.created,
+        Local.with_ymd_and_hms(2021, 3, 4, 0, 0, 0).single()
+    );
+    assert_eq!(
+        dated.updated,
+        Local.with_ymd_and_hms(2021, 3, 5, 6, 7, 8).single()
+    );
+    assert_eq!((dated.updates, dated.rest), (Some(3), None));
+    let undated = parse_frontmatter("created: someday\n").unwrap();
+    assert_eq!(undated.rest.unwrap(), "created: someday\n");
+    assert_eq!(parse_frontmatter("- a\n"), None);
+    assert_eq!(split_frontmatter("---\nno end\n"), None);
+
+    assert_eq!(
+        parse_wikilink("notes/Giant Steps.md#Changes#Bridge|the bridge"),
+        WikiLink {
+            note: "notes/Giant Steps".to_string(),
+            anchor: Some(LinkAnchor::Heading("Bridge".to_string())),
+            display: Some("the bridge".to_string()),
+        }
+    );
+    let block = parse_wikilink("Giant Steps#^abc123");
+    assert_eq!(block.anchor, Some(LinkAnchor::Block("abc123".to_string())));
+    assert_eq!(block.shown(), "Giant Steps^abc123");
+    assert_eq!(parse_wikilink("#Intro").note, "");
+    assert_eq!(parse_wikilink("a\\|b").shown(), "b");
+}
@@ -2,7 +2,13 @@
 #![allow(unused_variables)]
 #![feature(exit_status_error)]
 
+pub mod cli;
+pub mod config;
+pub mod error;
+pub mod export;
 pub mod git;
+pub mod import;
 pub mod node;
 pub mod nvim;
+pub mod search;
 pub mod tree;
@@ -1,50 +1,101 @@
 #![feature(exit_status_error)]
 use git2::Repository;
 use log::*;
+use log4rs::append::file::FileAppender;
+use log4rs::config::{Appender, Root};
+use log4rs::encode::pattern::PatternEncoder;
 use nvim_rs::create::tokio as create;
 use std::default::Default;
 use std::env;
 use std::error::Error;
 use std::path::Path;
-use std::sync::Arc;
-//use tokio::sync::Mutex; // use std::sync::Mutex instead???
-use std::sync::Mutex;
+mod cli;
+mod config;
+mod error;
+mod export;
 mod git;
+mod import;
 mod node;
 mod nvim;
+mod search;
 mod tree;
 
+use config::{Config, LogConfig};
 use git::{commit_paths, git_clone};
 use node::init_codex_repo;
-use nvim::NeovimHandler;
+use nvim::{NeovimHandler, TreeService};
+
+/// Log to the file of `[log]` when there is one, else as `codex-log.toml`
+/// in `CODEX_HOME` says
+fn init_logging(log: &LogConfig) -> Result<(), String> {
+    match (log.file_path(), env::var("CODEX_HOME")) {
+        (Some(file), _) => {
+            let appender = FileAppender::builder()
+                .encoder(Box::new(PatternEncoder::new("{d} [{l}] {M}:{m}{n}")))
+                .build(&file)
+                .map_err(|e| format!("unable to log to {:?}: {}", file, e))?;
+            let level = log.level_filter().unwrap_or(LevelFilter::Debug);
+            let config = log4rs::Config::builder()
+                .appender(Appender::builder().build("file", Box::new(appender)))
+                .build(Root::builder().appender("file").build(level))
+                .map_err(|e| format!("invalid logging config: {}", e))?;
+            log4rs::init_config(config).map_err(|e| format!("unable to set up logging: {}", e))?;
+        }
+        (None, Ok(plugin_dir)) => {
+            let config_file = format!("{}/codex-log.toml", plugin_dir);
+            log4rs::init_file(&config_file, Default::default())
+                .map_err(|e| format!("Error configuring logging with {}: {:?}", config_file, e))?;
+            if let Some(level) = log.level_filter() {
+                log::set_max_level(level);
+            }
+        }
+        (None, Err(_)) => {
+            return Err("CODEX_HOME is not set and codex.toml has no [log] file".to_string());
+        }
+    }
+    Ok(())
+}
 
 #[tokio::main]
 async fn main() {
-    let plugin_dir = if let Ok(dir) = std::env::var("CODEX_HOME") {
-        dir
-    } else {
-        let error_msg = "ENV var CODEX_HOME not set, panicking";
-        std::panic::panic_any(error_msg);
+    match cli::parse(env::args().skip(1).collect()) {
+        Ok(Some(cli)) => std::process::exit(cli::run(cli)),
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    }
+    let pwd = env::current_dir().unwrap();
+    // before a codex is cloned only the user's codex.toml is there
+    let (config, config_error) = match Config::from_dir(&pwd) {
+        Ok(config) => (config, None),
+        Err(e) => (Config::default(), Some(e)),
     };
-    let config_file = format!("{}/codex-log.toml", plugin_dir);
 
     log_panics::init();
-    if let Err(e) = log4rs::init_file(format!("{}/codex-log.toml", plugin_dir), Default::default())
-    {
-        eprintln!("Error configuring logging with {}: {:?}", config_file, e);
+    if let Err(e) = init_logging(&config.log) {
+        eprintln!("{}", e);
         return;
     }
-    debug!("backend live within: {:?}", env::current_dir().unwrap());
-    let pwd = env::current_dir().unwrap();
+    if let Some(e) = config_error {
+        error!("{}, using the default config", e);
+    }
+    debug!("backend live within: {:?}", pwd);
     match Repository::open(&pwd) {
         Ok(_repo) => {
             // pull latest from remote, merge any updates from remote to local
             // fetch_and_pull();
         }
         Err(_) => {
-            if let Ok(git_remote_url) = std::env::var("CODEX_GIT_REMOTE") {
+            let remote = config.git.remote.clone().or_else(|| {
+                let remote = env::var("CODEX_GIT_REMOTE").ok()?;
+                warn!("CODEX_GIT_REMOTE is deprecated, set remote under [git] in codex.toml");
+                Some(remote)
+            });
+            if let Some(git_remote_url) = remote {
                 debug!("cloning {}", &git_remote_url);
-                git_clone(&git_remote_url).unwrap();
+                git_clone(&git_remote_url, &config.git, &config.credentials).unwrap();
                 debug!("{} successfully cloned!", &git_remote_url);
             } else {
                 let repo = init_codex_repo(None);
@@ -53,7 +104,7 @@ async fn main() {
             }
         }
     };
-    let tree = Arc::new(Mutex::new(match tree::Tree::build(pwd.to_str().unwrap()) {
+    let tree = TreeService::spawn(match tree::Tree::build(pwd.to_str().unwrap()) {
         Ok(tree) => {
             debug!("tree gud!");
             tree
@@ -62,7 +113,7 @@ async fn main() {
             error!("tree ERROR! {:?}", e);
             panic!("tree Error - PANIC {:?}", e);
         }
-    }));
+    });
     let handler = NeovimHandler { tree };
     let (nvim, io_handler) = create::new_parent(handler).await;
     match io_handler.await {
@@ -23,3 +23,31 @@ pub mod codex_date_format {
             .map_err(serde::de::Error::custom)
     }
 }
+
+pub mod link_variant_format {
+    use serde::{self, Deserialize, Deserializer, Serializer};
+
+    const NAME_REF: &str = "name_ref";
+    const TEXT_REF: &str = "text_ref";
+
+    pub fn serialize<S>(is_name_linked: &bool, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(if *is_name_linked { NAME_REF } else { TEXT_REF })
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<bool, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match String::deserialize(deserializer)?.as_str() {
+            NAME_REF => Ok(true),
+            TEXT_REF => Ok(false),
+            other => Err(serde::de::Error::unknown_variant(
+                other,
+                &[NAME_REF, TEXT_REF],
+            )),
+        }
+    }
+}
@@ -0,0 +1,460 @@
+use unicode_normalization::UnicodeNormalization;
+
+/// A `[[...]]` reference found in a node body
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkRef {
+    pub text: String,
+    /// 1 indexed line of the reference
+    pub line: u64,
+    /// 0 indexed byte column of the opening `[[`
+    pub char: u64,
+}
+
+fn is_fence(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("```") || line.starts_with("~~~")
+}
+
+/// `[[...]]` references on a single line, skipping inline code spans
+fn line_links(line: &str, number: u64, found: &mut Vec<LinkRef>) {
+    let bytes = line.as_bytes();
+    let mut in_code = false;
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'`' => in_code = !in_code,
+            b'[' if !in_code && bytes.get(idx + 1) == Some(&b'[') => {
+                let start = idx + 2;
+                let end = line[start..].find(['[', ']']).map(|offset| start + offset);
+                match end {
+                    Some(end) if line[end..].starts_with("]]") => {
+                        let text = line[start..end].trim();
+                        if !text.is_empty() {
+                            found.push(LinkRef {
+                                text: text.to_string(),
+                                line: number,
+                                char: idx as u64,
+                            });
+                        }
+                        idx = end + 2;
+                        continue;
+                    }
+                    // `[[[` or an unclosed link, rescan from the next bracket
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        idx += 1;
+    }
+}
+
+/// Every `[[...]]` reference in a Markdown body, in document order.
+/// References inside of fenced code blocks and inline code are ignored.
+pub fn extract_links(body: &str) -> Vec<LinkRef> {
+    let mut found = vec![];
+    let mut in_fence = false;
+    for (idx, line) in body.lines().enumerate() {
+        if is_fence(line) {
+            in_fence = !in_fence;
+            continue;
+        }
+        if !in_fence {
+            line_links(line, idx as u64 + 1, &mut found);
+        }
+    }
+    found
+}
+
+/// An inline `#tag` found in a node body
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagRef {
+    /// Normalized tag without the `#`, see [`normalize_tag`]
+    pub tag: String,
+    /// 1 indexed line of the tag
+    pub line: u64,
+    /// 0 indexed byte column of the `#`
+    pub char: u64,
+    /// Length in bytes of the tag as written, `#` included
+    pub len: u64,
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '/'
+}
+
+/// Tags compare lowercased and without the `#` or stray slashes,
+/// `#Project/Codex/` is `project/codex`. None for text that can't be a
+/// tag, like an empty level or `#123` which is an issue number.
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.trim();
+    let tag = tag.strip_prefix('#').unwrap_or(tag).trim_matches('/');
+    let valid = !tag.is_empty()
+        && tag.chars().all(is_tag_char)
+        && tag.split('/').all(|level| !level.is_empty())
+        && !tag.chars().all(|c| c.is_ascii_digit() || c == '/');
+    if valid {
+        Some(tag.to_lowercase())
+    } else {
+        None
+    }
+}
+
+/// `#tag`s on a single line, skipping inline code spans
+fn line_tags(line: &str, number: u64, found: &mut Vec<TagRef>) {
+    let mut in_code = false;
+    let mut previous: Option<char> = None;
+    let mut chars = line.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '`' => in_code = !in_code,
+            // `#` has to start a word, so headings, `[[#Section]]` and url
+            // fragments are not tags
+            '#' if !in_code && previous.is_none_or(|p| p.is_whitespace() || p == '(') => {
+                let start = idx + 1;
+                let end = line[start..]
+                    .find(|c: char| !is_tag_char(c))
+                    .map_or(line.len(), |offset| start + offset);
+                let written = line[start..end].trim_end_matches('/');
+                if let Some(tag) = normalize_tag(written) {
+                    found.push(TagRef {
+                        tag,
+                        line: number,
+                        char: idx as u64,
+                        len: (written.len() + 1) as u64,
+                    });
+                }
+                while chars.peek().is_some_and(|(next, _)| *next < end) {
+                    chars.next();
+                }
+                previous = line[..end].chars().next_back();
+                continue;
+            }
+            _ => {}
+        }
+        previous = Some(c);
+    }
+}
+
+/// Every inline `#tag` in a Markdown body, in document order.
+/// Tags inside of fenced code blocks and inline code are ignored.
+pub fn extract_tags(body: &str) -> Vec<TagRef> {
+    let mut found = vec![];
+    let mut in_fence = false;
+    for (idx, line) in body.lines().enumerate() {
+        if is_fence(line) {
+            in_fence = !in_fence;
+            continue;
+        }
+        if !in_fence {
+            line_tags(line, idx as u64 + 1, &mut found);
+        }
+    }
+    found
+}
+
+/// Plain text in a node body that matches a node name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MentionRef {
+    /// The text as written
+    pub text: String,
+    /// 1 indexed line of the mention
+    pub line: u64,
+    /// 0 indexed byte column where the mention starts
+    pub char: u64,
+}
+
+/// Lowercased words of `text` along with their byte span
+fn words(text: &str) -> Vec<(usize, usize, String)> {
+    let mut found = vec![];
+    let mut start = None;
+    for (idx, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        match (start, c.is_alphanumeric()) {
+            (None, true) => start = Some(idx),
+            (Some(from), false) => {
+                let word: String = text[from..idx].nfkc().collect();
+                found.push((from, idx, word.to_lowercase()));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+/// Byte ranges of a line that mentions can't be in, links and inline code
+fn linked_or_code(line: &str) -> Vec<(usize, usize)> {
+    let mut ranges = vec![];
+    let mut links = vec![];
+    line_links(line, 0, &mut links);
+    for link in links {
+        let start = link.char as usize;
+        let end = line[start..]
+            .find("]]")
+            .map_or(line.len(), |end| start + end + 2);
+        ranges.push((start, end));
+    }
+    let ticks: Vec<usize> = line.match_indices('`').map(|(idx, _)| idx).collect();
+    for pair in ticks.chunks(2) {
+        let end = pair.get(1).map_or(line.len(), |end| end + 1);
+        ranges.push((pair[0], end));
+    }
+    ranges
+}
+
+/// Mentions of any of `names` in a Markdown body, in document order.
+/// Names match whole words ignoring case, with any mix of spaces, `-` or
+/// `_` between their words, so `blue note` also finds `Blue-Note`.
+/// Text that is already a link, a `#tag` or code is skipped, as are
+/// fenced code blocks. Longer names win where names overlap.
+pub fn find_mentions(body: &str, names: &[String]) -> Vec<MentionRef> {
+    let mut names: Vec<Vec<String>> = names
+        .iter()
+        .map(|name| words(name).into_iter().map(|(_, _, word)| word).collect())
+        .filter(|name: &Vec<String>| !name.is_empty())
+        .collect();
+    names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+    let mut found = vec![];
+    let mut in_fence = false;
+    for (number, line) in body.lines().enumerate() {
+        if is_fence(line) {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        let skipped = linked_or_code(line);
+        let words: Vec<(usize, usize, String)> = words(line)
+            .into_iter()
+            .filter(|(start, _, _)| {
+                !line[..*start].ends_with('#')
+                    && !skipped.iter().any(|(from, to)| from <= start && start < to)
+            })
+            .collect();
+        let joined = |from: usize, to: usize| {
+            line[from..to]
+                .chars()
+                .all(|c| c.is_whitespace() || c == '-' || c == '_')
+        };
+        let mut idx = 0;
+        while idx < words.len() {
+            let matched = names.iter().find(|name| {
+                let candidate = match words.get(idx..idx + name.len()) {
+                    Some(candidate) => candidate,
+                    None => return false,
+                };
+                candidate
+                    .iter()
+                    .zip(name.iter())
+                    .all(|(word, part)| word.2 == *part)
+                    && candidate
+                        .windows(2)
+                        .all(|pair| joined(pair[0].1, pair[1].0))
+            });
+            match matched {
+                Some(name) => {
+                    let (start, end) = (words[idx].0, words[idx + name.len() - 1].1);
+                    found.push(MentionRef {
+                        text: line[start..end].to_string(),
+                        line: number as u64 + 1,
+                        char: start as u64,
+                    });
+                    idx += name.len();
+                }
+                None => idx += 1,
+            }
+        }
+    }
+    found
+}
+
+/// Where inside of the target node a link lands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkAnchor {
+    /// `[[note#Section]]`
+    Heading(String),
+    /// `[[note^abc123]]`
+    Block(String),
+}
+
+/// Split link text into the node reference and an optional anchor,
+/// `note#Section` is `("note", Heading("Section"))`. The anchor starts at
+/// the last marker so `C# # Intro` links to a heading of `C#`, and a marker
+/// with nothing after it is part of the name.
+pub fn split_link_text(text: &str) -> (&str, Option<LinkAnchor>) {
+    match text
+        .rfind(['#', '^'])
+        .filter(|idx| !text[idx + 1..].trim().is_empty())
+    {
+        Some(idx) => {
+            let (note, anchor) = text.split_at(idx);
+            let id = anchor[1..].trim().to_string();
+            let anchor = if anchor.starts_with('#') {
+                LinkAnchor::Heading(id)
+            } else {
+                LinkAnchor::Block(id)
+            };
+            (note.trim(), Some(anchor))
+        }
+        None => (text.trim(), None),
+    }
+}
+
+/// Headings compare ignoring case and with `-` standing in for spaces
+fn heading_slug(text: &str) -> String {
+    text.split(|c: char| c.is_whitespace() || c == '-')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Text of an ATX heading line, `## Section ##` is `Section`
+fn heading_text(line: &str) -> Option<&str> {
+    let trimmed = line.trim_start();
+    let hashes = trimmed.chars().take_while(|c| *c == '#').count();
+    if hashes == 0 || hashes > 6 {
+        return None;
+    }
+    let rest = &trimmed[hashes..];
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(rest.trim().trim_end_matches('#').trim_end())
+}
+
+/// Block id at the end of a line, `some text ^abc123` is `abc123`
+fn block_id(line: &str) -> Option<&str> {
+    let (before, id) = line.trim_end().rsplit_once('^')?;
+    let valid = !id.is_empty() && id.chars().all(|c| c.is_alphanumeric() || c == '-');
+    if valid && (before.is_empty() || before.ends_with(char::is_whitespace)) {
+        Some(id)
+    } else {
+        None
+    }
+}
+
+/// Find an anchor in a Markdown body, returning its 1 indexed line and
+/// 0 indexed byte column. Fenced code blocks are skipped.
+pub fn find_anchor(body: &str, anchor: &LinkAnchor) -> Option<(u64, u64)> {
+    let mut in_fence = false;
+    for (idx, line) in body.lines().enumerate() {
+        if is_fence(line) {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        let found = match anchor {
+            LinkAnchor::Heading(heading) => {
+                heading_text(line).is_some_and(|text| heading_slug(text) == heading_slug(heading))
+            }
+            LinkAnchor::Block(id) => block_id(line) == Some(id.as_str()),
+        };
+        if found {
+            let char = line.len() - line.trim_start().len();
+            return Some((idx as u64 + 1, char as u64));
+        }
+    }
+    None
+}
+
+#[test]
+fn test_extract_links() {
+    let body = "# a\nsee [[jazz]] and [[ blue note ]]\n`[[code]]` [[]] [[x\n```\n[[fenced]]\n```\n[[[nested]]";
+    let links = extract_links(body);
+    assert_eq!(
+        links,
+        vec![
+            LinkRef {
+                text: "jazz".to_string(),
+                line: 2,
+                char: 4,
+            },
+            LinkRef {
+                text: "blue note".to_string(),
+                line: 2,
+                char: 17,
+            },
+            LinkRef {
+                text: "nested".to_string(),
+                line: 7,
+                char: 1,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_find_anchor() {
+    assert_eq!(split_link_text("jazz"), ("jazz", None));
+    assert_eq!(
+        split_link_text("jazz # Giant Steps"),
+        ("jazz", Some(LinkAnchor::Heading("Giant Steps".to_string())))
+    );
+    assert_eq!(
+        split_link_text("^abc-1"),
+        ("", Some(LinkAnchor::Block("abc-1".to_string())))
+    );
+    assert_eq!(split_link_text("C#"), ("C#", None));
+    assert_eq!(split_link_text("C# ^"), ("C# ^", None));
+    assert_eq!(
+        split_link_text("C# # Intro"),
+        ("C#", Some(LinkAnchor::Heading("Intro".to_string())))
+    );
+    let body = "# jazz\n\n```\n## Giant Steps\n```\n## Giant  Steps ##\ncoltrane ^abc-1\nx^nope\n";
+    let heading = |h: &str| LinkAnchor::Heading(h.to_string());
+    let block = |b: &str| LinkAnchor::Block(b.to_string());
+    assert_eq!(find_anchor(body, &heading("giant-steps")), Some((6, 0)));
+    assert_eq!(find_anchor(body, &heading("Jazz")), Some((1, 0)));
+    assert_eq!(find_anchor(body, &heading("Blue Train")), None);
+    assert_eq!(find_anchor(body, &block("abc-1")), Some((7, 0)));
+    assert_eq!(find_anchor(body, &block("nope")), None);
+}
+
+#[test]
+fn test_extract_tags() {
+    let body = "# heading #Jazz\n#project/Codex/ and (#todo) x#no `#code` #123 #\n```\n#fenced\n```\n[[#Section]] http://x.io/#frag #a-b_c";
+    let tags: Vec<(String, u64, u64, u64)> = extract_tags(body)
+        .into_iter()
+        .map(|t| (t.tag, t.line, t.char, t.len))
+        .collect();
+    let tag = |t: &str, line, char, len| (t.to_string(), line, char, len);
+    assert_eq!(
+        tags,
+        vec![
+            tag("jazz", 1, 10, 5),
+            tag("project/codex", 2, 0, 14),
+            tag("todo", 2, 21, 5),
+            tag("a-b_c", 6, 31, 6),
+        ]
+    );
+    assert_eq!(normalize_tag(" #Project//x"), None);
+    assert_eq!(normalize_tag("2024/10"), None);
+    assert_eq!(normalize_tag("#q1/2024"), Some("q1/2024".to_string()));
+}
+
+#[test]
+fn test_find_mentions() {
+    let names = vec!["Blue Note".to_string(), "blue".to_string(), "C".to_string()];
+    let body = "# a\nthe blue-note club, BLUE NOTE\n[[blue note]] `blue` #blue bluesy\n```\nblue\n```\nit's blue, c.\n";
+    let found: Vec<(String, u64, u64)> = find_mentions(body, &names)
+        .into_iter()
+        .map(|m| (m.text, m.line, m.char))
+        .collect();
+    let mention = |text: &str, line, char| (text.to_string(), line, char);
+    assert_eq!(
+        found,
+        vec![
+            mention("blue-note", 2, 4),
+            mention("BLUE NOTE", 2, 20),
+            mention("blue", 7, 5),
+            mention("c", 7, 11),
+        ]
+    );
+}
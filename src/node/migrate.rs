@@ -0,0 +1,88 @@
+use super::{codex_date_format, NodeLink, NodeMeta, META_VERSION};
+use chrono::{DateTime, Local};
+use log::*;
+use serde::Deserialize;
+use std::path::Path;
+
+/// `meta.toml` as written before the schema was versioned
+#[derive(Debug, Deserialize)]
+struct NodeMetaV1 {
+    name: String,
+    tags: Vec<String>,
+    links: Vec<String>,
+    backlinks: Vec<String>,
+    #[serde(with = "codex_date_format")]
+    created: DateTime<Local>,
+    #[serde(with = "codex_date_format")]
+    updated: DateTime<Local>,
+    updates: u64,
+    internal: Vec<String>,
+}
+
+/// Parse a version 1 `text|,|timestamp|,|node|,|line|,|char|,|variant` link.
+/// Splitting from the right keeps any `|,|` inside of the link text intact.
+pub fn parse_v1_link(encoded: &str) -> Option<NodeLink> {
+    let mut fields = encoded.rsplitn(6, "|,|");
+    let variant = fields.next()?;
+    let char = fields.next()?.parse::<u64>().ok()?;
+    let line = fields.next()?.parse::<u64>().ok()?;
+    let node = fields.next()?.to_string();
+    let timestamp = fields.next()?.parse::<i64>().ok()?;
+    let text = fields.next()?.to_string();
+    Some(NodeLink {
+        node,
+        text,
+        timestamp,
+        line,
+        char,
+        is_name_linked: variant == "name_ref",
+    })
+}
+
+fn parse_v1_links(encoded: Vec<String>, toml_path: &Path) -> Vec<NodeLink> {
+    encoded
+        .into_iter()
+        .filter_map(|s| {
+            let link = parse_v1_link(&s);
+            if link.is_none() {
+                error!("dropping malformed link {:?} in {}", s, toml_path.display());
+            }
+            link
+        })
+        .collect()
+}
+
+/// Bring an older `meta.toml` up to `META_VERSION`
+pub fn migrate(value: toml::Value, toml_path: &Path) -> Result<NodeMeta, toml::de::Error> {
+    let v1: NodeMetaV1 = value.try_into()?;
+    Ok(NodeMeta {
+        version: META_VERSION,
+        name: v1.name,
+        tags: v1.tags,
+        created: v1.created,
+        updated: v1.updated,
+        updates: v1.updates,
+        internal: v1.internal,
+        aliases: vec![],
+        template: None,
+        links: parse_v1_links(v1.links, toml_path),
+        backlinks: parse_v1_links(v1.backlinks, toml_path),
+    })
+}
+
+#[test]
+fn test_parse_v1_link() {
+    let link = parse_v1_link("a |,| b|,|1650000000|,|2-desk/1-a|,|4|,|2|,|text_ref").unwrap();
+    assert_eq!(link.text, "a |,| b");
+    assert_eq!(link.timestamp, 1650000000);
+    assert_eq!(link.node, "2-desk/1-a");
+    assert_eq!((link.line, link.char), (4, 2));
+    assert!(!link.is_name_linked);
+    assert!(
+        parse_v1_link("a|,|1|,|2-desk|,|0|,|0|,|name_ref")
+            .unwrap()
+            .is_name_linked
+    );
+    assert!(parse_v1_link("a|,|soon|,|2-desk|,|0|,|0|,|name_ref").is_none());
+    assert!(parse_v1_link("garbage").is_none());
+}
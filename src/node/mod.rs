@@ -1,15 +1,20 @@
+use crate::error::{Error, NodeFilesMissing};
+use crate::git::stage_move;
 use crate::nvim::Telescoped;
-use crate::tree::next_sibling_id;
+use crate::tree::{get_parent, next_sibling_id};
 use chrono::{DateTime, Local};
 use log::*;
 use nvim_rs::Value;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
-use std::fs::{create_dir, read_to_string, File, OpenOptions};
+use std::fs::{create_dir, read_to_string, rename, File, OpenOptions};
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 mod date_serde;
-use date_serde::codex_date_format;
+pub mod markdown;
+pub mod tasks;
+use date_serde::{codex_date_format, link_variant_format};
+mod migrate;
 mod utils;
 
 use git2::Repository;
@@ -27,6 +32,10 @@ pub struct Node {
     pub links: HashMap<String, NodeLink>,
     pub backlinks: HashMap<(String, i64), NodeLink>,
     pub tags: HashSet<String>,
+    /// Other names the node goes by in `[[...]]` links
+    pub aliases: Vec<String>,
+    /// Template given to new children, see `templates/`
+    pub template: Option<String>,
     pub internal: HashSet<String>,
     pub created: DateTime<Local>,
     pub updated: DateTime<Local>,
@@ -50,6 +59,7 @@ impl fmt::Display for Node {
         writeln!(f, "\t links: {:?}", self.links)?;
         writeln!(f, "\t backlinks: {:?}", self.backlinks)?;
         writeln!(f, "\t tags: {:?}", self.tags)?;
+        writeln!(f, "\t aliases: {:?}", self.aliases)?;
         writeln!(f, "}}")?;
         Ok(())
     }
@@ -80,6 +90,8 @@ impl Node {
             links: HashMap::new(),
             backlinks: HashMap::new(),
             tags: HashSet::new(),
+            aliases: vec![],
+            template: None,
             internal: HashSet::new(),
             created: now,
             updated: now,
@@ -126,10 +138,27 @@ impl Node {
         parent: Option<NodeKey>,
         children: Vec<NodeKey>,
         directory: &str,
-    ) -> Node {
+    ) -> crate::tree::Result<Node> {
         // let (name, tags, links, backlinks, created, updated, updates) =
         //     NodeMeta::from_toml(toml_path).data();
-        let metadata = NodeMeta::from_toml(toml_path);
+        if !toml_path.is_file() {
+            return Err(Error::NodeFilesMissing(NodeFilesMissing {
+                content_file_exists: toml_path.with_file_name("_.md").is_file(),
+                metadata_file_exists: false,
+                node: id,
+            }));
+        }
+        let metadata = NodeMeta::from_toml(toml_path)?;
+        Ok(Node::from_meta(id, metadata, parent, children, directory))
+    }
+    /// A node of a tree from its already parsed `meta.toml`
+    pub fn from_meta(
+        id: NodeKey,
+        metadata: NodeMeta,
+        parent: Option<NodeKey>,
+        children: Vec<NodeKey>,
+        directory: &str,
+    ) -> Node {
         Node {
             display_name: format_display_name(&id),
             id,
@@ -139,14 +168,16 @@ impl Node {
             links: metadata
                 .links
                 .into_iter()
-                .map(|s| NodeLink::parse_link_w_key(s))
+                .map(NodeLink::with_link_key)
                 .collect(),
             backlinks: metadata
                 .backlinks
                 .into_iter()
-                .map(|s| NodeLink::parse_backlink_w_key(s))
+                .map(NodeLink::with_backlink_key)
                 .collect(),
             tags: metadata.tags.into_iter().collect(),
+            aliases: metadata.aliases,
+            template: metadata.template,
             internal: metadata.internal.into_iter().collect(),
             created: metadata.created,
             updated: metadata.updated,
@@ -168,7 +199,7 @@ impl Node {
         self.write_meta();
         child
     }
-    fn tag(&mut self, new_tag: String) {
+    pub(crate) fn tag(&mut self, new_tag: String) {
         self.tags.insert(new_tag);
         self.tick_update_and_write_meta();
     }
@@ -181,19 +212,30 @@ impl Node {
             .insert((backlink.text.clone(), backlink.timestamp), backlink);
         self.tick_update_and_write_meta();
     }
-    pub fn get_link(&self, link_id: &str) -> (NodeKey, u64) {
-        let link = self.links.get(link_id).unwrap();
-        (link.node.clone(), link.line)
-    }
-    pub fn rerank(&mut self, _rank: u64) {
-        todo!();
+    /// Renumber this node within its siblings, zero padded to `width`
+    pub fn rerank(&mut self, rank: u64, width: usize) -> crate::tree::Result<NodeKey> {
+        let new_id = ranked_key(self.parent.as_deref(), rank, width, &self.id);
+        if new_id != self.id {
+            self.mv(new_id.clone())?;
+        }
+        Ok(new_id)
     }
-    pub fn mv(&mut self, new_path: NodeKey) {
-        // should probably return a result
-        // primitive fn for moving across fs
-        // should be a git move
+    /// Move the node directory (and so its whole subtree) to a new key.
+    /// The move is staged in git so history follows the files.
+    /// Only the node itself is updated, keys of descendants and
+    /// links are the tree's job.
+    pub fn mv(&mut self, new_path: NodeKey) -> crate::tree::Result<()> {
+        let old_path = self.directory.join(&self.id);
+        let new_dir = self.directory.join(&new_path);
+        debug!("moving {:?} to {:?}", old_path, new_dir);
+        rename(&old_path, &new_dir)?;
+        match Repository::open(&self.directory) {
+            Ok(repo) => stage_move(&repo, Path::new(&self.id), Path::new(&new_path))?,
+            Err(e) => warn!("{:?} moved outside of a git repo: {}", new_dir, e),
+        }
+        self.parent = get_parent(&new_path);
         self.id = new_path;
-        todo!();
+        Ok(())
     }
     pub fn rename_link(&mut self, id: &str, new_name: &str) {
         if let Some(link) = self.links.get_mut(id) {
@@ -210,6 +252,9 @@ impl Node {
     pub fn metadata_path(&self) -> PathBuf {
         self.directory.join(&self.id).join("meta.toml")
     }
+    pub fn content_path(&self) -> PathBuf {
+        self.directory.join(&self.id).join("_.md")
+    }
 
     pub fn write_meta(&self) {
         let metadata = self.metadata_path();
@@ -256,6 +301,22 @@ impl Telescoped for Node {
 
 pub type NodeKey = String;
 
+/// Key for a node at `rank` under `parent`, keeping the name of `key`
+pub fn ranked_key(parent: Option<&str>, rank: u64, width: usize, key: &str) -> NodeKey {
+    let tail = match key.rsplit_once('/') {
+        Some((_, tail)) => tail,
+        None => key,
+    };
+    let name = match tail.split_once('-') {
+        Some((_, name)) => name,
+        None => tail,
+    };
+    match parent {
+        Some(parent) => format!("{}/{:0width$}-{}", parent, rank, name, width = width),
+        None => format!("{:0width$}-{}", rank, name, width = width),
+    }
+}
+
 impl Telescoped for NodeKey {
     fn entry(&self) -> Value {
         Value::Map(vec![
@@ -278,13 +339,14 @@ impl Telescoped for NodeKey {
 // }
 // use LinkType::*;
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
 pub struct NodeLink {
     pub node: NodeKey,
     pub text: String,
     pub timestamp: i64,
     pub line: u64,
     pub char: u64,
+    #[serde(rename = "variant", with = "link_variant_format")]
     pub is_name_linked: bool,
 }
 
@@ -330,66 +392,50 @@ impl NodeLink {
             },
         )
     }
+    /// A single link rendered as a TOML table
     pub fn to_toml(&self) -> String {
-        // there should be some kind of string escaping here...
-        // to check that the link text doesn't have `|,|` in it
-
-        let link_varient = if self.is_name_linked {
-            "name_ref"
-        } else {
-            "text_ref"
-        };
-        format!(
-            "{}|,|{}|,|{}|,|{}|,|{}|,|{link_varient}",
-            self.text, self.timestamp, self.node, self.line, self.char
-        )
-    }
-    fn from_toml(toml: String) -> NodeLink {
-        let (text, link) = toml.split_once("|,|").unwrap();
-        let (timestamp, link) = link.split_once("|,|").unwrap();
-        let (node, link) = link.split_once("|,|").unwrap();
-        let (line, link) = link.split_once("|,|").unwrap();
-        let (char, link_varient) = link.split_once("|,|").unwrap();
-        NodeLink {
-            node: node.to_string(),
-            text: text.to_string(),
-            timestamp: timestamp.parse::<i64>().unwrap(),
-            line: line.parse::<u64>().unwrap(),
-            char: char.parse::<u64>().unwrap(),
-            is_name_linked: match link_varient {
-                "name_ref" => true,
-                _ => false,
-            },
-        }
+        toml::to_string(self).unwrap()
     }
-    pub fn parse_link_w_key(toml: String) -> (String, NodeLink) {
-        let link = Self::from_toml(toml);
-        (link.text.clone(), link)
+    pub fn with_link_key(self) -> (String, NodeLink) {
+        (self.text.clone(), self)
     }
-    pub fn parse_backlink_w_key(toml: String) -> ((String, i64), NodeLink) {
-        let link = Self::from_toml(toml);
-        ((link.text.clone(), link.timestamp), link)
+    pub fn with_backlink_key(self) -> ((String, i64), NodeLink) {
+        ((self.text.clone(), self.timestamp), self)
     }
 }
 
+/// Version of the `meta.toml` schema written by this build.
+/// Version 1 files have no `version` key and store links as `|,|` strings.
+pub const META_VERSION: u32 = 2;
+
+// links and backlinks are arrays of tables so they must come last,
+// and are left out when empty so no plain value follows a table
 #[derive(Debug, Deserialize, Serialize)]
 pub struct NodeMeta {
+    pub version: u32,
     pub name: String,
     pub tags: Vec<String>,
-    pub links: Vec<String>,
-    pub backlinks: Vec<String>,
     #[serde(with = "codex_date_format")]
     pub created: DateTime<Local>,
     #[serde(with = "codex_date_format")]
     pub updated: DateTime<Local>,
     pub updates: u64,
     pub internal: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<NodeLink>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backlinks: Vec<NodeLink>,
 }
 
 impl NodeMeta {
     pub fn new(name: String) -> NodeMeta {
         let now = Local::now();
         NodeMeta {
+            version: META_VERSION,
             name,
             tags: vec![],
             links: vec![],
@@ -398,6 +444,8 @@ impl NodeMeta {
             updated: now,
             updates: 1,
             internal: vec![],
+            aliases: vec![],
+            template: None,
         }
     }
     pub fn from(node: &Node) -> NodeMeta {
@@ -405,20 +453,53 @@ impl NodeMeta {
         tags.sort_unstable();
         let mut internal: Vec<String> = node.internal.clone().into_iter().collect();
         internal.sort_unstable();
+        let mut links: Vec<NodeLink> = node.links.values().cloned().collect();
+        links.sort_unstable();
+        let mut backlinks: Vec<NodeLink> = node.backlinks.values().cloned().collect();
+        backlinks.sort_unstable();
         NodeMeta {
+            version: META_VERSION,
             name: node.name.clone(),
             tags,
-            links: node.links.values().map(|link| link.to_toml()).collect(),
-            backlinks: node.backlinks.values().map(|link| link.to_toml()).collect(),
+            links,
+            backlinks,
             created: node.created,
             updated: node.updated,
             updates: node.updates,
             internal,
+            aliases: node.aliases.clone(),
+            template: node.template.clone(),
         }
     }
-    pub fn from_toml(toml_path: &Path) -> NodeMeta {
-        let toml_string = read_to_string(toml_path).unwrap();
-        toml::from_str(&toml_string).unwrap()
+    /// Parse `meta.toml` contents of any schema version,
+    /// returning whether it was migrated from an older version
+    pub fn parse(toml_string: &str, toml_path: &Path) -> crate::tree::Result<(NodeMeta, bool)> {
+        let value: toml::Value = toml::from_str(toml_string)?;
+        let version = value
+            .get("version")
+            .and_then(|v| v.as_integer())
+            .unwrap_or(1);
+        if version < META_VERSION as i64 {
+            Ok((migrate::migrate(value, toml_path)?, true))
+        } else {
+            Ok((value.try_into()?, false))
+        }
+    }
+    /// Read a `meta.toml`, migrating and rewriting it if it uses an older schema
+    pub fn from_toml(toml_path: &Path) -> crate::tree::Result<NodeMeta> {
+        let toml_string = read_to_string(toml_path)?;
+        let (meta, migrated) = NodeMeta::parse(&toml_string, toml_path)?;
+        if migrated {
+            match std::fs::write(toml_path, meta.to_toml()) {
+                Err(why) => error!("couldn't write migrated {}: {}", toml_path.display(), why),
+                Ok(_) => debug!(
+                    "migrated {} to version {}",
+                    toml_path.display(),
+                    META_VERSION
+                ),
+            }
+        }
+        Ok(meta)
     }
     pub fn to_toml(&self) -> String {
         toml::to_string_pretty(self).unwrap()
@@ -431,7 +512,6 @@ pub fn init_codex_repo(path: Option<&str>) -> Repository {
     let mut journal = Node::create("journal".to_string(), None, path);
     journal.tag(String::from("journal"));
     journal.write_meta();
-    dbg!(journal);
     let mut desk = Node::create("desk".to_string(), None, path);
     desk.tag(String::from("desk"));
     desk.write_meta();
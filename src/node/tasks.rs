@@ -0,0 +1,407 @@
+use super::{format_display_name, NodeKey};
+use crate::nvim::Telescoped;
+use chrono::NaiveDate;
+use nvim_rs::Value;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+
+/// Marker written over a task that was carried forward to a later day
+pub const MIGRATED_MARKER: &str = "[>]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum TaskState {
+    /// `- [] ` (or `- [ ] `), the Lua `todo()` writes the former
+    Open,
+    /// `- ✅ ` (or `- [x] `)
+    Done,
+    /// `- [>] `, carried forward to a later journal day
+    Migrated,
+}
+
+impl TaskState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskState::Open => "open",
+            TaskState::Done => "done",
+            TaskState::Migrated => "migrated",
+        }
+    }
+    pub fn parse(state: &str) -> Option<TaskState> {
+        match state {
+            "open" => Some(TaskState::Open),
+            "done" => Some(TaskState::Done),
+            "migrated" => Some(TaskState::Migrated),
+            _ => None,
+        }
+    }
+}
+
+/// A bullet in a Markdown list, `state` is None for plain bullets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListItem {
+    /// 0 indexed line in the body
+    pub line: usize,
+    /// Width of the leading whitespace, tabs count as 4
+    pub indent: usize,
+    /// Leading whitespace and bullet up to the task marker, `  - `
+    pub prefix: String,
+    pub state: Option<TaskState>,
+    pub text: String,
+    /// Index of the enclosing bullet in the parsed items
+    pub parent: Option<usize>,
+}
+
+impl ListItem {
+    pub fn is_open(&self) -> bool {
+        self.state == Some(TaskState::Open)
+    }
+    /// The item as a plain bullet, used for done parents of carried tasks
+    fn plain(&self) -> String {
+        format!("{}{}", self.prefix, self.text)
+    }
+}
+
+fn task_state(rest: &str) -> (Option<TaskState>, &str) {
+    let markers = [
+        ("[] ", TaskState::Open),
+        ("[ ] ", TaskState::Open),
+        ("✅ ", TaskState::Done),
+        ("[x] ", TaskState::Done),
+        ("[X] ", TaskState::Done),
+        ("[>] ", TaskState::Migrated),
+    ];
+    for (marker, state) in markers.iter() {
+        if let Some(text) = rest.strip_prefix(marker) {
+            return (Some(*state), text);
+        }
+    }
+    (None, rest)
+}
+
+fn list_item(line: &str) -> Option<(usize, String, Option<TaskState>, String)> {
+    let content = line.trim_start();
+    let whitespace = &line[..line.len() - content.len()];
+    let mut chars = content.chars();
+    let bullet = chars.next()?;
+    if !matches!(bullet, '-' | '*' | '+') || chars.next() != Some(' ') {
+        return None;
+    }
+    let indent = whitespace
+        .chars()
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum();
+    let prefix = format!("{}{} ", whitespace, bullet);
+    let (state, text) = task_state(&content[2..]);
+    Some((indent, prefix, state, text.trim_end().to_string()))
+}
+
+/// Every list item in a body with the bullet it is nested under.
+/// A line that is not indented and not a bullet (a heading or a
+/// paragraph) ends the current list.
+pub fn parse_list(body: &str) -> Vec<ListItem> {
+    let mut items: Vec<ListItem> = vec![];
+    // indexes of the open bullets, outermost first
+    let mut stack: Vec<usize> = vec![];
+    for (line, text) in body.lines().enumerate() {
+        match list_item(text) {
+            Some((indent, prefix, state, text)) => {
+                while stack.last().is_some_and(|idx| items[*idx].indent >= indent) {
+                    stack.pop();
+                }
+                items.push(ListItem {
+                    line,
+                    indent,
+                    prefix,
+                    state,
+                    text,
+                    parent: stack.last().copied(),
+                });
+                stack.push(items.len() - 1);
+            }
+            None => {
+                if !text.trim().is_empty() && !text.starts_with(char::is_whitespace) {
+                    stack.clear();
+                }
+            }
+        }
+    }
+    items
+}
+
+/// The nearest task an item is nested under
+fn task_ancestor(items: &[ListItem], idx: usize) -> Option<usize> {
+    let mut parent = items[idx].parent;
+    while let Some(p) = parent {
+        if items[p].state.is_some() {
+            return Some(p);
+        }
+        parent = items[p].parent;
+    }
+    None
+}
+
+/// Carry the open tasks of a `prior` day into `current`.
+///
+/// Open tasks come along with the bullets they are nested under (done
+/// parents become plain bullets) and the plain bullets nested under them.
+/// The originals are marked `[>]` so they are not carried twice, and
+/// tasks already open in `current` are not duplicated.
+/// Returns the updated prior and current bodies.
+pub fn migrate_tasks(prior: &str, current: &str) -> (String, String) {
+    let items = parse_list(prior);
+    let existing: HashSet<String> = parse_list(current)
+        .into_iter()
+        .filter(|item| item.is_open())
+        .map(|item| item.text.trim().to_string())
+        .collect();
+    let open: Vec<usize> = (0..items.len())
+        .filter(|idx| items[*idx].is_open())
+        .collect();
+    let carried_tasks: BTreeSet<usize> = open
+        .iter()
+        .copied()
+        .filter(|idx| !existing.contains(items[*idx].text.trim()))
+        .collect();
+    let mut carried = carried_tasks.clone();
+    for idx in &carried_tasks {
+        let mut parent = items[*idx].parent;
+        while let Some(p) = parent {
+            carried.insert(p);
+            parent = items[p].parent;
+        }
+    }
+    for (idx, item) in items.iter().enumerate() {
+        let under_carried = task_ancestor(&items, idx).is_some_and(|t| carried_tasks.contains(&t));
+        if item.state.is_none() && under_carried {
+            carried.insert(idx);
+        }
+    }
+
+    let mut lines: Vec<String> = prior.lines().map(String::from).collect();
+    for idx in &open {
+        let item = &items[*idx];
+        lines[item.line] = format!("{}{} {}", item.prefix, MIGRATED_MARKER, item.text);
+    }
+    let mut prior_body = lines.join("\n");
+    if prior.ends_with('\n') {
+        prior_body.push('\n');
+    }
+    if carried_tasks.is_empty() {
+        return (prior_body, current.to_string());
+    }
+
+    let block: Vec<String> = carried
+        .iter()
+        .map(|idx| {
+            let item = &items[*idx];
+            if item.is_open() || item.state.is_none() {
+                prior.lines().nth(item.line).unwrap().trim_end().to_string()
+            } else {
+                item.plain()
+            }
+        })
+        .collect();
+    let mut current_body = current.trim_end().to_string();
+    current_body.push_str("\n\n");
+    current_body.push_str(&block.join("\n"));
+    current_body.push('\n');
+    (prior_body, current_body)
+}
+
+/// A task found in a node body
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Task {
+    pub node: NodeKey,
+    /// 1 indexed line of the task
+    pub line: u64,
+    pub state: TaskState,
+    pub text: String,
+    /// `@due(2026-10-20)`
+    pub due: Option<NaiveDate>,
+    /// `@priority(1)` through `@priority(3)`, or high, medium and low
+    pub priority: Option<u8>,
+}
+
+impl Telescoped for Task {
+    fn entry(&self) -> Value {
+        let mut entry = vec![
+            (
+                Value::String("id".into()),
+                Value::String(self.node.clone().into()),
+            ),
+            (
+                Value::String("display".into()),
+                Value::String(format_display_name(&self.node).into()),
+            ),
+            (Value::String("line".into()), Value::from(self.line)),
+            (
+                Value::String("state".into()),
+                Value::String(self.state.as_str().into()),
+            ),
+            (
+                Value::String("text".into()),
+                Value::String(self.text.clone().into()),
+            ),
+        ];
+        if let Some(due) = self.due {
+            entry.push((
+                Value::String("due".into()),
+                Value::String(due.format("%Y-%m-%d").to_string().into()),
+            ));
+        }
+        if let Some(priority) = self.priority {
+            entry.push((Value::String("priority".into()), Value::from(priority)));
+        }
+        Value::Map(entry)
+    }
+}
+
+/// Values of `@name(value)` annotations in task text
+fn annotation<'a>(text: &'a str, name: &str) -> Option<&'a str> {
+    let start = text.find(&format!("@{}(", name))? + name.len() + 2;
+    let len = text[start..].find(')')?;
+    Some(text[start..start + len].trim())
+}
+
+fn parse_priority(priority: &str) -> Option<u8> {
+    match priority.to_lowercase().as_str() {
+        "1" | "high" => Some(1),
+        "2" | "medium" => Some(2),
+        "3" | "low" => Some(3),
+        _ => None,
+    }
+}
+
+/// Every task in the body of `node`
+pub fn parse_tasks(node: &str, body: &str) -> Vec<Task> {
+    parse_list(body)
+        .into_iter()
+        .filter_map(|item| {
+            let state = item.state?;
+            Some(Task {
+                node: node.to_string(),
+                line: item.line as u64 + 1,
+                state,
+                due: annotation(&item.text, "due")
+                    .and_then(|due| NaiveDate::parse_from_str(due, "%Y-%m-%d").ok()),
+                priority: annotation(&item.text, "priority").and_then(parse_priority),
+                text: item.text,
+            })
+        })
+        .collect()
+}
+
+/// Which tasks to list, every set field must match
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaskFilter {
+    pub state: Option<TaskState>,
+    /// Only tasks within this node or its descendants
+    pub node: Option<NodeKey>,
+    /// Only tasks due on or before this date
+    pub due_before: Option<NaiveDate>,
+    /// Only tasks with a due date
+    pub has_due: bool,
+    /// Only tasks of at least this priority (1 is the highest)
+    pub priority: Option<u8>,
+    /// Case insensitive substring of the task text
+    pub text: Option<String>,
+}
+
+impl TaskFilter {
+    pub fn matches(&self, task: &Task) -> bool {
+        let in_node =
+            |node: &NodeKey| task.node == *node || task.node.starts_with(&format!("{}/", node));
+        self.state.is_none_or(|state| task.state == state)
+            && self.node.as_ref().is_none_or(in_node)
+            && (!self.has_due || task.due.is_some())
+            && self
+                .due_before
+                .is_none_or(|before| task.due.is_some_and(|due| due <= before))
+            && self
+                .priority
+                .is_none_or(|priority| task.priority.is_some_and(|p| p <= priority))
+            && self
+                .text
+                .as_ref()
+                .is_none_or(|text| task.text.to_lowercase().contains(&text.to_lowercase()))
+    }
+}
+
+/// Soonest due first, then by priority, tasks without either go last
+pub fn sort_tasks(tasks: &mut [Task]) {
+    tasks.sort_by(|a, b| {
+        let key = |t: &Task| (t.due.is_none(), t.due, t.priority.is_none(), t.priority);
+        key(a)
+            .cmp(&key(b))
+            .then_with(|| a.node.cmp(&b.node))
+            .then_with(|| a.line.cmp(&b.line))
+    });
+}
+
+#[test]
+fn test_parse_list() {
+    let items = parse_list("# day\n- [] a\n  - note\n\t- ✅ b\n- [>] c\npara\n  - [x] d\n");
+    let summary: Vec<(usize, Option<TaskState>, &str, Option<usize>)> = items
+        .iter()
+        .map(|i| (i.line, i.state, i.text.as_str(), i.parent))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (1, Some(TaskState::Open), "a", None),
+            (2, None, "note", Some(0)),
+            (3, Some(TaskState::Done), "b", Some(1)),
+            (4, Some(TaskState::Migrated), "c", None),
+            (6, Some(TaskState::Done), "d", None),
+        ]
+    );
+}
+
+#[test]
+fn test_migrate_tasks() {
+    let prior = "# mon\n- ✅ project\n  - [] draft\n    - outline first\n  - ✅ call\n- [] groceries\n- plain\n";
+    let (prior, current) = migrate_tasks(prior, "# tue\n- [] groceries\n");
+    assert_eq!(
+        prior,
+        "# mon\n- ✅ project\n  - [>] draft\n    - outline first\n  - ✅ call\n- [>] groceries\n- plain\n"
+    );
+    assert_eq!(
+        current,
+        "# tue\n- [] groceries\n\n- project\n  - [] draft\n    - outline first\n"
+    );
+    // nothing is left to carry the second time around
+    let (again, unchanged) = migrate_tasks(&prior, &current);
+    assert_eq!(again, prior);
+    assert_eq!(unchanged, current);
+}
+
+#[test]
+fn test_parse_tasks() {
+    let body = "# a\n- [] pay rent @due(2026-10-20) @priority(high)\n- ✅ call @priority(2)\n- [] someday @due(soon)\n";
+    let tasks = parse_tasks("2-desk/1-a", body);
+    assert_eq!(tasks.len(), 3);
+    assert_eq!(tasks[0].line, 2);
+    assert_eq!(tasks[0].due, NaiveDate::from_ymd_opt(2026, 10, 20));
+    assert_eq!(tasks[0].priority, Some(1));
+    assert_eq!(
+        (tasks[1].state, tasks[1].priority),
+        (TaskState::Done, Some(2))
+    );
+    assert_eq!(tasks[2].due, None);
+    let filter = TaskFilter {
+        state: Some(TaskState::Open),
+        node: Some("2-desk".to_string()),
+        ..TaskFilter::default()
+    };
+    assert_eq!(tasks.iter().filter(|t| filter.matches(t)).count(), 2);
+    let filter = TaskFilter {
+        due_before: NaiveDate::from_ymd_opt(2026, 10, 19),
+        ..TaskFilter::default()
+    };
+    assert!(!tasks.iter().any(|t| filter.matches(t)));
+    let filter = TaskFilter {
+        node: Some("2-des".to_string()),
+        ..TaskFilter::default()
+    };
+    assert!(!tasks.iter().any(|t| filter.matches(t)));
+}
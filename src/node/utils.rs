@@ -1,3 +1,5 @@
+use unicode_normalization::UnicodeNormalization;
+
 pub fn power_of_ten(mut n: u64) -> Option<u64> {
     let mut pow = 1;
     let mut r = 0;
@@ -21,6 +23,18 @@ pub fn format_display_name(name: &str) -> String {
         .join(" / ")
 }
 
+/// Names compare ignoring case, unicode form and word separators,
+/// `Blue-Note`, `blue note` and `ｂｌｕｅ_ｎｏｔｅ` are all `blue note`
+pub fn fold_name(name: &str) -> String {
+    name.nfkc()
+        .collect::<String>()
+        .to_lowercase()
+        .split(|c: char| c.is_whitespace() || c == '-' || c == '_')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
 pub fn prepare_path_name(node_name: &str) -> String {
     node_name
         // .to_ascii_lowercase()
@@ -77,6 +91,15 @@ fn test_link_text_is_node_name() {
     ));
 }
 
+#[test]
+fn test_fold_name() {
+    assert_eq!(fold_name("Blue-Note"), "blue note");
+    assert_eq!(fold_name("  ｂｌｕｅ_ｎｏｔｅ "), "blue note");
+    // composed and decomposed accents fold the same
+    assert_eq!(fold_name("Caf\u{e9}"), fold_name("cafe\u{301}"));
+    assert_ne!(fold_name("café"), fold_name("cafe"));
+}
+
 #[test]
 fn test_power_of_ten() {
     assert_eq!(power_of_ten(0), None);
@@ -2,27 +2,38 @@ use async_trait::async_trait;
 use log::*;
 use nvim_rs::{compat::tokio::Compat, Handler, Neovim};
 use std::path::PathBuf;
-use std::sync::Arc;
 
-use crate::git::sync::{cmdline_fetch_and_pull, pull_origin_main, push_all_to_git_remote_cmd};
-use crate::tree;
-use crate::tree::next_sibling_id;
-//use tokio::sync::Mutex; // use std::sync::Mutex instead???
+use crate::config::{AutosaveConfig, Config, GitConfig};
+use crate::error::{Error, Result};
+use crate::export::graph::{render, GraphFormat};
+use crate::export::obsidian::{export_vault, Layout, VaultOptions};
+use crate::export::site::{export_site, SiteOptions};
 use crate::git::diff::{
     diff_w_last_commit, diff_w_last_commit_report, diff_w_main, diff_w_main_report,
     repo_is_modified,
 };
+use crate::git::sync::{cmdline_fetch_and_pull, pull_branch, push_all_to_git_remote_cmd};
 use crate::git::{
     commit_all, get_last_commit_of_branch, handle_git_branching, push_to_git_remote, repo,
     stage_all,
 };
-use crate::node::power_of_ten;
+use crate::node::tasks::{TaskFilter, TaskState};
+use crate::node::{power_of_ten, NodeKey};
+use crate::tree;
+use crate::tree::fsck;
+use crate::tree::graph::{Edges, GraphOptions};
+use crate::tree::next_sibling_id;
+use chrono::NaiveDate;
 use rmpv::Value;
 use std::env;
-use std::sync::Mutex;
 use tokio::io::Stdout;
+use tokio::task;
 use tokio::time;
 
+pub mod service;
+pub mod watch;
+pub use service::TreeService;
+
 pub trait Telescoped {
     fn entry(&self) -> Value;
 }
@@ -54,9 +65,186 @@ fn node_parent(id: &str, tree: &tree::Tree) -> Option<String> {
     }
 }
 
+/// Filters for the `tasks` request from an optional map like
+/// `{ state = "open", node = "2-desk", due_before = "2026-10-20", priority = 2 }`.
+/// Open tasks are listed by default, `state = "all"` lists every task.
+fn task_filter(arg: Option<&Value>) -> Result<TaskFilter> {
+    let mut filter = TaskFilter {
+        state: Some(TaskState::Open),
+        ..TaskFilter::default()
+    };
+    let entries = match arg {
+        None | Some(Value::Nil) => return Ok(filter),
+        Some(Value::Map(entries)) => entries,
+        Some(arg) => return Err(Error::Args(format!("task filter should be a map: {}", arg))),
+    };
+    for (key, value) in entries {
+        let invalid = || Error::Args(format!("invalid task filter {}: {}", key, value));
+        match key.as_str() {
+            Some("state") => {
+                filter.state = match value.as_str().ok_or_else(invalid)? {
+                    "all" => None,
+                    state => Some(TaskState::parse(state).ok_or_else(invalid)?),
+                }
+            }
+            Some("node") => filter.node = Some(value.as_str().ok_or_else(invalid)?.to_string()),
+            Some("due_before") => {
+                let due = value.as_str().ok_or_else(invalid)?;
+                filter.due_before =
+                    Some(NaiveDate::parse_from_str(due, "%Y-%m-%d").map_err(|_| invalid())?)
+            }
+            Some("has_due") => filter.has_due = value.as_bool().ok_or_else(invalid)?,
+            Some("priority") => {
+                filter.priority = Some(
+                    value
+                        .as_u64()
+                        .filter(|priority| (1..=3).contains(priority))
+                        .ok_or_else(invalid)? as u8,
+                )
+            }
+            Some("text") => filter.text = Some(value.as_str().ok_or_else(invalid)?.to_string()),
+            _ => return Err(Error::Args(format!("unknown task filter {}", key))),
+        }
+    }
+    Ok(filter)
+}
+
+/// Graph options sent by neovim, `{edges = "links", root = "2-desk", tag = "jazz"}`
+fn graph_options(arg: Option<&Value>) -> Result<GraphOptions> {
+    let mut options = GraphOptions::default();
+    let entries = match arg {
+        None | Some(Value::Nil) => return Ok(options),
+        Some(Value::Map(entries)) => entries,
+        Some(arg) => {
+            return Err(Error::Args(format!(
+                "graph options should be a map: {}",
+                arg
+            )))
+        }
+    };
+    for (key, value) in entries {
+        let invalid = || Error::Args(format!("invalid graph option {}: {}", key, value));
+        match key.as_str() {
+            Some("edges") => {
+                options.edges =
+                    Edges::parse(value.as_str().ok_or_else(invalid)?).ok_or_else(invalid)?
+            }
+            Some("root") => options.root = Some(value.as_str().ok_or_else(invalid)?.to_string()),
+            Some("tag") => options.tag = Some(value.as_str().ok_or_else(invalid)?.to_string()),
+            _ => return Err(Error::Args(format!("unknown graph option {}", key))),
+        }
+    }
+    Ok(options)
+}
+
+/// What to publish, `{roots = {"2-desk/1-a"}, tags = {"public"}}`
+fn site_options(arg: Option<&Value>) -> Result<SiteOptions> {
+    let mut options = SiteOptions::default();
+    let entries = match arg {
+        None | Some(Value::Nil) => return Ok(options),
+        Some(Value::Map(entries)) => entries,
+        Some(arg) => {
+            return Err(Error::Args(format!(
+                "site options should be a map: {}",
+                arg
+            )))
+        }
+    };
+    for (key, value) in entries {
+        let strings = || -> Result<Vec<String>> {
+            value
+                .as_array()
+                .and_then(|values| {
+                    values
+                        .iter()
+                        .map(|v| v.as_str().map(String::from))
+                        .collect()
+                })
+                .ok_or_else(|| Error::Args(format!("invalid site option {}: {}", key, value)))
+        };
+        match key.as_str() {
+            Some("roots") => options.roots = strings()?,
+            Some("tags") => options.tags = strings()?,
+            _ => return Err(Error::Args(format!("unknown site option {}", key))),
+        }
+    }
+    Ok(options)
+}
+
+/// Vault export options sent by neovim, `{layout = "flat", root = key}`
+fn vault_options(arg: Option<&Value>) -> Result<VaultOptions> {
+    let mut options = VaultOptions::default();
+    let entries = match arg {
+        None | Some(Value::Nil) => return Ok(options),
+        Some(Value::Map(entries)) => entries,
+        Some(arg) => {
+            return Err(Error::Args(format!(
+                "vault options should be a map: {}",
+                arg
+            )))
+        }
+    };
+    for (key, value) in entries {
+        let invalid = || Error::Args(format!("invalid vault option {}: {}", key, value));
+        match key.as_str() {
+            Some("layout") => {
+                options.layout =
+                    Layout::parse(value.as_str().ok_or_else(invalid)?).ok_or_else(invalid)?
+            }
+            Some("root") => options.root = Some(value.as_str().ok_or_else(invalid)?.to_string()),
+            _ => return Err(Error::Args(format!("unknown vault option {}", key))),
+        }
+    }
+    Ok(options)
+}
+
+/// Mentions picked in neovim, `[{id, line, char}, ...]`
+fn mentions_arg(args: &[Value], idx: usize) -> Result<Vec<(NodeKey, u64, u64)>> {
+    let invalid = || {
+        Error::Args(format!(
+            "argument {} should be a list of mentions: {:?}",
+            idx, args
+        ))
+    };
+    let picked = args
+        .get(idx)
+        .and_then(|arg| arg.as_array())
+        .ok_or_else(invalid)?;
+    picked
+        .iter()
+        .map(|mention| {
+            let field = |name: &str| {
+                mention
+                    .as_map()?
+                    .iter()
+                    .find(|(key, _)| key.as_str() == Some(name))
+                    .map(|(_, value)| value.clone())
+            };
+            let node = field("id").and_then(|id| id.as_str().map(String::from));
+            let line = field("line").and_then(|line| line.as_u64());
+            let char = field("char").and_then(|char| char.as_u64());
+            match (node, line, char) {
+                (Some(node), Some(line), Some(char)) => Ok((node, line, char)),
+                _ => Err(invalid()),
+            }
+        })
+        .collect()
+}
+
+/// Run blocking git or filesystem work off of the RPC loop
+async fn blocking<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    task::spawn_blocking(f)
+        .await
+        .expect("blocking task panicked")
+}
+
 #[derive(Clone)]
 pub struct NeovimHandler {
-    pub tree: Arc<Mutex<tree::Tree>>,
+    pub tree: TreeService,
 }
 
 async fn on_start(nvim: Neovim<Compat<Stdout>>) {
@@ -74,31 +262,98 @@ async fn on_start(nvim: Neovim<Compat<Stdout>>) {
     });
 }
 
-async fn pull_main_branch(nvim: Neovim<Compat<Stdout>>) {
-    match pull_origin_main() {
-        Err(msg) => {
-            let print_error_cmd =
-                format!("lua error(\"failed to pull and merge in main branch: {msg}\")");
-            nvim.command(&print_error_cmd).await.unwrap();
-        }
+async fn pull_main_branch(nvim: Neovim<Compat<Stdout>>, git: GitConfig) {
+    match blocking(move || pull_branch(&git)).await {
+        Err(e) => notify_error(&nvim, "pull", &e).await,
         Ok(_) => nvim.command("lua print(\"notes synced\")").await.unwrap(),
     }
 }
 
-#[async_trait]
-impl Handler for NeovimHandler {
-    type Writer = Compat<Stdout>;
+/// Commit every `commit_minutes` while there are changes, see `[autosave]`
+async fn autosave(nvim: Neovim<Compat<Stdout>>, autosave: AutosaveConfig) {
+    let minutes = time::Duration::from_secs(autosave.commit_minutes * 60);
+    let mut interval = time::interval_at(time::Instant::now() + minutes, minutes);
+    loop {
+        interval.tick().await;
+        let message = autosave.message.clone();
+        let saved = blocking(move || -> Result<bool> {
+            if !repo_is_modified()? {
+                return Ok(false);
+            }
+            stage_all()?;
+            commit_all(Some(&message))?;
+            Ok(true)
+        })
+        .await;
+        match saved {
+            Ok(saved) => debug!("autosave committed: {}", saved),
+            Err(e) => notify_error(&nvim, "autosave", &e).await,
+        }
+    }
+}
 
-    async fn handle_notify(&self, name: String, _args: Vec<Value>, neovim: Neovim<Compat<Stdout>>) {
-        // could make a sync fn that handles RPC commit_all
-        // and also handles Err results
-        match name.as_ref() {
+/// String argument `idx` of an RPC
+fn str_arg(args: &[Value], idx: usize) -> Result<String> {
+    args.get(idx)
+        .and_then(|arg| arg.as_str())
+        .map(String::from)
+        .ok_or_else(|| Error::Args(format!("argument {} should be a string: {:?}", idx, args)))
+}
+
+/// Integer argument `idx` of an RPC
+fn u64_arg(args: &[Value], idx: usize) -> Result<u64> {
+    args.get(idx)
+        .and_then(|arg| arg.as_u64())
+        .ok_or_else(|| Error::Args(format!("argument {} should be an integer: {:?}", idx, args)))
+}
+
+/// Integer argument `idx` of an RPC, `default` when it is absent or nil
+fn optional_u64_arg(args: &[Value], idx: usize, default: u64) -> Result<u64> {
+    match args.get(idx) {
+        None | Some(Value::Nil) => Ok(default),
+        Some(_) => u64_arg(args, idx),
+    }
+}
+
+/// Show an error from a notification, which has no reply to carry it
+async fn notify_error(neovim: &Neovim<Compat<Stdout>>, name: &str, e: &Error) {
+    error!("{} failed: {:?}", name, e);
+    let shown = neovim
+        .exec_lua(
+            "vim.notify(...)",
+            vec![
+                Value::from(format!("codex {} failed, {}: {}", name, e.code(), e)),
+                Value::from(4), // vim.log.levels.ERROR
+            ],
+        )
+        .await;
+    if let Err(e) = shown {
+        error!("unable to show the error in neovim: {}", e);
+    }
+}
+
+impl NeovimHandler {
+    async fn config(&self) -> Config {
+        self.tree.read(|tree| tree.config.clone()).await
+    }
+    /// Words added since forking from the main branch
+    async fn words_added(&self) -> Result<u64> {
+        let branch = self.config().await.git.branch;
+        Ok(blocking(move || diff_w_main(&branch)).await?)
+    }
+    async fn notify(
+        &self,
+        name: &str,
+        _args: Vec<Value>,
+        neovim: Neovim<Compat<Stdout>>,
+    ) -> Result<()> {
+        match name {
             "start" => {
                 log::debug!("starting CODEX!");
-                if let Some(dir) = env::current_dir().unwrap().to_str() {
-                    neovim.command(&format!("cd {}", dir)).await.unwrap()
+                if let Some(dir) = env::current_dir()?.to_str() {
+                    neovim.command(&format!("cd {}", dir)).await?
                 }
-                debug!("pwd: {:?}", std::env::current_dir().unwrap());
+                debug!("pwd: {:?}", std::env::current_dir()?);
                 debug!(
                     "env vars: {:?}",
                     std::env::vars()
@@ -109,92 +364,104 @@ impl Handler for NeovimHandler {
                 // fetch_and_pull().unwrap();
                 // cmdline_fetch_and_pull();
                 // handle_git_branching().unwrap();
-                self.tree.lock().unwrap().load();
-                let today = self.tree.lock().unwrap().today_node();
-                neovim.command(&format!("e {}/_.md", today)).await.unwrap();
-                let added = diff_w_main().unwrap();
+                if let Err(e) = self.tree.load().await {
+                    notify_error(&neovim, "config", &e).await;
+                }
+                let config = self.config().await;
+                let problems = self
+                    .tree
+                    .read(|tree| fsck::check(&tree.dir).problems.len())
+                    .await;
+                if problems > 0 {
+                    neovim
+                        .command(&format!(
+                            "lua vim.notify('codex fsck found {} problem(s), run Codex.fsck()', vim.log.levels.WARN)",
+                            problems
+                        ))
+                        .await?;
+                }
+                let today = self.tree.today().await?;
+                neovim.command(&format!("e {}/_.md", today)).await?;
+                let added = self.words_added().await?;
                 neovim
                     .command(&format!("lua vim.g.word_count = {added}"))
-                    .await
-                    .unwrap();
-                stage_all().unwrap();
-                tokio::spawn(async move {pull_main_branch(neovim.clone()).await});
+                    .await?;
+                blocking(stage_all).await?;
+                let dir = self.tree.read(|tree| tree.dir.clone()).await;
+                watch::spawn(self.tree.clone(), neovim.clone(), dir)?;
+                if config.autosave.commit_minutes > 0 {
+                    tokio::spawn(autosave(neovim.clone(), config.autosave));
+                }
+                if config.sync.pull_on_start {
+                    let git = config.git;
+                    tokio::spawn(async move { pull_main_branch(neovim.clone(), git).await });
+                }
 
                 // let today = tree.today_node();
-                debug!("git remote url {:?}", std::env::var("CODEX_GIT_REMOTE"));
             }
             "has_diff" => {
-                debug!("has diffs? {}", repo_is_modified().unwrap());
+                debug!("has diffs? {}", blocking(repo_is_modified).await?);
             }
             "diff" => {
-                let added = diff_w_main().unwrap();
+                let added = self.words_added().await?;
                 debug!("words added (vs main): {}", added);
                 neovim
                     .command(&format!("lua print('words: {}')", added))
-                    .await
-                    .unwrap();
+                    .await?;
             }
             "tick-updated" => {
                 // direct casting from Value to String will result in double quote chars within the
                 // String, ie '"1-nodes/1-jazznode"' (bad) vs '1-nodes/1-jazznode' (good)
-                let curr_node = _args[0].as_str().unwrap().to_string();
-                match self.tree.lock().unwrap().nodes.get_mut(&curr_node) {
-                    Some(node) => node.tick_update_and_write_meta(),
-                    None => {
-                        error!(
-                            "during tick-updated Node id: {} was not found in node tree 😨",
-                            curr_node
-                        );
-                        tokio::spawn(async move {
-                            neovim
-                            .command(&format!(
-                                "lua vim.notify('during tick-updated Node id: {} was not found in node tree 😨\n\n', vim.log.levels.WARN)",
-                                curr_node
-                            )).await.unwrap();
-                        });
-                    }
-                }
+                let curr_node = str_arg(&_args, 0)?;
+                self.tree.tick_updated(curr_node).await?;
             }
             "word-count" => {
-                let added = diff_w_main().unwrap();
+                let added = self.words_added().await?;
                 debug!("WORD COUNT UPDATE: {}", added);
                 neovim
                     .command(&format!("lua vim.g.word_count = {added}"))
-                    .await
-                    .unwrap();
+                    .await?;
             }
             "diff_last" => {
-                let added = diff_w_last_commit().unwrap();
+                let added = blocking(diff_w_last_commit).await?;
                 debug!("words added (vs prev commit): {}", added);
             }
             "diff_report" => {
-                let report = diff_w_main_report().unwrap();
+                let branch = self.config().await.git.branch;
+                let report = blocking(move || diff_w_main_report(&branch)).await?;
                 debug!("Diff Report (vs main): {}", report);
             }
             "diff_last_report" => {
-                let report = diff_w_last_commit_report().unwrap();
+                let report = blocking(diff_w_last_commit_report).await?;
                 debug!("Diff Report (vs last commit): {}", report);
             }
             "stage" => {
-                stage_all().unwrap();
+                blocking(stage_all).await?;
                 // commit_all(None).unwrap();
             }
             "commit" => {
-                commit_all(None).unwrap();
+                blocking(|| commit_all(None)).await?;
             }
             "branch_commit" => {
-                let repo = repo().unwrap();
-                let branch_name = _args[0].as_str().unwrap();
-                let commit = get_last_commit_of_branch(&repo, branch_name);
-                debug!("{}: {:?}", branch_name, commit);
+                let branch_name = str_arg(&_args, 0)?;
+                let commit = blocking(move || -> Result<String> {
+                    let repo = repo()?;
+                    let commit = get_last_commit_of_branch(&repo, &branch_name);
+                    Ok(format!("{}: {:?}", branch_name, commit))
+                })
+                .await?;
+                debug!("{}", commit);
             }
             "push" => {
-                debug!("push status: {:?}", push_to_git_remote().await);
+                let config = self.config().await;
+                if let Err(e) = push_to_git_remote(config.git, config.credentials).await {
+                    notify_error(&neovim, "push", &e).await;
+                }
             }
             "ping" => {
                 let args_s = format!("{:?}", _args);
                 let s = format!("lua print(\"hello pong {}\")", args_s.replace('"', "\\\""));
-                neovim.command(s.as_str()).await.unwrap();
+                neovim.command(s.as_str()).await?;
             }
             "repeat" => {
                 let mut count = 0;
@@ -208,31 +475,36 @@ impl Handler for NeovimHandler {
                             count,
                             args_s.replace('"', "\\\"")
                         );
-                        neovim.command(s.as_str()).await.unwrap();
+                        if let Err(e) = neovim.command(s.as_str()).await {
+                            error!("repeat stopped: {}", e);
+                            break;
+                        }
                         count += 1;
                     }
                 });
             }
             "create" => {
                 debug!("{:?}", _args);
-                // weird error i don't understack if I uncomment this...
-                // let tree = &mut *self.tree.lock().unwrap();
-                let new_node_key = self.tree.lock().unwrap().node_creation(_args).unwrap();
-                neovim
-                    .command(&format!("e {}/_.md", new_node_key))
-                    .await
-                    .unwrap();
-                stage_all().unwrap();
+                let new_node_key = self.tree.create(_args).await?;
+                neovim.command(&format!("e {}/_.md", new_node_key)).await?;
+                blocking(stage_all).await?;
             }
             "node" => {
-                let args: Vec<Option<&str>> = _args.iter().map(|arg| arg.as_str()).collect();
-                let tree = &*self.tree.lock().unwrap();
+                let args: Vec<Option<String>> = _args
+                    .iter()
+                    .map(|arg| arg.as_str().map(String::from))
+                    .collect();
                 if let [Some(node_ref)] = args.as_slice() {
-                    if let Some(node) = tree.nodes.get(&node_ref.to_string()) {
-                        debug!("{:?}: {}", node_ref, node);
-                    } else {
-                        debug!("{:?} not found", node_ref);
-                    }
+                    let node_ref = node_ref.clone();
+                    self.tree
+                        .read(move |tree| {
+                            if let Some(node) = tree.nodes.get(&node_ref) {
+                                debug!("{:?}: {}", node_ref, node);
+                            } else {
+                                debug!("{:?} not found", node_ref);
+                            }
+                        })
+                        .await;
                 }
             }
             "pow" => {
@@ -275,23 +547,28 @@ impl Handler for NeovimHandler {
             // }
             _ => {}
         }
+        Ok(())
     }
     // sync ops
-    async fn handle_request(
-        &self,
-        name: String,
-        _args: Vec<Value>,
-        _neovim: Neovim<Compat<Stdout>>,
-    ) -> Result<Value, Value> {
+    async fn request(&self, name: &str, _args: Vec<Value>) -> Result<Value> {
         debug!("in request handler");
-        match name.as_str() {
+        match name {
             "stop" => {
-                if repo_is_modified().unwrap() {
-                    push_all_to_git_remote_cmd().await;
+                if blocking(repo_is_modified).await? {
+                    let config = self.config().await;
+                    if config.sync.push_on_stop {
+                        push_all_to_git_remote_cmd(config.git).await?;
+                    } else {
+                        blocking(|| commit_all(None)).await?;
+                    }
                 }
                 Ok(Value::Nil)
             }
-            "nodes" => Ok(telescope_nodes(&*self.tree.lock().unwrap())),
+            "reload-config" => {
+                self.tree.reload_config().await?;
+                Ok(Value::Nil)
+            }
+            "nodes" => Ok(self.tree.read(telescope_nodes).await),
             "chk" => {
                 debug!("/////////// DEBUG ///////////");
                 debug!("{:?}", _args);
@@ -299,53 +576,301 @@ impl Handler for NeovimHandler {
                 Ok(Value::Nil)
             }
             "link" => {
-                // let args: Vec<&str> = _args.iter().map(|arg| arg.as_str().unwrap()).collect();
-                // debug!("{:?}", _args);
-
-                let text = _args[0].as_str().unwrap();
-                let from = _args[1].as_str().unwrap();
-                let from_ln = _args[2].as_u64().unwrap();
-                let from_col = _args[3].as_u64().unwrap();
-                let to = _args[4].as_str().unwrap();
-                let to_ln = _args[5].as_u64().unwrap();
-                let to_col = _args[6].as_u64().unwrap();
+                let text = str_arg(&_args, 0)?;
+                let from = str_arg(&_args, 1)?;
+                let from_ln = u64_arg(&_args, 2)?;
+                let from_col = u64_arg(&_args, 3)?;
+                let to = str_arg(&_args, 4)?;
+                let to_ln = u64_arg(&_args, 5)?;
+                let to_col = u64_arg(&_args, 6)?;
                 debug!("{text} {from} {from_ln} {from_col} {to} {to_ln} {to_col}");
                 self.tree
-                    .lock()
-                    .unwrap()
-                    .link(text, from, from_ln, from_col, to, to_ln, to_col);
+                    .link(text, (from, from_ln, from_col), (to, to_ln, to_col))
+                    .await?;
                 Ok(Value::Nil)
             }
             "follow-link" => {
-                // let node = _args[0].to_string();
-                // let link_id = _args[1].to_string();
-                let node = _args[0].as_str().unwrap();
-                let link_id = _args[1].as_str().unwrap();
+                let node = str_arg(&_args, 0)?;
+                let link_id = str_arg(&_args, 1)?;
                 debug!("{node} {link_id}");
-                let names: Vec<String> = self
+                self.tree
+                    .read(move |tree| match tree.follow_link(&node, &link_id) {
+                        Some((link, line, char)) => Ok(Value::from(vec![
+                            (Value::from("node"), Value::from(link)),
+                            (Value::from("line"), Value::from(line)),
+                            (Value::from("char"), Value::from(char)),
+                        ])),
+                        // tell which nodes a name could mean instead of going nowhere
+                        None => match tree.resolve_link_text(&link_id) {
+                            Err(e @ Error::Ambiguous(..)) => Err(e),
+                            _ => Ok(Value::Nil),
+                        },
+                    })
+                    .await
+            }
+            "move" => {
+                debug!("{:?}", _args);
+                let from = str_arg(&_args, 0)?;
+                let new_parent = str_arg(&_args, 1)?;
+                let position = _args
+                    .get(2)
+                    .and_then(|arg| arg.as_u64())
+                    .map(|p| p as usize);
+                let moved = self.tree.move_node(from, new_parent, position).await?;
+                Ok(Value::String(moved.into()))
+            }
+            "delete" => {
+                debug!("{:?}", _args);
+                let node = str_arg(&_args, 0)?;
+                let cascade = _args.get(1).and_then(|arg| arg.as_bool()).unwrap_or(false);
+                let removal = self.tree.delete(node, cascade).await?;
+                Ok(Value::from(vec![
+                    (
+                        Value::from("removed"),
+                        Value::Array(removal.removed.iter().map(|key| key.entry()).collect()),
+                    ),
+                    (
+                        Value::from("dangling"),
+                        Value::Array(removal.dangling.iter().map(|link| link.entry()).collect()),
+                    ),
+                ]))
+            }
+            "archive" => {
+                debug!("{:?}", _args);
+                let node = str_arg(&_args, 0)?;
+                let cascade = _args.get(1).and_then(|arg| arg.as_bool()).unwrap_or(false);
+                let archived = self.tree.archive(node, cascade).await?;
+                Ok(Value::String(archived.into()))
+            }
+            "fsck" => {
+                let repair = _args.first().and_then(|arg| arg.as_bool()).unwrap_or(false);
+                let problems = self.tree.fsck(repair).await?;
+                Ok(Value::Array(
+                    problems.iter().map(|problem| problem.entry()).collect(),
+                ))
+            }
+            "search" => {
+                debug!("{:?}", _args);
+                let query = str_arg(&_args, 0)?;
+                Ok(self
                     .tree
-                    .lock()
-                    .unwrap()
-                    .nodes
-                    .keys()
-                    .map(|k| k.clone())
-                    .collect();
-                debug!("{names:?}");
-                let (link, line) = self.tree.lock().unwrap().get_link(node, link_id);
+                    .read(move |tree| {
+                        Value::Array(tree.search(&query).iter().map(|hit| hit.entry()).collect())
+                    })
+                    .await)
+            }
+            "tasks" => {
+                debug!("{:?}", _args);
+                let filter = task_filter(_args.first())?;
+                Ok(self
+                    .tree
+                    .read(move |tree| {
+                        Value::Array(
+                            tree.query_tasks(&filter)
+                                .iter()
+                                .map(|task| task.entry())
+                                .collect(),
+                        )
+                    })
+                    .await)
+            }
+            "tags" => Ok(self
+                .tree
+                .read(|tree| Value::Array(tree.all_tags().iter().map(|tag| tag.entry()).collect()))
+                .await),
+            "nodes-by-tag" => {
+                debug!("{:?}", _args);
+                let tag = str_arg(&_args, 0)?;
+                self.tree
+                    .read(move |tree| {
+                        let nodes = tree.nodes_by_tag(&tag)?;
+                        Ok(Value::Array(
+                            nodes.iter().map(|node| node.entry()).collect(),
+                        ))
+                    })
+                    .await
+            }
+            "add-tag" => {
+                debug!("{:?}", _args);
+                let (node, tag) = (str_arg(&_args, 0)?, str_arg(&_args, 1)?);
+                let tag = self.tree.add_tag(node, tag).await?;
+                Ok(Value::String(tag.into()))
+            }
+            "remove-tag" => {
+                debug!("{:?}", _args);
+                let (node, tag) = (str_arg(&_args, 0)?, str_arg(&_args, 1)?);
+                self.tree.remove_tag(node, tag).await?;
+                Ok(Value::Nil)
+            }
+            "rename-tag" => {
+                debug!("{:?}", _args);
+                let (from, to) = (str_arg(&_args, 0)?, str_arg(&_args, 1)?);
+                let renamed = self.tree.rename_tag(from, to).await?;
+                Ok(Value::Array(
+                    renamed.iter().map(|key| key.entry()).collect(),
+                ))
+            }
+            "templates" => {
+                self.tree
+                    .read(|tree| {
+                        let templates = tree.templates()?;
+                        Ok(Value::Array(templates.iter().map(|t| t.entry()).collect()))
+                    })
+                    .await
+            }
+            "template-parents" => {
+                debug!("{:?}", _args);
+                let template = str_arg(&_args, 0)?;
+                Ok(self
+                    .tree
+                    .read(move |tree| {
+                        let parents = tree.template_parents(&template);
+                        Value::Array(parents.iter().map(|node| node.entry()).collect())
+                    })
+                    .await)
+            }
+            "set-template" => {
+                debug!("{:?}", _args);
+                let node = str_arg(&_args, 0)?;
+                let template = str_arg(&_args, 1).ok();
+                self.tree.set_template(node, template).await?;
+                Ok(Value::Nil)
+            }
+            "orphans" => Ok(self
+                .tree
+                .read(|tree| Value::Array(tree.orphans().iter().map(|node| node.entry()).collect()))
+                .await),
+            "hubs" => {
+                let count = optional_u64_arg(&_args, 0, 10)? as usize;
+                Ok(self
+                    .tree
+                    .read(move |tree| {
+                        Value::Array(tree.hubs(count).iter().map(|hub| hub.entry()).collect())
+                    })
+                    .await)
+            }
+            "shortest-path" => {
+                debug!("{:?}", _args);
+                let (from, to) = (str_arg(&_args, 0)?, str_arg(&_args, 1)?);
+                self.tree
+                    .read(move |tree| match tree.shortest_path(&from, &to)? {
+                        Some(path) => {
+                            Ok(Value::Array(path.iter().map(|key| key.entry()).collect()))
+                        }
+                        None => Ok(Value::Nil),
+                    })
+                    .await
+            }
+            "clusters" => Ok(self
+                .tree
+                .read(|tree| {
+                    let clusters = tree.link_clusters();
+                    Value::Array(
+                        clusters
+                            .iter()
+                            .map(|cluster| {
+                                Value::Array(cluster.iter().map(|key| key.entry()).collect())
+                            })
+                            .collect(),
+                    )
+                })
+                .await),
+            "stale" => {
+                let days = optional_u64_arg(&_args, 0, 30)?;
+                self.tree
+                    .read(move |tree| {
+                        let stale = tree.stale_linked_to_today(days)?;
+                        Ok(Value::Array(
+                            stale.iter().map(|node| node.entry()).collect(),
+                        ))
+                    })
+                    .await
+            }
+            "graph" => {
+                debug!("{:?}", _args);
+                let format = str_arg(&_args, 0)?;
+                let format = GraphFormat::parse(&format)
+                    .ok_or_else(|| Error::Args(format!("not a graph format: {}", format)))?;
+                let options = graph_options(_args.get(1))?;
+                self.tree
+                    .read(move |tree| {
+                        let graph = tree.graph(&options)?;
+                        Ok(Value::from(render(&graph, format)))
+                    })
+                    .await
+            }
+            "export-site" => {
+                debug!("{:?}", _args);
+                let out = PathBuf::from(str_arg(&_args, 0)?);
+                let options = site_options(_args.get(1))?;
+                let pages = self
+                    .tree
+                    .read(move |tree| export_site(tree, &options, &out))
+                    .await?;
+                Ok(Value::from(pages as u64))
+            }
+            "export-vault" => {
+                debug!("{:?}", _args);
+                let out = PathBuf::from(str_arg(&_args, 0)?);
+                let options = vault_options(_args.get(1))?;
+                let notes = self
+                    .tree
+                    .read(move |tree| export_vault(tree, &options, &out))
+                    .await?;
+                Ok(Value::from(notes as u64))
+            }
+            "import-obsidian" => {
+                debug!("{:?}", _args);
+                let vault = PathBuf::from(str_arg(&_args, 0)?);
+                let parent = str_arg(&_args, 1).ok();
+                let stats = self.tree.import_obsidian(vault, parent).await?;
+                blocking(stage_all).await?;
                 Ok(Value::from(vec![
-                    (Value::from("node"), Value::from(link)),
-                    (Value::from("line"), Value::from(line)),
+                    (Value::from("id"), Value::from(stats.root.as_str())),
+                    (Value::from("notes"), Value::from(stats.notes as u64)),
+                    (Value::from("folders"), Value::from(stats.folders as u64)),
+                    (Value::from("links"), Value::from(stats.links as u64)),
+                    (
+                        Value::from("attachments"),
+                        Value::from(stats.attachments as u64),
+                    ),
+                    (
+                        Value::from("unresolved"),
+                        Value::from(stats.unresolved as u64),
+                    ),
                 ]))
             }
+            "mentions" => {
+                debug!("{:?}", _args);
+                let node = str_arg(&_args, 0)?;
+                self.tree
+                    .read(move |tree| {
+                        let mentions = tree.unlinked_mentions(&node)?;
+                        Ok(Value::Array(
+                            mentions.iter().map(|mention| mention.entry()).collect(),
+                        ))
+                    })
+                    .await
+            }
+            "link-mentions" => {
+                debug!("{:?}", _args);
+                let node = str_arg(&_args, 0)?;
+                let picked = mentions_arg(&_args, 1)?;
+                let linked = self.tree.link_mentions(node, picked).await?;
+                Ok(Value::from(linked as u64))
+            }
             "children" => {
                 debug!("{:?}", _args);
-                let args: Vec<&str> = _args.iter().map(|arg| arg.as_str().unwrap()).collect();
-                Ok(telescope_child_nodes(args[0], &*self.tree.lock().unwrap()))
+                let id = str_arg(&_args, 0)?;
+                Ok(self
+                    .tree
+                    .read(move |tree| telescope_child_nodes(&id, tree))
+                    .await)
             }
             "parent" => {
                 debug!("{:?}", _args);
-                let args: Vec<&str> = _args.iter().map(|arg| arg.as_str().unwrap()).collect();
-                if let Some(parent) = node_parent(args[0], &*self.tree.lock().unwrap()) {
+                let id = str_arg(&_args, 0)?;
+                if let Some(parent) = self.tree.read(move |tree| node_parent(&id, tree)).await {
                     debug!("found parent: {}", parent);
                     Ok(Value::String(parent.into()))
                 } else {
@@ -353,27 +878,121 @@ impl Handler for NeovimHandler {
                 }
             }
             "latest-journal" => {
-                let page = self.tree.lock().unwrap().latest_journal();
+                let page = self.tree.read(|tree| tree.latest_journal()).await;
                 debug!("{page}");
                 Ok(Value::String(page.into()))
             }
             "prev-sibling" => {
-                let args: Vec<&str> = _args.iter().map(|arg| arg.as_str().unwrap()).collect();
-                Ok(Value::String(
-                    self.tree.lock().unwrap().next_sibling(args[0], true).into(),
-                ))
+                let id = str_arg(&_args, 0)?;
+                let sibling = self
+                    .tree
+                    .read(move |tree| tree.next_sibling(&id, true))
+                    .await?;
+                Ok(Value::String(sibling.into()))
             }
             "next-sibling" => {
-                let args: Vec<&str> = _args.iter().map(|arg| arg.as_str().unwrap()).collect();
-                Ok(Value::String(
-                    self.tree
-                        .lock()
-                        .unwrap()
-                        .next_sibling(args[0], false)
-                        .into(),
-                ))
+                let id = str_arg(&_args, 0)?;
+                let sibling = self
+                    .tree
+                    .read(move |tree| tree.next_sibling(&id, false))
+                    .await?;
+                Ok(Value::String(sibling.into()))
             }
             _ => Ok(Value::Nil),
         }
     }
 }
+
+#[async_trait]
+impl Handler for NeovimHandler {
+    type Writer = Compat<Stdout>;
+
+    async fn handle_notify(&self, name: String, args: Vec<Value>, neovim: Neovim<Compat<Stdout>>) {
+        if let Err(e) = self.notify(&name, args, neovim.clone()).await {
+            notify_error(&neovim, &name, &e).await;
+        }
+    }
+    /// Errors are replied as `"<code>: <message>"`, see `Error::to_value`
+    async fn handle_request(
+        &self,
+        name: String,
+        args: Vec<Value>,
+        _neovim: Neovim<Compat<Stdout>>,
+    ) -> std::result::Result<Value, Value> {
+        self.request(&name, args).await.map_err(|e| {
+            error!("{} failed: {:?}", name, e);
+            e.to_value()
+        })
+    }
+}
+
+#[cfg(test)]
+fn map(entries: Vec<(&str, Value)>) -> Value {
+    Value::Map(
+        entries
+            .into_iter()
+            .map(|(key, value)| (Value::from(key), value))
+            .collect(),
+    )
+}
+
+#[test]
+fn test_task_filter() {
+    assert_eq!(task_filter(None).unwrap().state, Some(TaskState::Open));
+    let filter = task_filter(Some(&map(vec![
+        ("state", Value::from("all")),
+        ("due_before", Value::from("2026-10-20")),
+        ("priority", Value::from(2)),
+    ])))
+    .unwrap();
+    assert_eq!(filter.state, None);
+    assert_eq!(filter.due_before, NaiveDate::from_ymd_opt(2026, 10, 20));
+    assert_eq!(filter.priority, Some(2));
+    let invalid = |entries| task_filter(Some(&map(entries))).unwrap_err().to_string();
+    assert!(invalid(vec![("state", Value::from("opne"))]).contains("opne"));
+    assert!(invalid(vec![("due_before", Value::from("tomorrow"))]).contains("due_before"));
+    assert!(invalid(vec![("priority", Value::from(9))]).contains("priority"));
+    assert!(invalid(vec![("stat", Value::from("open"))]).contains("stat"));
+    assert_eq!(
+        task_filter(Some(&Value::from("open"))).unwrap_err().code(),
+        "invalid-args"
+    );
+}
+
+#[test]
+fn test_graph_options() {
+    assert_eq!(graph_options(None).unwrap(), GraphOptions::default());
+    let options = graph_options(Some(&map(vec![
+        ("edges", Value::from("links")),
+        ("tag", Value::from("jazz")),
+    ])))
+    .unwrap();
+    assert_eq!(options.edges, Edges::Links);
+    assert_eq!(options.tag.as_deref(), Some("jazz"));
+    let invalid = |entries| graph_options(Some(&map(entries))).unwrap_err().to_string();
+    assert!(invalid(vec![("edge", Value::from("links"))]).contains("unknown graph option"));
+    assert!(invalid(vec![("edges", Value::from("roads"))]).contains("roads"));
+    assert!(invalid(vec![("root", Value::from(1))]).contains("root"));
+}
+
+#[test]
+fn test_site_options() {
+    assert_eq!(site_options(None).unwrap(), SiteOptions::default());
+    let roots = Value::Array(vec![Value::from("2-desk/1-a")]);
+    let options = site_options(Some(&map(vec![("roots", roots)]))).unwrap();
+    assert_eq!(options.roots, vec!["2-desk/1-a"]);
+    let invalid = |entries| site_options(Some(&map(entries))).unwrap_err().to_string();
+    assert!(invalid(vec![("root", Value::from("2-desk"))]).contains("unknown site option"));
+    assert!(invalid(vec![("tags", Value::from("public"))]).contains("tags"));
+    assert!(invalid(vec![("tags", Value::Array(vec![Value::from(1)]))]).contains("tags"));
+}
+
+#[test]
+fn test_vault_options() {
+    assert_eq!(vault_options(None).unwrap(), VaultOptions::default());
+    let options = vault_options(Some(&map(vec![("layout", Value::from("flat"))]))).unwrap();
+    assert_eq!(options.layout, Layout::Flat);
+    let invalid = |entries| vault_options(Some(&map(entries))).unwrap_err().to_string();
+    assert!(invalid(vec![("flat", Value::from(true))]).contains("unknown vault option"));
+    assert!(invalid(vec![("layout", Value::from("tree"))]).contains("tree"));
+}
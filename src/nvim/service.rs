@@ -0,0 +1,401 @@
+use crate::error::{Error, Result};
+use crate::import::obsidian::{import_vault, ImportStats};
+use crate::node::NodeKey;
+use crate::tree::fsck::{self, Problem};
+use crate::tree::watch::TreeChange;
+use crate::tree::{Removal, Tree};
+use log::*;
+use rmpv::Value;
+use std::path::PathBuf;
+use std::sync::{Arc, PoisonError, RwLock};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task;
+
+type Reply<T> = oneshot::Sender<Result<T>>;
+
+/// Changes to the tree, applied one at a time by the tree task
+pub enum TreeCommand {
+    /// Load the tree from disk, replying with the error of an invalid
+    /// `codex.toml` whose previous config is kept
+    Load {
+        reply: Reply<()>,
+    },
+    /// Read `codex.toml` again
+    ReloadConfig {
+        reply: Reply<()>,
+    },
+    Today {
+        reply: Reply<NodeKey>,
+    },
+    TickUpdated {
+        node: NodeKey,
+        reply: Reply<()>,
+    },
+    /// `[parent, name]` or `[name]` as sent by neovim, `create_node` renders
+    /// the parent's or tag's template into the body. `[parent, name, template,
+    /// answers?]` renders the named template instead
+    Create {
+        args: Vec<Value>,
+        reply: Reply<NodeKey>,
+    },
+    Link {
+        text: String,
+        from: NodeKey,
+        from_line: u64,
+        from_char: u64,
+        to: NodeKey,
+        to_line: u64,
+        to_char: u64,
+        reply: Reply<()>,
+    },
+    Move {
+        from: NodeKey,
+        new_parent: NodeKey,
+        position: Option<usize>,
+        reply: Reply<NodeKey>,
+    },
+    Delete {
+        node: NodeKey,
+        cascade: bool,
+        reply: Reply<Removal>,
+    },
+    Archive {
+        node: NodeKey,
+        cascade: bool,
+        reply: Reply<NodeKey>,
+    },
+    Fsck {
+        repair: bool,
+        reply: Reply<Vec<Problem>>,
+    },
+    AddTag {
+        node: NodeKey,
+        tag: String,
+        reply: Reply<String>,
+    },
+    RemoveTag {
+        node: NodeKey,
+        tag: String,
+        reply: Reply<()>,
+    },
+    RenameTag {
+        from: String,
+        to: String,
+        reply: Reply<Vec<NodeKey>>,
+    },
+    /// Start new children of `node` from `template`, or stop doing so
+    SetTemplate {
+        node: NodeKey,
+        template: Option<String>,
+        reply: Reply<()>,
+    },
+    /// Turn picked unlinked mentions of `node` into links
+    LinkMentions {
+        node: NodeKey,
+        picked: Vec<(NodeKey, u64, u64)>,
+        reply: Reply<usize>,
+    },
+    /// Import an Obsidian vault under `parent`, the desk when None
+    ImportObsidian {
+        vault: PathBuf,
+        parent: Option<NodeKey>,
+        reply: Reply<ImportStats>,
+    },
+    /// Paths changed on disk outside of the tree
+    Refresh {
+        paths: Vec<PathBuf>,
+        reply: Reply<Vec<TreeChange>>,
+    },
+}
+use TreeCommand::*;
+
+fn apply(tree: &mut Tree, command: TreeCommand) {
+    // a dropped receiver only means the requester stopped waiting
+    match command {
+        Load { reply } => {
+            let config = tree.reload_config();
+            tree.load_nodes();
+            let _ = reply.send(config);
+        }
+        ReloadConfig { reply } => {
+            let _ = reply.send(tree.reload_config());
+        }
+        Today { reply } => {
+            let today = tree.journal_day(chrono::Local::now().date_naive());
+            let _ = reply.send(today);
+        }
+        TickUpdated { node, reply } => {
+            let result = match tree.nodes.get_mut(&node) {
+                Some(updated) => {
+                    updated.tick_update_and_write_meta();
+                    tree.reindex_node(&node);
+                    tree.sync_links(&node).map(|_| ())
+                }
+                None => Err(Error::NodeNotFound(node)),
+            };
+            let _ = reply.send(result);
+        }
+        Create { args, reply } => {
+            let _ = reply.send(tree.node_creation(args));
+        }
+        Link {
+            text,
+            from,
+            from_line,
+            from_char,
+            to,
+            to_line,
+            to_char,
+            reply,
+        } => {
+            let result = if !tree.nodes.contains_key(&from) {
+                Err(Error::NodeNotFound(from))
+            } else if !tree.nodes.contains_key(&to) {
+                Err(Error::NodeNotFound(to))
+            } else {
+                tree.link(&text, &from, from_line, from_char, &to, to_line, to_char);
+                Ok(())
+            };
+            let _ = reply.send(result);
+        }
+        Move {
+            from,
+            new_parent,
+            position,
+            reply,
+        } => {
+            let _ = reply.send(tree.move_node(&from, &new_parent, position));
+        }
+        Delete {
+            node,
+            cascade,
+            reply,
+        } => {
+            let _ = reply.send(tree.delete_node(&node, cascade));
+        }
+        Archive {
+            node,
+            cascade,
+            reply,
+        } => {
+            let _ = reply.send(tree.archive_node(&node, cascade));
+        }
+        AddTag { node, tag, reply } => {
+            let _ = reply.send(tree.add_tag(&node, &tag));
+        }
+        RemoveTag { node, tag, reply } => {
+            let _ = reply.send(tree.remove_tag(&node, &tag));
+        }
+        RenameTag { from, to, reply } => {
+            let _ = reply.send(tree.rename_tag(&from, &to));
+        }
+        SetTemplate {
+            node,
+            template,
+            reply,
+        } => {
+            let _ = reply.send(tree.set_template(&node, template.as_deref()));
+        }
+        LinkMentions {
+            node,
+            picked,
+            reply,
+        } => {
+            let _ = reply.send(tree.link_mentions(&node, &picked));
+        }
+        ImportObsidian {
+            vault,
+            parent,
+            reply,
+        } => {
+            let parent = parent.unwrap_or_else(|| tree.desk.clone());
+            let _ = reply.send(import_vault(tree, &vault, &parent));
+        }
+        Fsck { repair, reply } => {
+            if repair {
+                match fsck::repair(&tree.dir) {
+                    Ok(repaired) => debug!("fsck repaired:\n{}", repaired),
+                    Err(e) => {
+                        let _ = reply.send(Err(e));
+                        return;
+                    }
+                }
+                tree.load();
+            }
+            let mut report = fsck::check(&tree.dir);
+            report.problems.extend(tree.check_family().problems);
+            let _ = reply.send(Ok(report.problems));
+        }
+        Refresh { paths, reply } => {
+            let _ = reply.send(Ok(tree.refresh_paths(&paths)));
+        }
+    }
+}
+
+/// Owns the codex `Tree` for the neovim handler.
+///
+/// Reads share a lock and run concurrently, changes are sent over a
+/// channel as `TreeCommand`s and applied in order by a dedicated task.
+/// Both run on the blocking thread pool since they touch the filesystem,
+/// so slow tree or git work never stalls the RPC loop.
+#[derive(Clone)]
+pub struct TreeService {
+    tree: Arc<RwLock<Tree>>,
+    commands: mpsc::Sender<TreeCommand>,
+}
+
+impl TreeService {
+    /// Start the tree task, must be called within the tokio runtime
+    pub fn spawn(tree: Tree) -> TreeService {
+        let tree = Arc::new(RwLock::new(tree));
+        let (commands, mut receiver) = mpsc::channel::<TreeCommand>(64);
+        let owned = tree.clone();
+        tokio::spawn(async move {
+            while let Some(command) = receiver.recv().await {
+                let tree = owned.clone();
+                let applied = task::spawn_blocking(move || {
+                    let mut tree = tree.write().unwrap_or_else(PoisonError::into_inner);
+                    apply(&mut tree, command);
+                })
+                .await;
+                if let Err(e) = applied {
+                    error!("tree command failed: {}", e);
+                }
+            }
+            debug!("tree task stopped");
+        });
+        TreeService { tree, commands }
+    }
+    /// Run `f` against the tree alongside any other readers
+    pub async fn read<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&Tree) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let tree = self.tree.clone();
+        task::spawn_blocking(move || f(&tree.read().unwrap_or_else(PoisonError::into_inner)))
+            .await
+            .expect("tree read panicked")
+    }
+    async fn send<T>(&self, command: impl FnOnce(Reply<T>) -> TreeCommand) -> Result<T> {
+        let (reply, response) = oneshot::channel();
+        self.commands
+            .clone()
+            .send(command(reply))
+            .await
+            .map_err(|_| Error::Tree("tree task has stopped".to_string()))?;
+        response
+            .await
+            .map_err(|_| Error::Tree("tree task dropped the request".to_string()))?
+    }
+    pub async fn load(&self) -> Result<()> {
+        self.send(|reply| Load { reply }).await
+    }
+    pub async fn reload_config(&self) -> Result<()> {
+        self.send(|reply| ReloadConfig { reply }).await
+    }
+    pub async fn today(&self) -> Result<NodeKey> {
+        self.send(|reply| Today { reply }).await
+    }
+    pub async fn tick_updated(&self, node: NodeKey) -> Result<()> {
+        self.send(|reply| TickUpdated { node, reply }).await
+    }
+    pub async fn create(&self, args: Vec<Value>) -> Result<NodeKey> {
+        self.send(|reply| Create { args, reply }).await
+    }
+    pub async fn link(
+        &self,
+        text: String,
+        (from, from_line, from_char): (NodeKey, u64, u64),
+        (to, to_line, to_char): (NodeKey, u64, u64),
+    ) -> Result<()> {
+        self.send(|reply| Link {
+            text,
+            from,
+            from_line,
+            from_char,
+            to,
+            to_line,
+            to_char,
+            reply,
+        })
+        .await
+    }
+    pub async fn move_node(
+        &self,
+        from: NodeKey,
+        new_parent: NodeKey,
+        position: Option<usize>,
+    ) -> Result<NodeKey> {
+        self.send(|reply| Move {
+            from,
+            new_parent,
+            position,
+            reply,
+        })
+        .await
+    }
+    pub async fn delete(&self, node: NodeKey, cascade: bool) -> Result<Removal> {
+        self.send(|reply| Delete {
+            node,
+            cascade,
+            reply,
+        })
+        .await
+    }
+    pub async fn archive(&self, node: NodeKey, cascade: bool) -> Result<NodeKey> {
+        self.send(|reply| Archive {
+            node,
+            cascade,
+            reply,
+        })
+        .await
+    }
+    pub async fn fsck(&self, repair: bool) -> Result<Vec<Problem>> {
+        self.send(|reply| Fsck { repair, reply }).await
+    }
+    pub async fn add_tag(&self, node: NodeKey, tag: String) -> Result<String> {
+        self.send(|reply| AddTag { node, tag, reply }).await
+    }
+    pub async fn remove_tag(&self, node: NodeKey, tag: String) -> Result<()> {
+        self.send(|reply| RemoveTag { node, tag, reply }).await
+    }
+    pub async fn rename_tag(&self, from: String, to: String) -> Result<Vec<NodeKey>> {
+        self.send(|reply| RenameTag { from, to, reply }).await
+    }
+    pub async fn set_template(&self, node: NodeKey, template: Option<String>) -> Result<()> {
+        self.send(|reply| SetTemplate {
+            node,
+            template,
+            reply,
+        })
+        .await
+    }
+    pub async fn link_mentions(
+        &self,
+        node: NodeKey,
+        picked: Vec<(NodeKey, u64, u64)>,
+    ) -> Result<usize> {
+        self.send(|reply| LinkMentions {
+            node,
+            picked,
+            reply,
+        })
+        .await
+    }
+    pub async fn import_obsidian(
+        &self,
+        vault: PathBuf,
+        parent: Option<NodeKey>,
+    ) -> Result<ImportStats> {
+        self.send(|reply| ImportObsidian {
+            vault,
+            parent,
+            reply,
+        })
+        .await
+    }
+    pub async fn refresh(&self, paths: Vec<PathBuf>) -> Result<Vec<TreeChange>> {
+        self.send(|reply| Refresh { paths, reply }).await
+    }
+}
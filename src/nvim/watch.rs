@@ -0,0 +1,100 @@
+use super::{Telescoped, TreeService};
+use crate::error::{Error, Result};
+use log::*;
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use nvim_rs::{compat::tokio::Compat, Neovim};
+use rmpv::Value;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use tokio::io::Stdout;
+use tokio::sync::mpsc as tokio_mpsc;
+
+/// Paths changed on disk, None when the watcher lost track and the
+/// whole tree needs to be reloaded
+type Batch = Option<Vec<PathBuf>>;
+
+fn in_git_dir(path: &Path) -> bool {
+    path.components().any(|part| part.as_os_str() == ".git")
+}
+
+fn event_paths(event: DebouncedEvent) -> Batch {
+    let paths = match event {
+        DebouncedEvent::Create(path)
+        | DebouncedEvent::Write(path)
+        | DebouncedEvent::Remove(path) => vec![path],
+        DebouncedEvent::Rename(from, to) => vec![from, to],
+        DebouncedEvent::Rescan => return None,
+        DebouncedEvent::Error(e, path) => {
+            error!("codex watcher error at {:?}: {}", path, e);
+            vec![]
+        }
+        // notices come before the debounced event, chmod changes no content
+        _ => vec![],
+    };
+    Some(paths.into_iter().filter(|path| !in_git_dir(path)).collect())
+}
+
+/// Watch the codex directory for edits made outside of neovim, such as a
+/// `git pull` or another editor, patch the tree to match and tell neovim
+/// through `require("codex").tree_changed(changes)`.
+pub fn spawn(service: TreeService, neovim: Neovim<Compat<Stdout>>, dir: PathBuf) -> Result<()> {
+    let (events, received) = mpsc::channel();
+    let mut fs_watcher = watcher(events, Duration::from_millis(500))
+        .map_err(|e| Error::Tree(format!("unable to watch {:?}: {}", dir, e)))?;
+    fs_watcher
+        .watch(&dir, RecursiveMode::Recursive)
+        .map_err(|e| Error::Tree(format!("unable to watch {:?}: {}", dir, e)))?;
+    let (batches, mut changed) = tokio_mpsc::unbounded_channel::<Batch>();
+    thread::spawn(move || {
+        // the watcher stops when dropped, so it lives with this thread
+        let _fs_watcher = fs_watcher;
+        while let Ok(event) = received.recv() {
+            let mut batch = event_paths(event);
+            for event in received.try_iter() {
+                batch = match (batch, event_paths(event)) {
+                    (Some(mut paths), Some(more)) => {
+                        paths.extend(more);
+                        Some(paths)
+                    }
+                    _ => None,
+                };
+            }
+            if batch.as_ref().is_some_and(|paths| paths.is_empty()) {
+                continue;
+            }
+            if batches.send(batch).is_err() {
+                break;
+            }
+        }
+        debug!("codex watcher stopped");
+    });
+    tokio::spawn(async move {
+        while let Some(batch) = changed.recv().await {
+            let changes = match batch {
+                Some(paths) => service.refresh(paths).await,
+                None => service.load().await.map(|_| vec![]),
+            };
+            let changes = match changes {
+                Ok(changes) => changes,
+                Err(e) => {
+                    error!("unable to refresh the tree: {:?}", e);
+                    continue;
+                }
+            };
+            if changes.is_empty() {
+                continue;
+            }
+            debug!("tree changed on disk: {:?}", changes);
+            let changes = Value::Array(changes.iter().map(|change| change.entry()).collect());
+            if let Err(e) = neovim
+                .exec_lua("require('codex').tree_changed(...)", vec![changes])
+                .await
+            {
+                error!("unable to tell neovim about tree changes: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,311 @@
+use crate::node::{format_display_name, NodeKey};
+use crate::nvim::Telescoped;
+use nvim_rs::Value;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Longest snippet (in chars) returned with a search hit
+const SNIPPET_LEN: usize = 80;
+
+/// Where a token shows up inside of a node body
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Posting {
+    position: usize,
+    line: usize,
+}
+
+/// The index entries of a single body, kept by the load cache so an
+/// unchanged body isn't read and tokenized again
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedBody {
+    lines: Vec<String>,
+    postings: BTreeMap<String, Vec<Posting>>,
+}
+
+impl IndexedBody {
+    pub fn new(body: &str) -> Self {
+        let mut indexed = IndexedBody::default();
+        let mut position = 0;
+        for (line, text) in body.lines().enumerate() {
+            for token in tokenize(text) {
+                indexed.postings.entry(token).or_default().push(Posting {
+                    position,
+                    line: line + 1,
+                });
+                position += 1;
+            }
+            indexed.lines.push(text.to_string());
+        }
+        indexed
+    }
+}
+
+#[derive(Debug, Default)]
+struct Document {
+    lines: Vec<String>,
+    terms: HashSet<String>,
+}
+
+/// Inverted index over the `_.md` bodies of the nodes in a tree
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    terms: BTreeMap<String, HashMap<NodeKey, Vec<Posting>>>,
+    docs: HashMap<NodeKey, Document>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub node: NodeKey,
+    pub line: u64,
+    pub snippet: String,
+    pub score: f64,
+}
+
+impl Telescoped for SearchHit {
+    fn entry(&self) -> Value {
+        Value::Map(vec![
+            (
+                Value::String("id".into()),
+                Value::String(self.node.clone().into()),
+            ),
+            (
+                Value::String("display".into()),
+                Value::String(format_display_name(&self.node).into()),
+            ),
+            (Value::String("line".into()), Value::from(self.line)),
+            (
+                Value::String("snippet".into()),
+                Value::String(self.snippet.clone().into()),
+            ),
+            (Value::String("score".into()), Value::from(self.score)),
+        ])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Clause {
+    Term(String),
+    Prefix(String),
+    Phrase(Vec<String>),
+}
+
+/// Split text into lowercased alphanumeric tokens
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+        .collect()
+}
+
+/// Parse a query string into clauses, all of which must match.
+/// `"quoted words"` are phrases and a trailing `*` makes a prefix query.
+fn parse_query(query: &str) -> Vec<Clause> {
+    let mut clauses = vec![];
+    for (idx, part) in query.split('"').enumerate() {
+        if idx % 2 == 1 {
+            let phrase = tokenize(part);
+            match phrase.len() {
+                0 => {}
+                1 => clauses.push(Clause::Term(phrase[0].clone())),
+                _ => clauses.push(Clause::Phrase(phrase)),
+            }
+            continue;
+        }
+        for word in part.split_whitespace() {
+            if let Some(prefix) = word.strip_suffix('*') {
+                if let Some(token) = tokenize(prefix).pop() {
+                    clauses.push(Clause::Prefix(token));
+                }
+            } else {
+                clauses.extend(tokenize(word).into_iter().map(Clause::Term));
+            }
+        }
+    }
+    clauses
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        SearchIndex::default()
+    }
+    /// Index (or re-index) the body of a node
+    #[cfg(test)]
+    pub fn insert(&mut self, node: &str, body: &str) {
+        self.insert_indexed(node, IndexedBody::new(body));
+    }
+    /// Index (or re-index) a node from the entries of its body
+    pub fn insert_indexed(&mut self, node: &str, indexed: IndexedBody) {
+        self.remove(node);
+        let mut doc = Document::default();
+        for (term, postings) in indexed.postings {
+            self.terms
+                .entry(term.clone())
+                .or_default()
+                .insert(node.to_string(), postings);
+            doc.terms.insert(term);
+        }
+        doc.lines = indexed.lines;
+        self.docs.insert(node.to_string(), doc);
+    }
+    /// Whether `body` is what is indexed for a node
+    pub fn holds(&self, node: &str, body: &str) -> bool {
+        self.docs
+            .get(node)
+            .is_some_and(|doc| doc.lines.iter().map(String::as_str).eq(body.lines()))
+    }
+    /// Drop a node from the index
+    pub fn remove(&mut self, node: &str) {
+        if let Some(doc) = self.docs.remove(node) {
+            for term in doc.terms {
+                if let Some(postings) = self.terms.get_mut(&term) {
+                    postings.remove(node);
+                    if postings.is_empty() {
+                        self.terms.remove(&term);
+                    }
+                }
+            }
+        }
+    }
+    /// Postings per node for a single clause
+    fn matches(&self, clause: &Clause) -> HashMap<NodeKey, Vec<Posting>> {
+        match clause {
+            Clause::Term(term) => self.terms.get(term).cloned().unwrap_or_default(),
+            Clause::Prefix(prefix) => {
+                let mut found: HashMap<NodeKey, Vec<Posting>> = HashMap::new();
+                for (_, postings) in self
+                    .terms
+                    .range(prefix.clone()..)
+                    .take_while(|(term, _)| term.starts_with(prefix.as_str()))
+                {
+                    for (node, hits) in postings {
+                        found.entry(node.clone()).or_default().extend(hits);
+                    }
+                }
+                found
+            }
+            Clause::Phrase(words) => {
+                let mut found = HashMap::new();
+                let first = match self.terms.get(&words[0]) {
+                    Some(postings) => postings,
+                    None => return found,
+                };
+                for (node, starts) in first {
+                    let rest: Option<Vec<HashSet<usize>>> = words[1..]
+                        .iter()
+                        .map(|w| {
+                            self.terms
+                                .get(w)
+                                .and_then(|p| p.get(node))
+                                .map(|hits| hits.iter().map(|h| h.position).collect())
+                        })
+                        .collect();
+                    let rest = match rest {
+                        Some(rest) => rest,
+                        None => continue,
+                    };
+                    let hits: Vec<Posting> = starts
+                        .iter()
+                        .filter(|start| {
+                            rest.iter()
+                                .enumerate()
+                                .all(|(i, positions)| positions.contains(&(start.position + i + 1)))
+                        })
+                        .copied()
+                        .collect();
+                    if !hits.is_empty() {
+                        found.insert(node.clone(), hits);
+                    }
+                }
+                found
+            }
+        }
+    }
+    /// Ranked search over the indexed bodies
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        let clauses = parse_query(query);
+        if clauses.is_empty() {
+            return vec![];
+        }
+        let total = self.docs.len() as f64;
+        let mut scored: Option<HashMap<NodeKey, (f64, usize)>> = None;
+        for clause in &clauses {
+            let matched = self.matches(clause);
+            let idf = (1.0 + total / (matched.len() as f64 + 1.0)).ln();
+            let clause_scores: HashMap<NodeKey, (f64, usize)> = matched
+                .into_iter()
+                .map(|(node, hits)| {
+                    let line = hits.iter().map(|h| h.line).min().unwrap_or(1);
+                    let score = (1.0 + hits.len() as f64).ln() * idf;
+                    (node, (score, line))
+                })
+                .collect();
+            scored = Some(match scored {
+                None => clause_scores,
+                Some(prior) => prior
+                    .into_iter()
+                    .filter_map(|(node, (score, line))| {
+                        clause_scores
+                            .get(&node)
+                            .map(|(s, l)| (node, (score + s, line.min(*l))))
+                    })
+                    .collect(),
+            });
+        }
+        let mut hits: Vec<SearchHit> = scored
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(node, (score, line))| SearchHit {
+                snippet: self.snippet(&node, line),
+                node,
+                line: line as u64,
+                score,
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.node.cmp(&b.node))
+        });
+        hits
+    }
+    fn snippet(&self, node: &str, line: usize) -> String {
+        self.docs
+            .get(node)
+            .and_then(|doc| doc.lines.get(line - 1))
+            .map(|text| text.trim().chars().take(SNIPPET_LEN).collect())
+            .unwrap_or_default()
+    }
+}
+
+#[test]
+fn test_parse_query() {
+    assert_eq!(
+        parse_query("Jazz \"blue  Note\" bird*"),
+        vec![
+            Clause::Term("jazz".to_string()),
+            Clause::Phrase(vec!["blue".to_string(), "note".to_string()]),
+            Clause::Prefix("bird".to_string()),
+        ]
+    );
+    assert_eq!(parse_query("  "), vec![]);
+}
+
+#[test]
+fn test_search_index() {
+    let mut index = SearchIndex::new();
+    index.insert("1-a", "# a\nKind of Blue\nblue train");
+    index.insert("2-b", "# b\nblue note records");
+    assert_eq!(index.search("BLUE").len(), 2);
+    let hits = index.search("\"kind of blue\"");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].node, "1-a");
+    assert_eq!(hits[0].line, 2);
+    assert_eq!(hits[0].snippet, "Kind of Blue");
+    assert_eq!(index.search("rec*")[0].node, "2-b");
+    assert!(index.search("blue train records").is_empty());
+    index.insert("2-b", "# b\nnothing here");
+    assert!(index.search("records").is_empty());
+    index.remove("1-a");
+    assert!(index.search("blue").is_empty());
+}
@@ -0,0 +1,232 @@
+use super::{Error, Result, Tree};
+use crate::node::{format_display_name, Node, NodeKey};
+use crate::nvim::Telescoped;
+use chrono::{DateTime, Duration, Local};
+use nvim_rs::Value;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::convert::TryFrom;
+
+/// A node and how many links point at it and out of it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hub {
+    pub node: NodeKey,
+    pub links_in: usize,
+    pub links_out: usize,
+}
+
+impl Telescoped for Hub {
+    fn entry(&self) -> Value {
+        Value::from(vec![
+            (Value::from("id"), Value::from(self.node.as_str())),
+            (
+                Value::from("display"),
+                Value::from(format!(
+                    "{} ({} in, {} out)",
+                    format_display_name(&self.node),
+                    self.links_in,
+                    self.links_out
+                )),
+            ),
+            (Value::from("links_in"), Value::from(self.links_in as u64)),
+            (Value::from("links_out"), Value::from(self.links_out as u64)),
+        ])
+    }
+}
+
+/// A node left alone for a while that is linked with nodes worked on today
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleNode {
+    pub node: NodeKey,
+    pub updated: DateTime<Local>,
+    /// Nodes updated today on the other end of its links
+    pub linked: Vec<NodeKey>,
+}
+
+impl Telescoped for StaleNode {
+    fn entry(&self) -> Value {
+        let linked: Vec<String> = self
+            .linked
+            .iter()
+            .map(|key| format_display_name(key))
+            .collect();
+        Value::from(vec![
+            (Value::from("id"), Value::from(self.node.as_str())),
+            (
+                Value::from("display"),
+                Value::from(format!(
+                    "{} ({}) -> {}",
+                    format_display_name(&self.node),
+                    self.updated.format("%Y-%m-%d"),
+                    linked.join(", ")
+                )),
+            ),
+            (
+                Value::from("updated"),
+                Value::from(self.updated.to_rfc3339()),
+            ),
+            (
+                Value::from("linked"),
+                Value::Array(
+                    self.linked
+                        .iter()
+                        .map(|key| Value::from(key.as_str()))
+                        .collect(),
+                ),
+            ),
+        ])
+    }
+}
+
+impl Tree {
+    /// Nodes joined by a link in either direction, links to nodes that
+    /// no longer exist are left out
+    fn link_neighbours(&self) -> BTreeMap<&NodeKey, BTreeSet<&NodeKey>> {
+        let mut neighbours: BTreeMap<&NodeKey, BTreeSet<&NodeKey>> = BTreeMap::new();
+        for node in self.nodes.values() {
+            let linked = node.links.values().chain(node.backlinks.values());
+            for link in linked {
+                if let Some((target, _)) = self.nodes.get_key_value(&link.node) {
+                    if *target != node.id {
+                        neighbours.entry(&node.id).or_default().insert(target);
+                        neighbours.entry(target).or_default().insert(&node.id);
+                    }
+                }
+            }
+        }
+        neighbours
+    }
+    /// Nodes without any link in or out. Root nodes only hold the tree
+    /// together and are never orphans.
+    pub fn orphans(&self) -> Vec<&Node> {
+        let neighbours = self.link_neighbours();
+        self.nodes
+            .values()
+            .filter(|node| node.parent.is_some() && !neighbours.contains_key(&node.id))
+            .collect()
+    }
+    /// The `count` nodes with the most links in and out, most linked first.
+    /// Links within a node, like `[[#Section]]`, don't count.
+    pub fn hubs(&self, count: usize) -> Vec<Hub> {
+        // counted from links, backlinks with the same text and timestamp
+        // from different nodes share a key
+        let mut counts: BTreeMap<&NodeKey, (usize, usize)> = BTreeMap::new();
+        for node in self.nodes.values() {
+            for link in node.links.values() {
+                if link.node != node.id && self.nodes.contains_key(&link.node) {
+                    counts.entry(&node.id).or_default().1 += 1;
+                    counts.entry(&link.node).or_default().0 += 1;
+                }
+            }
+        }
+        let mut hubs: Vec<Hub> = counts
+            .into_iter()
+            .map(|(node, (links_in, links_out))| Hub {
+                node: node.clone(),
+                links_in,
+                links_out,
+            })
+            .collect();
+        // stable, so ties stay in key order
+        hubs.sort_by_key(|hub| Reverse(hub.links_in + hub.links_out));
+        hubs.truncate(count);
+        hubs
+    }
+    /// The fewest links to follow, in either direction, to get from one
+    /// node to another, both ends included. None when they aren't linked
+    /// at all.
+    pub fn shortest_path(&self, from: &str, to: &str) -> Result<Option<Vec<NodeKey>>> {
+        for key in [from, to].iter() {
+            if !self.nodes.contains_key(*key) {
+                return Err(Error::NodeNotFound(key.to_string()));
+            }
+        }
+        let neighbours = self.link_neighbours();
+        let from = &from.to_string();
+        let mut came_from: BTreeMap<&NodeKey, &NodeKey> = BTreeMap::new();
+        let mut queue: VecDeque<&NodeKey> = VecDeque::from(vec![from]);
+        let mut seen: BTreeSet<&NodeKey> = BTreeSet::new();
+        seen.insert(from);
+        while let Some(key) = queue.pop_front() {
+            if key == to {
+                let mut path = vec![key.clone()];
+                let mut step = key;
+                while let Some(previous) = came_from.get(step) {
+                    path.push(previous.to_string());
+                    step = previous;
+                }
+                path.reverse();
+                return Ok(Some(path));
+            }
+            for next in neighbours.get(key).into_iter().flatten() {
+                if seen.insert(next) {
+                    came_from.insert(next, key);
+                    queue.push_back(next);
+                }
+            }
+        }
+        Ok(None)
+    }
+    /// Groups of nodes reachable from each other through links, biggest
+    /// first. Nodes without links are left to [`Tree::orphans`].
+    pub fn link_clusters(&self) -> Vec<Vec<NodeKey>> {
+        let neighbours = self.link_neighbours();
+        let mut seen: BTreeSet<&NodeKey> = BTreeSet::new();
+        let mut clusters = vec![];
+        for start in neighbours.keys() {
+            if !seen.insert(start) {
+                continue;
+            }
+            let mut cluster = vec![];
+            let mut stack = vec![*start];
+            while let Some(key) = stack.pop() {
+                cluster.push(key.clone());
+                for next in &neighbours[key] {
+                    if seen.insert(next) {
+                        stack.push(next);
+                    }
+                }
+            }
+            cluster.sort();
+            clusters.push(cluster);
+        }
+        clusters.sort_by_key(|cluster| Reverse(cluster.len()));
+        clusters
+    }
+    /// Nodes not updated in `days` that share a link, either way, with a
+    /// node updated today. Longest untouched first.
+    pub fn stale_linked_to_today(&self, days: u64) -> Result<Vec<StaleNode>> {
+        let now = Local::now();
+        let today = now.date_naive();
+        // a Duration holds at most i64::MAX milliseconds
+        let cutoff = i64::try_from(days)
+            .ok()
+            .filter(|days| *days <= i64::MAX / 86_400_000)
+            .and_then(|days| now.checked_sub_signed(Duration::days(days)))
+            .ok_or_else(|| Error::Args(format!("{} days is too far back", days)))?;
+        let neighbours = self.link_neighbours();
+        let mut stale: Vec<StaleNode> = self
+            .nodes
+            .values()
+            .filter(|node| node.updated < cutoff)
+            .filter_map(|node| {
+                let linked: Vec<NodeKey> = neighbours
+                    .get(&node.id)?
+                    .iter()
+                    .filter(|key| self.nodes[**key].updated.date_naive() == today)
+                    .map(|key| key.to_string())
+                    .collect();
+                if linked.is_empty() {
+                    return None;
+                }
+                Some(StaleNode {
+                    node: node.id.clone(),
+                    updated: node.updated,
+                    linked,
+                })
+            })
+            .collect();
+        stale.sort_by_key(|stale| stale.updated);
+        Ok(stale)
+    }
+}
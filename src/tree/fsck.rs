@@ -0,0 +1,493 @@
+use super::{get_parent, key_width, NodeFilesMissing, Result, Tree};
+use crate::node::{format_display_name, NodeKey, NodeLink, NodeMeta};
+use crate::nvim::Telescoped;
+use log::*;
+use nvim_rs::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::{read_dir, read_to_string, write};
+use std::path::Path;
+
+/// Something wrong with the codex on disk (or with a loaded tree)
+#[derive(Debug)]
+pub enum Problem {
+    MissingFiles(NodeFilesMissing),
+    MalformedMeta {
+        node: NodeKey,
+        error: String,
+    },
+    BadNodeKey {
+        path: String,
+    },
+    MissingRoot {
+        tag: String,
+    },
+    DanglingLink {
+        node: NodeKey,
+        text: String,
+        target: NodeKey,
+    },
+    MissingBacklink {
+        node: NodeKey,
+        text: String,
+        target: NodeKey,
+    },
+    OrphanedBacklink {
+        node: NodeKey,
+        text: String,
+        timestamp: i64,
+        source: NodeKey,
+    },
+    NumberingGap {
+        parent: Option<NodeKey>,
+        numbers: Vec<u64>,
+    },
+    InconsistentPadding {
+        parent: Option<NodeKey>,
+        width: usize,
+        nodes: Vec<NodeKey>,
+    },
+    FamilyMismatch {
+        parent: NodeKey,
+        child: NodeKey,
+    },
+}
+use Problem::*;
+
+impl Problem {
+    /// The node a problem should be looked at from
+    pub fn node(&self) -> Option<&str> {
+        match self {
+            MissingFiles(missing) => Some(&missing.node),
+            MalformedMeta { node, .. }
+            | DanglingLink { node, .. }
+            | MissingBacklink { node, .. }
+            | OrphanedBacklink { node, .. } => Some(node),
+            NumberingGap { parent, .. } | InconsistentPadding { parent, .. } => parent.as_deref(),
+            FamilyMismatch { parent, .. } => Some(parent),
+            BadNodeKey { .. } | MissingRoot { .. } => None,
+        }
+    }
+    /// Whether `repair` knows how to safely fix this problem
+    pub fn is_repairable(&self) -> bool {
+        match self {
+            MissingFiles(_) | MissingBacklink { .. } | OrphanedBacklink { .. } => true,
+            NumberingGap { parent, .. } | InconsistentPadding { parent, .. } => parent.is_some(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let family = |parent: &Option<NodeKey>| match parent {
+            Some(parent) => format!("children of {}", parent),
+            None => "root nodes".to_string(),
+        };
+        match self {
+            MissingFiles(missing) => write!(f, "{}", missing),
+            MalformedMeta { node, error } => {
+                write!(f, "Malformed `meta.toml` for {}: {}", node, error)
+            }
+            BadNodeKey { path } => write!(f, "{} is not named like a node (`N-name`)", path),
+            MissingRoot { tag } => write!(f, "No root node is tagged `{}`", tag),
+            DanglingLink { node, text, target } => {
+                write!(f, "{} links [[{}]] to missing node {}", node, text, target)
+            }
+            MissingBacklink { node, text, target } => {
+                write!(
+                    f,
+                    "{} links [[{}]] to {} without a backlink",
+                    node, text, target
+                )
+            }
+            OrphanedBacklink {
+                node, text, source, ..
+            } => write!(
+                f,
+                "{} has a backlink [[{}]] from {} without a matching link",
+                node, text, source
+            ),
+            NumberingGap { parent, numbers } => {
+                write!(f, "{} are numbered {:?}", family(parent), numbers)
+            }
+            InconsistentPadding {
+                parent,
+                width,
+                nodes,
+            } => write!(
+                f,
+                "{} should be zero padded to width {}: {:?}",
+                family(parent),
+                width,
+                nodes
+            ),
+            FamilyMismatch { parent, child } => {
+                write!(
+                    f,
+                    "{} and its child {} disagree on parentage",
+                    parent, child
+                )
+            }
+        }
+    }
+}
+
+impl Telescoped for Problem {
+    fn entry(&self) -> Value {
+        Value::Map(vec![
+            (
+                Value::String("id".into()),
+                match self.node() {
+                    Some(node) => Value::String(node.into()),
+                    None => Value::Nil,
+                },
+            ),
+            (
+                Value::String("display".into()),
+                Value::String(self.to_string().into()),
+            ),
+            (
+                Value::String("repairable".into()),
+                Value::Boolean(self.is_repairable()),
+            ),
+        ])
+    }
+}
+
+/// Everything found wrong in a single pass
+#[derive(Debug, Default)]
+pub struct Report {
+    pub problems: Vec<Problem>,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for problem in &self.problems {
+            writeln!(f, "{}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// Split the last part of a node key into its number and width
+fn key_number(key: &str) -> Option<(u64, usize)> {
+    let tail = match key.rsplit_once('/') {
+        Some((_, tail)) => tail,
+        None => key,
+    };
+    let (num, name) = tail.split_once('-')?;
+    if name.is_empty() || num.is_empty() || !num.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((num.parse().ok()?, num.len()))
+}
+
+fn join_key(parent: Option<&str>, name: &str) -> NodeKey {
+    match parent {
+        Some(parent) => format!("{}/{}", parent, name),
+        None => name.to_string(),
+    }
+}
+
+/// Walk the node directories below `parent`, checking files and numbering
+fn walk(
+    dir: &Path,
+    parent: Option<&str>,
+    metas: &mut BTreeMap<NodeKey, NodeMeta>,
+    report: &mut Report,
+) {
+    let search_dir = match parent {
+        Some(parent) => dir.join(parent),
+        None => dir.to_path_buf(),
+    };
+    let entries = match read_dir(&search_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("fsck unable to read {:?}: {}", search_dir, e);
+            return;
+        }
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| e.file_name().to_str().map(String::from))
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    names.sort();
+    let mut family = vec![];
+    for name in names {
+        let key = join_key(parent, &name);
+        let node_dir = dir.join(&key);
+        let content_file_exists = node_dir.join("_.md").is_file();
+        let metadata_file_exists = node_dir.join("meta.toml").is_file();
+        match key_number(&name) {
+            None => {
+                if content_file_exists || metadata_file_exists {
+                    report.problems.push(BadNodeKey { path: key });
+                }
+                // anything else is not part of the tree (attachments, .git ...)
+                continue;
+            }
+            Some((number, width)) => family.push((key.clone(), number, width)),
+        }
+        if !content_file_exists || !metadata_file_exists {
+            report.problems.push(MissingFiles(NodeFilesMissing {
+                content_file_exists,
+                metadata_file_exists,
+                node: key.clone(),
+            }));
+        }
+        if metadata_file_exists {
+            let meta_path = node_dir.join("meta.toml");
+            match read_to_string(&meta_path)
+                .map_err(|e| e.to_string())
+                .and_then(|toml| NodeMeta::parse(&toml, &meta_path).map_err(|e| e.to_string()))
+            {
+                Ok((meta, _)) => {
+                    metas.insert(key.clone(), meta);
+                }
+                Err(error) => report.problems.push(MalformedMeta {
+                    node: key.clone(),
+                    error,
+                }),
+            }
+        }
+        walk(dir, Some(&key), metas, report);
+    }
+    if family.is_empty() {
+        return;
+    }
+    let mut numbers: Vec<u64> = family.iter().map(|(_, number, _)| *number).collect();
+    numbers.sort_unstable();
+    if numbers
+        .iter()
+        .enumerate()
+        .any(|(idx, number)| *number != idx as u64 + 1)
+    {
+        report.problems.push(NumberingGap {
+            parent: parent.map(String::from),
+            numbers,
+        });
+    }
+    let width = key_width(family.len());
+    let nodes: Vec<NodeKey> = family
+        .into_iter()
+        .filter(|(_, _, w)| *w != width)
+        .map(|(key, _, _)| key)
+        .collect();
+    if !nodes.is_empty() {
+        report.problems.push(InconsistentPadding {
+            parent: parent.map(String::from),
+            width,
+            nodes,
+        });
+    }
+}
+
+fn link_matches(link: &NodeLink, node: &str, text: &str, timestamp: i64) -> bool {
+    link.node == node && link.text == text && link.timestamp == timestamp
+}
+
+fn check_links(metas: &BTreeMap<NodeKey, NodeMeta>, report: &mut Report) {
+    for (key, meta) in metas {
+        for link in &meta.links {
+            match metas.get(&link.node) {
+                None => report.problems.push(DanglingLink {
+                    node: key.clone(),
+                    text: link.text.clone(),
+                    target: link.node.clone(),
+                }),
+                Some(target) => {
+                    if !target
+                        .backlinks
+                        .iter()
+                        .any(|b| link_matches(b, key, &link.text, link.timestamp))
+                    {
+                        report.problems.push(MissingBacklink {
+                            node: key.clone(),
+                            text: link.text.clone(),
+                            target: link.node.clone(),
+                        })
+                    }
+                }
+            }
+        }
+        for backlink in &meta.backlinks {
+            let linked = metas.get(&backlink.node).is_some_and(|source| {
+                source
+                    .links
+                    .iter()
+                    .any(|l| link_matches(l, key, &backlink.text, backlink.timestamp))
+            });
+            if !linked {
+                report.problems.push(OrphanedBacklink {
+                    node: key.clone(),
+                    text: backlink.text.clone(),
+                    timestamp: backlink.timestamp,
+                    source: backlink.node.clone(),
+                });
+            }
+        }
+    }
+}
+
+/// Check the codex rooted at `dir` without modifying anything
+pub fn check(dir: &Path) -> Report {
+    let mut report = Report::default();
+    let mut metas = BTreeMap::new();
+    walk(dir, None, &mut metas, &mut report);
+    for tag in ["journal", "desk"] {
+        let found = metas
+            .iter()
+            .any(|(key, meta)| get_parent(key).is_none() && meta.tags.iter().any(|t| t == tag));
+        if !found {
+            report.problems.push(MissingRoot {
+                tag: tag.to_string(),
+            });
+        }
+    }
+    check_links(&metas, &mut report);
+    report
+}
+
+fn read_meta(dir: &Path, key: &str) -> Result<NodeMeta> {
+    let meta_path = dir.join(key).join("meta.toml");
+    Ok(NodeMeta::parse(&read_to_string(&meta_path)?, &meta_path)?.0)
+}
+
+fn write_meta(dir: &Path, key: &str, meta: &NodeMeta) -> Result<()> {
+    write(dir.join(key).join("meta.toml"), meta.to_toml())?;
+    Ok(())
+}
+
+/// Fix whatever can be fixed without guessing at user content.
+/// Returns the problems that were repaired, run `check` again for
+/// anything left over.
+pub fn repair(dir: &Path) -> Result<Report> {
+    let found = check(dir);
+    // renumbering goes through a loaded tree which needs its root nodes
+    let has_roots = !found
+        .problems
+        .iter()
+        .any(|problem| matches!(problem, MissingRoot { .. }));
+    let mut repaired = Report::default();
+    let mut families = BTreeSet::new();
+    for problem in found.problems {
+        if !problem.is_repairable() {
+            continue;
+        }
+        match &problem {
+            MissingFiles(missing) => {
+                let node_dir = dir.join(&missing.node);
+                let name = match read_meta(dir, &missing.node) {
+                    Ok(meta) => meta.name,
+                    Err(_) => format_display_name(&missing.node)
+                        .rsplit(" / ")
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                };
+                if !missing.metadata_file_exists {
+                    write_meta(dir, &missing.node, &NodeMeta::new(name.clone()))?;
+                }
+                if !missing.content_file_exists {
+                    write(node_dir.join("_.md"), format!("# {}\n", name))?;
+                }
+            }
+            MissingBacklink { node, text, target } => {
+                let source = read_meta(dir, node)?;
+                let link = match source.links.iter().find(|l| &l.text == text) {
+                    Some(link) => link,
+                    None => continue,
+                };
+                let mut meta = read_meta(dir, target)?;
+                meta.backlinks.push(NodeLink {
+                    node: node.clone(),
+                    text: text.clone(),
+                    timestamp: link.timestamp,
+                    line: 0,
+                    char: 0,
+                    is_name_linked: link.is_name_linked,
+                });
+                write_meta(dir, target, &meta)?;
+            }
+            OrphanedBacklink {
+                node,
+                text,
+                timestamp,
+                source,
+            } => {
+                let mut meta = read_meta(dir, node)?;
+                meta.backlinks
+                    .retain(|b| !link_matches(b, source, text, *timestamp));
+                write_meta(dir, node, &meta)?;
+            }
+            NumberingGap {
+                parent: Some(parent),
+                ..
+            }
+            | InconsistentPadding {
+                parent: Some(parent),
+                ..
+            } => {
+                if !has_roots {
+                    continue;
+                }
+                families.insert(parent.clone());
+            }
+            _ => {}
+        }
+        debug!("fsck repaired: {}", problem);
+        repaired.problems.push(problem);
+    }
+    if !families.is_empty() {
+        let mut tree = Tree::build(dir.to_str().unwrap())?;
+        tree.load();
+        // renumber the deepest families first so parent keys stay valid
+        for parent in families.iter().rev() {
+            tree.renumber_children(parent)?;
+        }
+    }
+    Ok(repaired)
+}
+
+impl Tree {
+    /// Check the parent and children of every loaded node agree
+    pub fn check_family(&self) -> Report {
+        let mut report = Report::default();
+        for (key, node) in &self.nodes {
+            for child in &node.children {
+                let agrees = self
+                    .nodes
+                    .get(child)
+                    .is_some_and(|c| c.parent.as_ref() == Some(key));
+                if !agrees {
+                    report.problems.push(FamilyMismatch {
+                        parent: key.clone(),
+                        child: child.clone(),
+                    });
+                }
+            }
+            if let Some(parent) = &node.parent {
+                let agrees = self
+                    .nodes
+                    .get(parent)
+                    .is_some_and(|p| p.children.contains(key));
+                if !agrees {
+                    report.problems.push(FamilyMismatch {
+                        parent: parent.clone(),
+                        child: key.clone(),
+                    });
+                }
+            }
+        }
+        report
+    }
+}
@@ -0,0 +1,160 @@
+use super::{Error, Result, Tree};
+use crate::node::NodeKey;
+use std::collections::BTreeSet;
+
+/// Which relations between nodes become edges of a graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edges {
+    /// Parent to child
+    Hierarchy,
+    /// Node to the nodes its `[[...]]` links point at
+    Links,
+    Both,
+}
+
+impl Edges {
+    pub fn parse(edges: &str) -> Option<Edges> {
+        match edges {
+            "hierarchy" => Some(Edges::Hierarchy),
+            "links" => Some(Edges::Links),
+            "both" => Some(Edges::Both),
+            _ => None,
+        }
+    }
+    fn hierarchy(self) -> bool {
+        self != Edges::Links
+    }
+    fn links(self) -> bool {
+        self != Edges::Hierarchy
+    }
+}
+
+/// What part of the tree a graph covers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphOptions {
+    pub edges: Edges,
+    /// Only this node and the nodes below it
+    pub root: Option<NodeKey>,
+    /// Only nodes carrying this tag or one below it
+    pub tag: Option<String>,
+}
+
+impl Default for GraphOptions {
+    fn default() -> Self {
+        GraphOptions {
+            edges: Edges::Both,
+            root: None,
+            tag: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphNode {
+    pub id: NodeKey,
+    pub name: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EdgeKind {
+    Hierarchy,
+    Link,
+}
+
+impl EdgeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EdgeKind::Hierarchy => "hierarchy",
+            EdgeKind::Link => "link",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GraphEdge {
+    pub from: NodeKey,
+    pub to: NodeKey,
+    pub kind: EdgeKind,
+}
+
+/// Nodes of a tree and the edges between them, both in key order.
+/// Edges only join nodes within the graph.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl Tree {
+    /// The hierarchy and/or link network of the nodes picked by `options`
+    pub fn graph(&self, options: &GraphOptions) -> Result<Graph> {
+        if let Some(root) = &options.root {
+            if !self.nodes.contains_key(root) {
+                return Err(Error::NodeNotFound(root.clone()));
+            }
+        }
+        let tagged: Option<BTreeSet<&NodeKey>> = match &options.tag {
+            Some(tag) => {
+                let tagged = self.nodes_by_tag(tag)?;
+                Some(tagged.into_iter().map(|node| &node.id).collect())
+            }
+            None => None,
+        };
+        let within_root = |key: &NodeKey| match &options.root {
+            Some(root) => key == root || key.starts_with(&format!("{}/", root)),
+            None => true,
+        };
+        let included: BTreeSet<&NodeKey> = self
+            .nodes
+            .keys()
+            .filter(|key| within_root(key))
+            .filter(|key| tagged.as_ref().is_none_or(|tagged| tagged.contains(key)))
+            .collect();
+
+        let mut edges: BTreeSet<GraphEdge> = BTreeSet::new();
+        for key in &included {
+            let node = &self.nodes[*key];
+            let mut edge = |to: &NodeKey, kind: EdgeKind| {
+                if included.contains(to) {
+                    edges.insert(GraphEdge {
+                        from: node.id.clone(),
+                        to: to.clone(),
+                        kind,
+                    });
+                }
+            };
+            if options.edges.hierarchy() {
+                node.children
+                    .iter()
+                    .for_each(|child| edge(child, EdgeKind::Hierarchy));
+            }
+            if options.edges.links() {
+                node.links
+                    .values()
+                    .for_each(|link| edge(&link.node, EdgeKind::Link));
+            }
+        }
+        let nodes = included
+            .into_iter()
+            .map(|key| {
+                // inline tags too, from the tag index
+                let tags = self
+                    .tags
+                    .iter()
+                    .filter(|(_, tagged)| tagged.contains(key))
+                    .map(|(tag, _)| tag.clone())
+                    .collect();
+                GraphNode {
+                    id: key.clone(),
+                    name: self.nodes[key].name.clone(),
+                    tags,
+                }
+            })
+            .collect();
+        Ok(Graph {
+            nodes,
+            edges: edges.into_iter().collect(),
+        })
+    }
+}
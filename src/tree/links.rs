@@ -0,0 +1,181 @@
+use super::{Error, Result, Tree};
+use crate::node::markdown::{extract_links, find_anchor, split_link_text, LinkRef};
+use crate::node::{fold_name, NodeKey, NodeLink};
+use log::*;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::read_to_string;
+
+/// Last part of a node's display name, `2-desk/3-blue-note` is `blue note`
+fn short_name(key: &str) -> String {
+    let last = key.rsplit('/').next().unwrap_or(key);
+    match last.split_once('-') {
+        Some((_, name)) => name.replace('-', " "),
+        None => last.to_string(),
+    }
+}
+
+impl Tree {
+    /// The one node going by `name`, as its name, display name, the last
+    /// part of its key or one of its aliases. Names are compared with
+    /// [`fold_name`] so case, unicode form and word separators don't matter.
+    pub fn resolve_name(&self, name: &str) -> Result<NodeKey> {
+        let folded = fold_name(name);
+        let found: Vec<NodeKey> = self
+            .nodes
+            .values()
+            .filter(|node| {
+                fold_name(&node.name) == folded
+                    || fold_name(&node.display_name) == folded
+                    || fold_name(&short_name(&node.id)) == folded
+                    || node.aliases.iter().any(|alias| fold_name(alias) == folded)
+            })
+            .map(|node| node.id.clone())
+            .collect();
+        match found.len() {
+            0 => Err(Error::NodeNotFound(name.to_string())),
+            1 => Ok(found.into_iter().next().unwrap()),
+            _ => Err(Error::Ambiguous(name.to_string(), found)),
+        }
+    }
+    /// Resolve `[[...]]` text to a node by name, ignoring any `#heading`
+    /// or `^block` anchor
+    pub fn resolve_link_text(&self, text: &str) -> Result<NodeKey> {
+        self.resolve_name(split_link_text(text).0)
+    }
+    /// Resolve link text written in `from`, `[[#Section]]` and
+    /// `[[^abc123]]` without a node name point back into `from`
+    fn resolve_link_from(&self, from: &str, text: &str) -> Result<NodeKey> {
+        match split_link_text(text) {
+            ("", Some(_)) => Ok(from.to_string()),
+            _ => self.resolve_link_text(text),
+        }
+    }
+    /// Where following the link `text` in `node` should land.
+    /// Links with a `#heading` or `^block` anchor are found in the
+    /// target body as it is now, others use the stored line and char.
+    /// Text typed since the last save is resolved by name.
+    pub fn follow_link(&self, node: &str, text: &str) -> Option<(NodeKey, u64, u64)> {
+        let (target, line, char) = match self.nodes.get(node)?.links.get(text) {
+            Some(link) => (link.node.clone(), link.line, link.char),
+            None => (self.resolve_link_from(node, text).ok()?, 0, 0),
+        };
+        let anchor = match split_link_text(text).1 {
+            Some(anchor) => anchor,
+            None => return Some((target, line, char)),
+        };
+        let body = match read_to_string(self.nodes.get(&target)?.content_path()) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("unable to read {}: {}", target, e);
+                return Some((target, line, char));
+            }
+        };
+        match find_anchor(&body, &anchor) {
+            Some((line, char)) => Some((target, line, char)),
+            None => {
+                warn!("{:?} not found in {}", anchor, target);
+                Some((target, line, char))
+            }
+        }
+    }
+    /// Reconcile the links of a node with the `[[...]]` references in its
+    /// `_.md`. Links keep their target while their text is in the body,
+    /// new references are resolved by name and links whose text is gone
+    /// are removed along with the target's backlink.
+    /// Returns whether any link or backlink changed.
+    pub fn sync_links(&mut self, key: &str) -> Result<bool> {
+        let node = match self.nodes.get(key) {
+            Some(node) => node,
+            None => return Err(Error::NodeNotFound(key.to_string())),
+        };
+        let mut refs: Vec<LinkRef> = vec![];
+        for found in extract_links(&read_to_string(node.content_path())?) {
+            // links are keyed by text so only the first reference counts
+            if !refs.iter().any(|r| r.text == found.text) {
+                refs.push(found);
+            }
+        }
+        let existing = node.links.clone();
+        let mut links: HashMap<String, NodeLink> = HashMap::new();
+        let mut backlinks: Vec<(NodeKey, NodeLink)> = vec![];
+        for found in refs {
+            match existing.get(&found.text) {
+                Some(link) if self.nodes.contains_key(&link.node) => {
+                    let backlink = NodeLink {
+                        node: key.to_string(),
+                        line: found.line,
+                        char: found.char,
+                        ..link.clone()
+                    };
+                    backlinks.push((link.node.clone(), backlink));
+                    links.insert(found.text, link.clone());
+                }
+                stale => match self.resolve_link_from(key, &found.text) {
+                    Ok(target) => {
+                        let (mut link, mut backlink) = NodeLink::pair(
+                            found.text.clone(),
+                            key.to_string(),
+                            found.line,
+                            found.char,
+                            target,
+                            0,
+                            0,
+                        );
+                        // found by name, whatever the case or alias used
+                        link.is_name_linked = true;
+                        backlink.is_name_linked = true;
+                        backlinks.push((link.node.clone(), backlink));
+                        links.insert(found.text, link);
+                    }
+                    Err(e) => match stale {
+                        // left dangling for fsck to report
+                        Some(link) => {
+                            links.insert(found.text, link.clone());
+                        }
+                        None => debug!("{} has unresolved link [[{}]]: {}", key, found.text, e),
+                    },
+                },
+            }
+        }
+        let mut touched: BTreeSet<NodeKey> = BTreeSet::new();
+        for (text, link) in &existing {
+            if links.get(text) == Some(link) {
+                continue;
+            }
+            if let Some(target) = self.nodes.get_mut(&link.node) {
+                let id = (link.text.clone(), link.timestamp);
+                if target.backlinks.get(&id).is_some_and(|b| b.node == key) {
+                    target.backlinks.remove(&id);
+                    touched.insert(target.id.clone());
+                }
+            }
+        }
+        for (target, backlink) in backlinks {
+            if let Some(target) = self.nodes.get_mut(&target) {
+                let id = (backlink.text.clone(), backlink.timestamp);
+                if target.backlinks.get(&id) != Some(&backlink) {
+                    target.backlinks.insert(id, backlink);
+                    touched.insert(target.id.clone());
+                }
+            }
+        }
+        let changed = links != existing;
+        if changed {
+            let node = self.nodes.get_mut(key).unwrap();
+            node.links = links;
+            touched.insert(key.to_string());
+        }
+        for touched in &touched {
+            self.nodes[touched].write_meta();
+        }
+        Ok(changed || !touched.is_empty())
+    }
+    /// Reconcile the links of each of `keys` with its body
+    pub fn sync_links_of(&mut self, keys: Vec<NodeKey>) {
+        for key in keys {
+            if let Err(e) = self.sync_links(&key) {
+                error!("unable to sync links of {}: {}", key, e);
+            }
+        }
+    }
+}
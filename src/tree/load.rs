@@ -0,0 +1,290 @@
+use super::Tree;
+use crate::node::markdown::extract_tags;
+use crate::node::tasks::{parse_tasks, Task};
+use crate::node::{Node, NodeKey, NodeMeta};
+use crate::search::IndexedBody;
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::SystemTime;
+use walkdir::{DirEntry, WalkDir};
+
+/// Parsed metadata of every node as of the last load, kept in the git
+/// directory so it is never committed
+pub const CACHE_FILE: &str = ".git/codex-cache.json";
+
+#[derive(Serialize, Deserialize)]
+struct CachedMeta {
+    modified: SystemTime,
+    meta: NodeMeta,
+}
+
+/// What the search, task and tag indexes take from a `_.md`
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct BodyIndex {
+    pub search: IndexedBody,
+    pub tasks: Vec<Task>,
+    pub tags: Vec<String>,
+}
+
+impl BodyIndex {
+    pub fn new(key: &str, body: &str) -> BodyIndex {
+        BodyIndex {
+            search: IndexedBody::new(body),
+            tasks: parse_tasks(key, body),
+            tags: extract_tags(body)
+                .into_iter()
+                .map(|found| found.tag)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedBody {
+    modified: SystemTime,
+    index: BodyIndex,
+}
+
+/// `meta.toml` and the indexed `_.md` of each node keyed by its path and
+/// modified time, only files changed since they were cached are read again
+#[derive(Default, Serialize, Deserialize)]
+pub struct LoadCache {
+    nodes: BTreeMap<NodeKey, CachedMeta>,
+    #[serde(default)]
+    bodies: BTreeMap<NodeKey, CachedBody>,
+    /// Nodes whose metadata `read_nodes` took from the cache
+    #[serde(skip)]
+    reused: BTreeSet<NodeKey>,
+}
+
+/// How many nodes a load parsed and how many came from the cache
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LoadStats {
+    pub parsed: usize,
+    pub cached: usize,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    path.metadata().and_then(|meta| meta.modified()).ok()
+}
+
+impl LoadCache {
+    /// The cache of a codex, empty when missing or unreadable
+    pub fn read(dir: &Path) -> LoadCache {
+        let path = dir.join(CACHE_FILE);
+        let cached = match read_to_string(&path) {
+            Ok(cached) => cached,
+            Err(_) => return LoadCache::default(),
+        };
+        serde_json::from_str(&cached).unwrap_or_else(|e| {
+            warn!("ignoring unreadable load cache {:?}: {}", path, e);
+            LoadCache::default()
+        })
+    }
+    /// Cache the metadata of the nodes of a loaded tree as it is on disk now
+    pub fn update_metas(&mut self, tree: &Tree) {
+        self.nodes = tree
+            .nodes
+            .values()
+            .filter_map(|node| {
+                let modified = modified(&tree.dir.join(&node.id).join("meta.toml"))?;
+                let meta = NodeMeta::from(node);
+                Some((node.id.clone(), CachedMeta { modified, meta }))
+            })
+            .collect();
+    }
+    pub fn write(&self, dir: &Path) {
+        if !dir.join(".git").is_dir() {
+            return;
+        }
+        let path = dir.join(CACHE_FILE);
+        match serde_json::to_string(self) {
+            Ok(cached) => {
+                if let Err(e) = write(&path, cached) {
+                    warn!("unable to write load cache {:?}: {}", path, e);
+                }
+            }
+            Err(e) => warn!("unable to serialize load cache: {}", e),
+        }
+    }
+    fn take(&mut self, key: &str, modified: Option<SystemTime>) -> Option<NodeMeta> {
+        let cached = self.nodes.remove(key)?;
+        if Some(cached.modified) == modified {
+            self.reused.insert(key.to_string());
+            Some(cached.meta)
+        } else {
+            None
+        }
+    }
+    fn take_body(&mut self, key: &str, modified: Option<SystemTime>) -> Option<BodyIndex> {
+        let cached = self.bodies.remove(key)?;
+        if Some(cached.modified) == modified {
+            Some(cached.index)
+        } else {
+            None
+        }
+    }
+}
+
+impl Tree {
+    /// Index the bodies of the nodes just read, taking the entries of an
+    /// unchanged `_.md` from `cache` and caching those read again. Returns
+    /// the nodes whose body or metadata changed since the cache was written.
+    pub(super) fn index_loaded(&mut self, cache: &mut LoadCache) -> BTreeSet<NodeKey> {
+        let mut changed = BTreeSet::new();
+        let mut bodies = BTreeMap::new();
+        let keys: Vec<NodeKey> = self.nodes.keys().cloned().collect();
+        for key in keys {
+            let path = self.nodes[&key].content_path();
+            let modified = modified(&path);
+            let index = match cache.take_body(&key, modified) {
+                Some(index) => index,
+                None => {
+                    changed.insert(key.clone());
+                    match read_to_string(&path) {
+                        Ok(body) => BodyIndex::new(&key, &body),
+                        Err(e) => {
+                            error!("unable to index {}: {}", key, e);
+                            self.reindex_node(&key);
+                            continue;
+                        }
+                    }
+                }
+            };
+            if !cache.reused.contains(&key) {
+                changed.insert(key.clone());
+            }
+            if let Some(modified) = modified {
+                let cached = CachedBody {
+                    modified,
+                    index: index.clone(),
+                };
+                bodies.insert(key.clone(), cached);
+            }
+            self.index_body(&key, index);
+        }
+        cache.bodies = bodies;
+        changed
+    }
+}
+
+fn is_hidden(entry: &DirEntry) -> bool {
+    entry.depth() > 0
+        && entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.starts_with('.'))
+}
+
+/// Parse `meta.toml` files spread over every core, a failed parse is
+/// logged and its node left out
+fn parse_parallel(misses: Vec<(NodeKey, PathBuf)>) -> Vec<(NodeKey, NodeMeta)> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = misses.len().div_ceil(threads).max(1);
+    thread::scope(|scope| {
+        let workers: Vec<_> = misses
+            .chunks(chunk)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .filter_map(|(key, path)| match NodeMeta::from_toml(path) {
+                            Ok(meta) => Some((key.clone(), meta)),
+                            Err(e) => {
+                                error!("skipping node {}: {}", key, e);
+                                None
+                            }
+                        })
+                        .collect::<Vec<(NodeKey, NodeMeta)>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    })
+}
+
+/// Read the node `root` and every node below it from disk, the whole
+/// codex when `root` is None. Metadata is taken from `cache` when its
+/// `meta.toml` is unchanged. Nodes whose metadata can't be read are left
+/// out along with everything below them.
+pub fn read_nodes(
+    dir: &Path,
+    root: Option<&str>,
+    cache: &mut LoadCache,
+) -> (BTreeMap<NodeKey, Node>, LoadStats) {
+    let start = match root {
+        Some(key) => dir.join(key),
+        None => dir.to_path_buf(),
+    };
+    let mut found: Vec<(NodeKey, PathBuf)> = WalkDir::new(&start)
+        .into_iter()
+        .filter_entry(|entry| !is_hidden(entry))
+        // entries can vanish mid walk when the codex is changed under us
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file() && entry.file_name() == "meta.toml")
+        .filter_map(|entry| {
+            let key = entry.path().parent()?.strip_prefix(dir).ok()?.to_str()?;
+            if key.is_empty() {
+                return None;
+            }
+            Some((key.to_string(), entry.into_path()))
+        })
+        .collect();
+    found.sort();
+
+    let mut stats = LoadStats::default();
+    let mut metas: BTreeMap<NodeKey, NodeMeta> = BTreeMap::new();
+    let mut misses = vec![];
+    for (key, path) in found {
+        match cache.take(&key, modified(&path)) {
+            Some(meta) => {
+                metas.insert(key, meta);
+            }
+            None => misses.push((key, path)),
+        }
+    }
+    stats.cached = metas.len();
+    stats.parsed = misses.len();
+    metas.extend(parse_parallel(misses));
+
+    // keys sort parents first, so a missing parent is known before its children
+    let mut loaded: BTreeSet<NodeKey> = BTreeSet::new();
+    for key in metas.keys() {
+        let parent_loaded = Some(key.as_str()) == root
+            || match key.rsplit_once('/') {
+                Some((parent, _)) => loaded.contains(parent),
+                None => true,
+            };
+        if parent_loaded {
+            loaded.insert(key.clone());
+        }
+    }
+    let mut children: BTreeMap<NodeKey, Vec<NodeKey>> = BTreeMap::new();
+    for key in &loaded {
+        if let Some((parent, _)) = key.rsplit_once('/') {
+            children
+                .entry(parent.to_string())
+                .or_default()
+                .push(key.clone());
+        }
+    }
+    let directory = dir.to_str().unwrap();
+    let nodes = metas
+        .into_iter()
+        .filter(|(key, _)| loaded.contains(key))
+        .map(|(key, meta)| {
+            let parent = key.rsplit_once('/').map(|(parent, _)| parent.to_string());
+            let node_children = children.remove(&key).unwrap_or_default();
+            let node = Node::from_meta(key.clone(), meta, parent, node_children, directory);
+            (key, node)
+        })
+        .collect();
+    (nodes, stats)
+}
@@ -0,0 +1,133 @@
+use super::{Error, Result, Tree};
+use crate::node::markdown::find_mentions;
+use crate::node::NodeKey;
+use crate::nvim::Telescoped;
+use log::*;
+use nvim_rs::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{read_to_string, write};
+
+/// A node's name or alias written in another node without being a link
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mention {
+    /// Node the mention was found in
+    pub node: NodeKey,
+    pub text: String,
+    pub line: u64,
+    pub char: u64,
+    /// The whole line around the mention
+    pub context: String,
+}
+
+impl Telescoped for Mention {
+    fn entry(&self) -> Value {
+        Value::from(vec![
+            (Value::from("id"), Value::from(self.node.as_str())),
+            (
+                Value::from("display"),
+                Value::from(format!("{}:{}: {}", self.node, self.line, self.context)),
+            ),
+            (Value::from("text"), Value::from(self.text.as_str())),
+            (Value::from("line"), Value::from(self.line)),
+            (Value::from("char"), Value::from(self.char)),
+            (Value::from("context"), Value::from(self.context.as_str())),
+        ])
+    }
+}
+
+/// Wrap each mention on its line in `[[...]]`, returning the new body
+/// and where each mention's link now starts, in the order given
+fn wrap_mentions(body: &str, mentions: &[&Mention]) -> (String, Vec<u64>) {
+    let mut lines: Vec<String> = body.split_inclusive('\n').map(String::from).collect();
+    let mut by_line: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+    for (idx, mention) in mentions.iter().enumerate() {
+        by_line.entry(mention.line).or_default().push(idx);
+    }
+    let mut chars = vec![0; mentions.len()];
+    for (line, mut on_line) in by_line {
+        on_line.sort_by_key(|idx| mentions[*idx].char);
+        let text = &mut lines[line as usize - 1];
+        // wrap back to front so earlier columns stay put
+        for idx in on_line.iter().rev() {
+            let (start, len) = (mentions[*idx].char as usize, mentions[*idx].text.len());
+            text.insert_str(start + len, "]]");
+            text.insert_str(start, "[[");
+        }
+        // every earlier mention on the line adds 4 bytes of brackets
+        for (before, idx) in on_line.iter().enumerate() {
+            chars[*idx] = mentions[*idx].char + 4 * before as u64;
+        }
+    }
+    (lines.concat(), chars)
+}
+
+impl Tree {
+    /// Names a node could be mentioned by, its name and aliases
+    fn mention_names(&self, key: &str) -> Result<Vec<String>> {
+        let node = self
+            .nodes
+            .get(key)
+            .ok_or_else(|| Error::NodeNotFound(key.to_string()))?;
+        Ok(std::iter::once(node.name.clone())
+            .chain(node.aliases.iter().cloned())
+            .collect())
+    }
+    /// Every place another node's body names `key` in plain text, that is
+    /// not already a link, in key and then document order
+    pub fn unlinked_mentions(&self, key: &str) -> Result<Vec<Mention>> {
+        let names = self.mention_names(key)?;
+        let mut mentions = vec![];
+        for node in self.nodes.values().filter(|node| node.id != key) {
+            let body = match read_to_string(node.content_path()) {
+                Ok(body) => body,
+                Err(e) => {
+                    warn!("unable to read {} for mentions: {}", node.id, e);
+                    continue;
+                }
+            };
+            let lines: Vec<&str> = body.lines().collect();
+            mentions.extend(
+                find_mentions(&body, &names)
+                    .into_iter()
+                    .map(|found| Mention {
+                        node: node.id.clone(),
+                        context: lines[found.line as usize - 1].trim().to_string(),
+                        text: found.text,
+                        line: found.line,
+                        char: found.char,
+                    }),
+            );
+        }
+        Ok(mentions)
+    }
+    /// Turn the unlinked mentions of `key` found at `picked` node, line and
+    /// char into `[[...]]` links to it, with backlinks on `key`.
+    /// Picks that are no longer a mention are skipped.
+    /// Returns how many mentions were linked.
+    pub fn link_mentions(&mut self, key: &str, picked: &[(NodeKey, u64, u64)]) -> Result<usize> {
+        let mentions: Vec<Mention> = self
+            .unlinked_mentions(key)?
+            .into_iter()
+            .filter(|mention| picked.contains(&(mention.node.clone(), mention.line, mention.char)))
+            .collect();
+        let mut by_node: BTreeMap<&NodeKey, Vec<&Mention>> = BTreeMap::new();
+        for mention in &mentions {
+            by_node.entry(&mention.node).or_default().push(mention);
+        }
+        for (node, found) in by_node {
+            let path = self.nodes[node].content_path();
+            let (body, chars) = wrap_mentions(&read_to_string(&path)?, &found);
+            write(&path, body)?;
+            // links are keyed by text so only the first reference counts
+            let mut linked: BTreeSet<&str> = BTreeSet::new();
+            for (mention, char) in found.iter().zip(chars) {
+                if linked.insert(&mention.text) {
+                    self.link(&mention.text, node, mention.line, char, key, 0, 0);
+                }
+            }
+            self.reindex_node(node);
+        }
+        debug!("linked {} mention(s) of {}", mentions.len(), key);
+        Ok(mentions.len())
+    }
+}
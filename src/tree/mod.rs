@@ -1,25 +1,88 @@
-use crate::git::stage_all;
-use crate::node::{power_of_ten, prepare_path_name, Node, NodeKey, NodeLink};
-use chrono::Local;
+use crate::config::{Config, JournalConfig};
+use crate::git::{stage_paths_in, stage_removal};
+use crate::node::tasks::{migrate_tasks, sort_tasks, Task, TaskFilter};
+use crate::node::{format_display_name, power_of_ten, ranked_key, Node, NodeKey, NodeLink};
+use crate::nvim::Telescoped;
+use crate::search::{SearchHit, SearchIndex};
+use chrono::format::{parse, Parsed, StrftimeItems};
+use chrono::{Local, NaiveDate};
 use git2::Repository;
 use log::*;
 use nvim_rs::Value;
-use regex::Regex;
-use std::collections::BTreeMap;
-use std::error;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt;
-use std::fs::{read_to_string, rename, write};
+use std::fs::{read_to_string, remove_dir_all, write};
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
+pub mod analytics;
+pub mod fsck;
+pub mod graph;
+pub mod links;
+pub mod load;
+pub mod mentions;
+pub mod tags;
+pub mod templates;
+pub mod watch;
 
-pub type Result<T> = std::result::Result<T, Box<dyn error::Error>>;
+use load::{read_nodes, BodyIndex, LoadCache, LoadStats};
+
+pub use crate::error::{Error, NodeFilesMissing, Result};
 
 #[derive(Debug)]
 pub struct Tree {
     pub nodes: BTreeMap<NodeKey, Node>,
     pub journal: NodeKey,
     pub desk: NodeKey,
+    pub archive: Option<NodeKey>,
     pub dir: PathBuf,
+    pub index: SearchIndex,
+    /// Tasks in the body of every node
+    pub tasks: BTreeMap<NodeKey, Vec<Task>>,
+    /// Nodes carrying each tag, in `meta.toml` or inline in the body
+    pub tags: BTreeMap<String, BTreeSet<NodeKey>>,
+    pub config: Config,
+}
+
+/// A link left pointing at a node that no longer exists
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DanglingLink {
+    pub node: NodeKey,
+    pub text: String,
+    pub target: NodeKey,
+    pub line: u64,
+    pub char: u64,
+}
+
+impl Telescoped for DanglingLink {
+    fn entry(&self) -> Value {
+        Value::Map(vec![
+            (
+                Value::String("id".into()),
+                Value::String(self.node.clone().into()),
+            ),
+            (
+                Value::String("display".into()),
+                Value::String(format_display_name(&self.node).into()),
+            ),
+            (
+                Value::String("text".into()),
+                Value::String(self.text.clone().into()),
+            ),
+            (
+                Value::String("target".into()),
+                Value::String(self.target.clone().into()),
+            ),
+            (Value::String("line".into()), Value::from(self.line)),
+            (Value::String("char".into()), Value::from(self.char)),
+        ])
+    }
+}
+
+/// Outcome of deleting a node
+#[derive(Debug, Default)]
+pub struct Removal {
+    pub removed: Vec<NodeKey>,
+    pub dangling: Vec<DanglingLink>,
 }
 
 impl Drop for Tree {
@@ -40,59 +103,6 @@ impl fmt::Display for Tree {
     }
 }
 
-pub struct TreeError {
-    err_text: String,
-}
-
-impl fmt::Display for TreeError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.err_text)
-    }
-}
-
-impl fmt::Debug for TreeError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "TreeError( {} )", self.err_text)
-    }
-}
-
-impl error::Error for TreeError {}
-
-pub struct NodeFilesMissingError {
-    content_file_exists: bool,
-    metadata_file_exists: bool,
-    node: String,
-}
-
-impl NodeFilesMissingError {
-    fn err_text(&self) -> String {
-        format!(
-            "{} {}",
-            match (self.content_file_exists, self.metadata_file_exists) {
-                (false, true) => "Missing `_.md` for ",
-                (true, false) => "Missing `meta.toml` for ",
-                _ => "Missing `_.md` and `meta.toml` for ",
-            }
-            .to_string(),
-            self.node
-        )
-    }
-}
-
-impl fmt::Display for NodeFilesMissingError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.err_text())
-    }
-}
-
-impl fmt::Debug for NodeFilesMissingError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "NodeFilesMissingError( {} )", self.err_text())
-    }
-}
-
-impl error::Error for NodeFilesMissingError {}
-
 /// Used to find the next id for root nodes
 pub fn next_sibling_id(key: &PathBuf) -> u64 {
     let metas = WalkDir::new(key)
@@ -132,6 +142,11 @@ pub fn get_parent(key: &NodeKey) -> Option<NodeKey> {
     }
 }
 
+/// Zero padding width needed to number a family of `siblings`
+pub fn key_width(siblings: usize) -> usize {
+    siblings.max(1).to_string().len()
+}
+
 fn get_node_key_number(node_key: &NodeKey) -> u64 {
     // should be a result?
     let (_base, node) = node_key.as_str().rsplit_once('/').unwrap();
@@ -139,148 +154,158 @@ fn get_node_key_number(node_key: &NodeKey) -> u64 {
     x.parse::<u64>().unwrap()
 }
 
+/// Whether `name` is a date written with the strftime string `format`
+fn matches_format(name: &str, format: &str) -> bool {
+    parse(&mut Parsed::new(), name, StrftimeItems::new(format)).is_ok()
+}
+
 impl Tree {
-    pub fn load(self: &mut Tree) {
-        fn dfs(
-            name: Option<NodeKey>,
-            node_map: &mut BTreeMap<NodeKey, Node>,
-            parent: Option<NodeKey>,
-            journal: &mut Option<NodeKey>,
-            desk: &mut Option<NodeKey>,
-            base: &Path,
-        ) {
-            let n = base.to_str().unwrap().chars().count() + 1; // plus one to factor the / char
-            let search_dir = match name.clone() {
-                None => base.to_path_buf(),
-                Some(name_path) => base.join(PathBuf::from(name_path)),
-            };
-            dbg!(&base, &search_dir);
-            debug!("{:?} {:?}", &base, &search_dir);
-            let children = WalkDir::new(search_dir)
-                .sort_by_file_name()
-                .contents_first(true)
-                .min_depth(2)
-                .max_depth(2)
-                .into_iter()
-                .map(|entry| entry.unwrap())
-                .filter(|path| path.file_name().to_str().unwrap().ends_with("meta.toml"))
-                .map(|e| {
-                    e.into_path()
-                        .parent()
-                        .unwrap()
-                        // .file_name()
-                        // .unwrap()
-                        .to_str()
-                        .unwrap()
-                        // .to_string()
-                        .chars()
-                        .skip(n)
-                        .collect::<String>()
-                })
-                .collect::<Vec<NodeKey>>();
-            dbg!(&children);
-            dbg!(&base);
-            for node in children.iter() {
-                dfs(
-                    Some(node.clone()),
-                    node_map,
-                    name.clone(),
-                    journal,
-                    desk,
-                    &base,
-                );
-            }
-            match name {
-                Some(namepath) => {
-                    // debug!("{:?}", &namepath);
-                    let meta_path = base.join(&namepath).join("meta.toml");
-                    let node = Node::from_tree(
-                        namepath,
-                        &meta_path,
-                        parent,
-                        children,
-                        base.to_str().unwrap(),
-                    );
-                    if journal.is_none() && node.tags.contains("journal") {
-                        *journal = Some(node.id.clone());
-                    }
-                    if desk.is_none() && node.tags.contains("desk") {
-                        *desk = Some(node.id.clone());
-                    }
-                    // debug!("{}", &node);
-                    node_map.insert(node.id.clone(), node);
-                }
-                None => {}
-            }
+    /// Read the config again and load every node from disk, an invalid
+    /// config is logged and the previous one kept
+    pub fn load(self: &mut Tree) -> LoadStats {
+        if let Err(e) = self.reload_config() {
+            error!("{}, keeping the previous config", e);
         }
-        let mut node_map: BTreeMap<NodeKey, Node> = BTreeMap::new();
-        let mut journal: Option<NodeKey> = None;
-        let mut desk: Option<NodeKey> = None;
-        dfs(
-            None,
-            &mut node_map,
-            None,
-            &mut journal,
-            &mut desk,
-            &self.dir,
-        );
-        self.nodes = node_map;
-        self.journal = journal.unwrap();
-        self.desk = desk.unwrap();
+        self.load_nodes()
+    }
+    /// Load every node from disk, reusing the metadata cached by the last
+    /// load for nodes whose `meta.toml` is unchanged
+    pub fn load_nodes(&mut self) -> LoadStats {
+        let mut cache = LoadCache::read(&self.dir);
+        let (nodes, stats) = read_nodes(&self.dir, None, &mut cache);
+        debug!("loaded {} nodes: {:?}", nodes.len(), stats);
+        self.nodes = nodes;
+        let root_tagged = |tag: &str| {
+            self.nodes
+                .values()
+                .find(|node| node.parent.is_none() && node.tags.contains(tag))
+                .map(|node| node.id.clone())
+        };
+        let (journal, desk) = (root_tagged("journal"), root_tagged("desk"));
+        // a codex missing its roots loads with empty keys, see fsck
+        self.journal = journal.unwrap_or_default();
+        self.desk = desk.unwrap_or_default();
+        self.archive = self
+            .nodes
+            .values()
+            .find(|node| node.parent.is_none() && node.tags.contains("archive"))
+            .map(|node| node.id.clone());
+        self.index = SearchIndex::new();
+        self.tasks = BTreeMap::new();
+        self.tags = BTreeMap::new();
+        let changed = self.index_loaded(&mut cache);
+        // links of an unchanged body are in sync unless their target changed
+        let stale: Vec<NodeKey> = self
+            .nodes
+            .values()
+            .filter(|node| {
+                changed.contains(&node.id)
+                    || node.links.values().any(|link| {
+                        changed.contains(&link.node) || !self.nodes.contains_key(&link.node)
+                    })
+            })
+            .map(|node| node.id.clone())
+            .collect();
+        debug!("syncing the links of {} changed nodes", stale.len());
+        self.sync_links_of(stale);
+        cache.update_metas(self);
+        cache.write(&self.dir);
+        stats
+    }
+    /// Read the config of the codex again, the current config is kept
+    /// when the files are invalid
+    pub fn reload_config(&mut self) -> Result<()> {
+        self.config = Config::from_dir(&self.dir)?;
+        Ok(())
     }
     pub fn build(root: &str) -> Result<Tree> {
-        let mut node_map: BTreeMap<NodeKey, Node> = BTreeMap::new();
-        dbg!(root);
         assert_ne!(root.chars().last().unwrap(), '/');
         Ok(Tree {
-            nodes: node_map,
+            nodes: BTreeMap::new(),
             journal: NodeKey::new(),
             desk: NodeKey::new(),
+            archive: None,
             dir: PathBuf::from(root),
+            index: SearchIndex::new(),
+            tasks: BTreeMap::new(),
+            tags: BTreeMap::new(),
+            config: Config::default(),
         })
     }
-    pub fn today_node(&mut self) -> NodeKey {
-        // TODO: some way for user to pass in strf string
-        let today = Local::now().format("%a %b %d %Y");
-        let journal = self.journal.clone();
-        let journal_node = self.nodes.get(&self.journal).unwrap();
-        if journal_node.children.is_empty() {
-            debug!("creating first day node: {}", &today);
-            return self
-                .create_node(Some(&journal), Some(&today.to_string()))
-                .unwrap();
+    pub fn today_node(&mut self) -> Result<NodeKey> {
+        self.journal_day(Local::now().date_naive())
+    }
+    /// The journal node for `date`, created along with any missing
+    /// year, month or week nodes configured in `[journal]`.
+    /// A newly created day gets the open todos of the previous day.
+    pub fn journal_day(&mut self, date: NaiveDate) -> Result<NodeKey> {
+        // a codex missing its journal root loads with an empty key
+        if !self.nodes.contains_key(&self.journal) {
+            return Err(Error::NodeNotFound(format!(
+                "journal root {:?}",
+                self.journal
+            )));
         }
-        let newest_child = journal_node.children[journal_node.children.len() - 1].clone();
-        debug!(
-            "newest child {} today {}",
-            newest_child,
-            prepare_path_name(&today.to_string())
-        );
-        if newest_child.ends_with(&prepare_path_name(&today.to_string())) {
-            newest_child
-        } else {
-            debug!("creating new day node for {}", &today);
-            let today = self
-                .create_node(Some(&journal), Some(&today.to_string()))
-                .unwrap();
-            let node = self.nodes.get(&journal).unwrap();
-            let yesterday = newest_child;
-            rollover_todos_from_yesterday(&yesterday, &today);
-            today
+        let mut previous = self.latest_journal();
+        let path = self.config.journal.path_for(date);
+        let mut node = self.journal.clone();
+        let mut created = false;
+        for name in path {
+            let existing = self.nodes[&node]
+                .children
+                .iter()
+                .find(|child| self.nodes.get(*child).is_some_and(|c| c.name == name))
+                .cloned();
+            node = match existing {
+                Some(existing) => existing,
+                None => {
+                    debug!("creating journal node {:?} under {}", name, node);
+                    let (child, renames) = self.create_child(&node, &name)?;
+                    if let Some(renamed) = renames.get(&previous) {
+                        previous = renamed.clone();
+                    }
+                    created = true;
+                    child
+                }
+            };
         }
+        if created && previous != self.journal && previous != node {
+            rollover_todos_from_yesterday(&self.dir, &previous, &node)?;
+            self.reindex_node(&previous);
+            self.reindex_node(&node);
+        }
+        Ok(node)
     }
     /// Validates data from RPC call
     /// TODO: move into a module response for linking nvim RPC calls and backend
     pub fn node_creation(&mut self, args: Vec<Value>) -> Result<NodeKey> {
+        if let [parent, child, template, rest @ ..] = args.as_slice() {
+            if let (Some(parent), Some(child), Some(template)) =
+                (parent.as_str(), child.as_str(), template.as_str())
+            {
+                // optional answers to the template's prompts
+                let answers = match rest.first().and_then(|answers| answers.as_map()) {
+                    Some(answers) => answers
+                        .iter()
+                        .filter_map(|(question, answer)| {
+                            Some((question.as_str()?.to_string(), answer.as_str()?.to_string()))
+                        })
+                        .collect(),
+                    None => HashMap::new(),
+                };
+                return self.create_from_template(parent, child, template, &answers);
+            }
+        }
         let args: Vec<Option<&str>> = args.iter().map(|arg| arg.as_str()).collect();
         match args.as_slice() {
-            [Some(parent), Some(child)] => Ok(self.create_node(Some(parent), Some(child)).unwrap()),
-            [Some(node_name)] => Ok(self.create_node(None, Some(node_name)).unwrap()),
+            [Some(parent), Some(child)] => self.create_node(Some(parent), Some(child)),
+            [Some(node_name)] => self.create_node(None, Some(node_name)),
             _ => {
                 error!("invalid args to create: {:?}", args);
-                Err(Box::new(TreeError {
-                    err_text: format!("invalid args to node_creation: {:?}", args),
-                }))
+                Err(Error::Args(format!(
+                    "invalid args to node_creation: {:?}",
+                    args
+                )))
             }
         }
     }
@@ -290,128 +315,7 @@ impl Tree {
         // need to decouple this node creation on tree
         // from processing of RPC message pack value
         match (parent, child) {
-            (Some(parent), Some(child)) => {
-                let parent = parent.to_string();
-                debug!("parent {:?} and child {:?}", parent, child);
-                dbg!(&parent, &child);
-                let child = match self.nodes.get_mut(&parent) {
-                    Some(parent) => {
-                        Some(parent.create_child(child.to_string(), self.dir.to_str().unwrap()))
-                    }
-                    None => {
-                        error!("no node in tree named: {:?}", parent);
-                        None
-                    }
-                };
-                dbg!(&child);
-                if let Some(child) = child {
-                    // a new node is created, it has a parent
-                    let child_id = child.id.clone();
-                    self.nodes.insert(child.id.clone(), child);
-                    // stage_all().unwrap();
-                    let parent_ref = get_parent(&child_id).unwrap();
-                    if let Some(n) = power_of_ten(get_node_key_number(&child_id)) {
-                        // this newly created node is a power of 10 node
-                        // we must go to all the siblings and rename them
-                        // TODO make this section more DRY
-                        let _repo = Repository::open(self.dir.to_str().unwrap()).unwrap();
-                        let siblings = self.nodes.get_mut(&parent_ref).unwrap().children.clone();
-                        for idx in 0..siblings.len() {
-                            if &siblings[idx] == &child_id {
-                                // the new nodes is named in the
-                                // siblings vec, it does not need
-                                // rename
-                                continue;
-                            }
-                            // get current name of siblings
-                            let sibid = &siblings[idx].clone();
-                            // TODO make this a function -> (u64, &str)
-                            let (base, node) = sibid.rsplit_once('/').unwrap();
-                            let (x, name_path) = node.split_once('-').unwrap();
-                            let x = x.parse::<u64>().unwrap();
-                            // new name of sibling
-                            let newid = format!(
-                                "{}/{:0width$}-{}",
-                                base,
-                                x,
-                                name_path,
-                                width = (n as usize) + 1
-                            );
-                            // remove sib node w old key from map
-                            let mut node_clone = self.nodes.remove(sibid).unwrap();
-                            // node_clone.mv(newid.clone());
-                            node_clone.id = newid.clone();
-                            debug!("renaming {:?} to {:?}", sibid, &newid);
-                            let old_path = node_clone.directory.join(&sibid);
-                            let new_path = node_clone.directory.join(&newid);
-                            // move sib node on fs
-                            rename(old_path, new_path).unwrap();
-                            // link is another node
-                            // that this node points to in its content
-                            for (id, link) in &node_clone.links {
-                                let linked = self.nodes.get_mut(&link.node).unwrap();
-                                linked.rename_backlink(&(id.clone(), link.timestamp), &newid);
-                            }
-                            // a backlink is a node that has a link
-                            // in its content that points to this node
-                            for (id, backlink) in &node_clone.backlinks {
-                                let backlinked = self.nodes.get_mut(&backlink.node).unwrap();
-                                backlinked.rename_link(&id.0, &newid);
-                            }
-                            // all children need to be renamed since their
-                            // parent has a new id and that suffixes their
-                            // id
-                            // could refactor this to have the renameing based on
-                            // width happen in here
-                            fn rename_dfs(
-                                node_ref: &NodeKey,
-                                parent: &NodeKey,
-                                map: &mut BTreeMap<NodeKey, Node>,
-                            ) -> NodeKey {
-                                // remove child from map
-                                let mut node = map.remove(node_ref).unwrap();
-                                let (_, node_name) = node_ref.rsplit_once('/').unwrap();
-                                // calc new name
-                                let newid = format!("{}/{}", parent, node_name,);
-                                // inform links
-                                for (id, link) in &node.links {
-                                    let linked = map.get_mut(&link.node).unwrap();
-                                    linked.rename_backlink(&(id.clone(), link.timestamp), &newid);
-                                }
-                                for (id, backlink) in &node.backlinks {
-                                    let backlinked = map.get_mut(&backlink.node).unwrap();
-                                    backlinked.rename_link(&id.0, &newid);
-                                }
-                                node.parent = Some(parent.to_string());
-                                node.id = newid.clone();
-                                for i in 0..node.children.len() {
-                                    node.children[i] = rename_dfs(&node.children[i], &newid, map)
-                                }
-                                node.write_meta();
-                                map.insert(newid.clone(), node);
-                                newid
-                            }
-                            for i in 0..node_clone.children.len() {
-                                node_clone.children[i] =
-                                    rename_dfs(&node_clone.children[i], &newid, &mut self.nodes)
-                            }
-                            self.nodes.insert(newid, node_clone);
-                        }
-                        // commit_paths(
-                        //     &repo,
-                        //     vec![Path::new("./*")],
-                        //     &format!("node renames due to new power of ten node {}", child_id),
-                        // )
-                        // .unwrap();
-                    }
-                    Ok(child_id)
-                } else {
-                    error!("problem");
-                    Err(Box::new(TreeError {
-                        err_text: "child creation failed".to_string(),
-                    }))
-                }
-            }
+            (Some(parent), Some(child)) => Ok(self.create_child(parent, child)?.0),
             (None, Some(node_name)) => {
                 // TODO what is the right way to remove this hard coding?
                 // 'static or const?
@@ -420,7 +324,10 @@ impl Tree {
                 let node = Node::create(node_name.to_string(), None, self.dir.to_str().unwrap());
                 let node_id = node.id.clone();
                 self.nodes.insert(node_id.clone(), node);
-                stage_all().unwrap();
+                self.apply_template_of(None, &node_id);
+                self.reindex_node(&node_id);
+                let repo = Repository::open(&self.dir)?;
+                stage_paths_in(&repo, vec![Path::new(&node_id)])?;
                 Ok(node_id)
             }
             _ => {
@@ -428,15 +335,52 @@ impl Tree {
                     "invalid args to create_node: new node: {:?} parent: {:?}",
                     child, parent
                 );
-                Err(Box::new(TreeError {
-                    err_text: format!(
-                        "invalid args to create_node: new node: {:?} parent: {:?}",
-                        child, parent
-                    ),
-                }))
+                Err(Error::Tree(format!(
+                    "invalid args to create_node: new node: {:?} parent: {:?}",
+                    child, parent
+                )))
             }
         }
     }
+    /// Create a child node, returning its key and any siblings renamed
+    /// to make room for it
+    pub(crate) fn create_child(
+        &mut self,
+        parent: &str,
+        child: &str,
+    ) -> Result<(NodeKey, BTreeMap<NodeKey, NodeKey>)> {
+        let parent = parent.to_string();
+        debug!("parent {:?} and child {:?}", parent, child);
+        let child = match self.nodes.get_mut(&parent) {
+            Some(parent) => {
+                Some(parent.create_child(child.to_string(), self.dir.to_str().unwrap()))
+            }
+            None => {
+                error!("no node in tree named: {:?}", parent);
+                None
+            }
+        };
+        if let Some(child) = child {
+            // a new node is created, it has a parent
+            let child_id = child.id.clone();
+            self.nodes.insert(child.id.clone(), child);
+            // stage_all().unwrap();
+            let parent_ref = get_parent(&child_id).unwrap();
+            let mut renames = BTreeMap::new();
+            if power_of_ten(get_node_key_number(&child_id)).is_some() {
+                // this newly created node is a power of 10 node
+                // we must go to all the siblings and rename them
+                // with a wider zero padding
+                renames = self.renumber_children(&parent_ref)?;
+            }
+            self.apply_template_of(Some(&parent_ref), &child_id);
+            self.reindex_node(&child_id);
+            Ok((child_id, renames))
+        } else {
+            error!("problem");
+            Err(Error::Tree("child creation failed".to_string()))
+        }
+    }
     pub fn link(
         &mut self,
         text: &str,
@@ -459,21 +403,313 @@ impl Tree {
         self.nodes.get_mut(from).unwrap().insert_link(link);
         self.nodes.get_mut(to).unwrap().insert_backlink(backlink);
     }
-    pub fn get_link(&self, name: &str, text: &str) -> (NodeKey, u64) {
-        let node = self.nodes.get(name).unwrap();
-        node.get_link(text)
+    /// Move a node (and its subtree) under a new parent at a 1 indexed
+    /// `position` among its new siblings, or last when `position` is None.
+    /// Both the old and new families are renumbered. Returns the new key.
+    pub fn move_node(
+        &mut self,
+        from: &str,
+        new_parent: &str,
+        position: Option<usize>,
+    ) -> Result<NodeKey> {
+        let old_parent = match self.nodes.get(from) {
+            Some(node) => match &node.parent {
+                Some(parent) => parent.clone(),
+                None => return Err(Error::Tree(format!("root node {} cannot be moved", from))),
+            },
+            None => return Err(Error::NodeNotFound(from.to_string())),
+        };
+        if !self.nodes.contains_key(new_parent) {
+            return Err(Error::NodeNotFound(new_parent.to_string()));
+        }
+        if new_parent == from || new_parent.starts_with(&format!("{}/", from)) {
+            return Err(Error::Tree(format!("cannot move {} under itself", from)));
+        }
+        debug!("moving {} under {} at {:?}", from, new_parent, position);
+        let from = from.to_string();
+        let new_parent = new_parent.to_string();
+        if let Some(parent) = self.nodes.get_mut(&old_parent) {
+            parent.children.retain(|c| c != &from);
+        }
+        let family = &mut self.nodes.get_mut(&new_parent).unwrap().children;
+        let idx = match position {
+            Some(position) => position.saturating_sub(1).min(family.len()),
+            None => family.len(),
+        };
+        family.insert(idx, from.clone());
+        let renames = self.renumber_children(&new_parent)?;
+        let mut moved = renames.get(&from).cloned().unwrap_or(from);
+        if old_parent != new_parent {
+            let old_parent = renames.get(&old_parent).cloned().unwrap_or(old_parent);
+            let renames = self.renumber_children(&old_parent)?;
+            moved = renames.get(&moved).cloned().unwrap_or(moved);
+        }
+        Ok(moved)
+    }
+    /// Delete a node from the tree and disk. With `cascade` the whole
+    /// subtree goes, otherwise nodes with children are refused.
+    /// Remaining siblings are renumbered and links in other nodes that
+    /// pointed into the deleted nodes are reported as dangling.
+    pub fn delete_node(&mut self, key: &str, cascade: bool) -> Result<Removal> {
+        let parent = self.removable_parent(key, cascade)?;
+        let prefix = format!("{}/", key);
+        let removed: BTreeSet<NodeKey> = self
+            .nodes
+            .keys()
+            .filter(|k| *k == key || k.starts_with(&prefix))
+            .cloned()
+            .collect();
+        let mut dangling = vec![];
+        for old in &removed {
+            let node = self.nodes.remove(old).unwrap();
+            self.index.remove(old);
+            self.tasks.remove(old);
+            self.unindex_tags(old);
+            for link in node.links.values() {
+                if let Some(target) = self.nodes.get_mut(&link.node) {
+                    target
+                        .backlinks
+                        .remove(&(link.text.clone(), link.timestamp));
+                    target.write_meta();
+                }
+            }
+            dangling.extend(
+                node.backlinks
+                    .values()
+                    .filter(|backlink| !removed.contains(&backlink.node))
+                    .map(|backlink| DanglingLink {
+                        node: backlink.node.clone(),
+                        text: backlink.text.clone(),
+                        target: old.clone(),
+                        line: backlink.line,
+                        char: backlink.char,
+                    }),
+            );
+        }
+        // renumbering may hand a removed key to a sibling, so links to the
+        // removed nodes are dropped rather than left to point at it
+        for link in &dangling {
+            if let Some(source) = self.nodes.get_mut(&link.node) {
+                let stale = source
+                    .links
+                    .get(&link.text)
+                    .is_some_and(|l| l.node == link.target);
+                if stale {
+                    source.links.remove(&link.text);
+                    source.write_meta();
+                }
+            }
+        }
+        debug!("deleting {:?}", removed);
+        remove_dir_all(self.dir.join(key))?;
+        match Repository::open(&self.dir) {
+            Ok(repo) => stage_removal(&repo, Path::new(key))?,
+            Err(e) => warn!("{} deleted outside of a git repo: {}", key, e),
+        }
+        self.nodes
+            .get_mut(&parent)
+            .unwrap()
+            .children
+            .retain(|child| child != key);
+        let renames = self.renumber_children(&parent)?;
+        for link in dangling.iter_mut() {
+            if let Some(renamed) = renames.get(&link.node) {
+                link.node = renamed.clone();
+            }
+        }
+        dangling.sort_by(|a, b| a.node.cmp(&b.node).then(a.line.cmp(&b.line)));
+        Ok(Removal {
+            removed: removed.into_iter().collect(),
+            dangling,
+        })
+    }
+    /// Move a node under the `archive` root node, creating it if needed.
+    /// Returns the new key of the node.
+    pub fn archive_node(&mut self, key: &str, cascade: bool) -> Result<NodeKey> {
+        self.removable_parent(key, cascade)?;
+        let archive = match &self.archive {
+            Some(archive) => archive.clone(),
+            None => {
+                let archive = self.create_node(None, Some("archive"))?;
+                let node = self.nodes.get_mut(&archive).unwrap();
+                node.tag(String::from("archive"));
+                self.archive = Some(archive.clone());
+                archive
+            }
+        };
+        if key.starts_with(&format!("{}/", archive)) {
+            return Err(Error::Tree(format!("{} is already archived", key)));
+        }
+        self.move_node(key, &archive, None)
+    }
+    /// Parent of a node that is allowed to be deleted or archived
+    fn removable_parent(&self, key: &str, cascade: bool) -> Result<NodeKey> {
+        let node = match self.nodes.get(key) {
+            Some(node) => node,
+            None => return Err(Error::NodeNotFound(key.to_string())),
+        };
+        if !cascade && !node.children.is_empty() {
+            return Err(Error::Tree(format!(
+                "{} has {} children",
+                key,
+                node.children.len()
+            )));
+        }
+        match &node.parent {
+            Some(parent) => Ok(parent.clone()),
+            None => Err(Error::Tree(format!("root node {} cannot be removed", key))),
+        }
+    }
+    /// Give every child of `parent` a key matching its place in the
+    /// children vec, zero padded to the width of the family.
+    /// Returns the old to new key of every node that was renamed.
+    pub fn renumber_children(&mut self, parent: &NodeKey) -> Result<BTreeMap<NodeKey, NodeKey>> {
+        let children = match self.nodes.get(parent) {
+            Some(node) => node.children.clone(),
+            None => return Err(Error::NodeNotFound(parent.to_string())),
+        };
+        let width = key_width(children.len());
+        let targets: Vec<NodeKey> = children
+            .iter()
+            .enumerate()
+            .map(|(idx, child)| ranked_key(Some(parent), idx as u64 + 1, width, child))
+            .collect();
+        let pending: Vec<(NodeKey, NodeKey)> = children
+            .into_iter()
+            .zip(targets.iter().cloned())
+            .filter(|(old, new)| old != new)
+            .collect();
+        // a sibling may still sit on the key another one needs,
+        // if so everything is first moved out of the way
+        let collides = pending.iter().any(|(_, new)| self.nodes.contains_key(new));
+        let mut renames = BTreeMap::new();
+        for (idx, (old, new)) in pending.iter().enumerate() {
+            let first = if collides {
+                format!("{}/.mv{}", parent, ranked_key(None, idx as u64, 1, old))
+            } else {
+                new.clone()
+            };
+            renames.extend(self.move_subtree(old, &first)?);
+        }
+        if collides {
+            for (old, new) in pending.iter() {
+                let tmp = renames.get(old).unwrap().clone();
+                let second = self.move_subtree(&tmp, new)?;
+                for to in renames.values_mut() {
+                    if let Some(moved) = second.get(to) {
+                        *to = moved.clone();
+                    }
+                }
+            }
+        }
+        let parent = self.nodes.get_mut(parent).unwrap();
+        parent.children = targets;
+        parent.write_meta();
+        Ok(renames)
+    }
+    /// Move a node directory on disk and rekey it along with its descendants
+    fn move_subtree(&mut self, old: &NodeKey, new: &NodeKey) -> Result<BTreeMap<NodeKey, NodeKey>> {
+        match self.nodes.get_mut(old) {
+            Some(node) => node.mv(new.clone())?,
+            None => return Err(Error::NodeNotFound(old.to_string())),
+        }
+        let prefix = format!("{}/", old);
+        let renames: BTreeMap<NodeKey, NodeKey> = self
+            .nodes
+            .keys()
+            .filter(|key| *key == old || key.starts_with(&prefix))
+            .map(|key| (key.clone(), format!("{}{}", new, &key[old.len()..])))
+            .collect();
+        self.rekey(&renames);
+        Ok(renames)
+    }
+    /// Swap node keys within the tree, fixing up the parent, children,
+    /// links and backlinks that point at any of the renamed nodes.
+    /// The node directories must already have been moved.
+    fn rekey(&mut self, renames: &BTreeMap<NodeKey, NodeKey>) {
+        let renamed = |key: &NodeKey| renames.get(key).unwrap_or(key).clone();
+        let mut moved = vec![];
+        for (old, new) in renames {
+            if let Some(mut node) = self.nodes.remove(old) {
+                debug!("renaming {:?} to {:?}", old, new);
+                node.id = new.clone();
+                node.parent = get_parent(new);
+                node.children = node.children.iter().map(renamed).collect();
+                self.index.remove(old);
+                self.tasks.remove(old);
+                self.unindex_tags(old);
+                moved.push(node);
+            }
+        }
+        // every node on the other end of a link needs its meta rewritten
+        let mut touched: BTreeSet<NodeKey> = BTreeSet::new();
+        for node in moved {
+            touched.insert(node.id.clone());
+            touched.extend(node.links.values().map(|link| renamed(&link.node)));
+            touched.extend(node.backlinks.values().map(|link| renamed(&link.node)));
+            self.nodes.insert(node.id.clone(), node);
+        }
+        for key in touched {
+            if let Some(node) = self.nodes.get_mut(&key) {
+                for link in node.links.values_mut() {
+                    link.node = renamed(&link.node);
+                }
+                for backlink in node.backlinks.values_mut() {
+                    backlink.node = renamed(&backlink.node);
+                }
+                node.write_meta();
+            }
+        }
+        for new in renames.values() {
+            self.reindex_node(new);
+        }
     }
+    /// The newest journal day, the last one in tree order whose names
+    /// down from the journal match the `[journal]` formats. Flat days of
+    /// the default format from before levels were configured count too,
+    /// children of a day are not days. The journal itself when it has no
+    /// days yet.
     pub fn latest_journal(&self) -> NodeKey {
-        let journal_node = self.nodes.get(&self.journal).unwrap();
-        journal_node.children[journal_node.children.len() - 1].clone()
+        let default = JournalConfig::default();
+        let layouts = [self.config.journal.formats(), default.formats()];
+        self.last_day(&self.journal, 0, &layouts)
+            .unwrap_or_else(|| self.journal.clone())
     }
-    pub fn next_sibling(&self, node: &str, previous: bool) -> NodeKey {
-        let child = self.nodes.get(node).unwrap();
+    /// Last day under `key`, a node `depth` levels below the journal whose
+    /// ancestors matched the first `depth` formats of `layouts`
+    fn last_day(&self, key: &str, depth: usize, layouts: &[Vec<&str>]) -> Option<NodeKey> {
+        for child in self.nodes.get(key)?.children.iter().rev() {
+            let name = match self.nodes.get(child) {
+                Some(node) => &node.name,
+                None => continue,
+            };
+            let matching: Vec<Vec<&str>> = layouts
+                .iter()
+                .filter(|layout| layout.get(depth).is_some_and(|f| matches_format(name, f)))
+                .cloned()
+                .collect();
+            if matching.iter().any(|layout| layout.len() == depth + 1) {
+                return Some(child.clone());
+            }
+            if let Some(day) = self.last_day(child, depth + 1, &matching) {
+                return Some(day);
+            }
+        }
+        None
+    }
+    pub fn next_sibling(&self, node: &str, previous: bool) -> Result<NodeKey> {
+        let child = self
+            .nodes
+            .get(node)
+            .ok_or_else(|| Error::NodeNotFound(node.to_string()))?;
         let parent_key = match &child.parent {
-            None => return child.id.clone(), // TODO handle when node is on bottom level
+            None => return Ok(child.id.clone()), // TODO handle when node is on bottom level
             Some(parent) => parent,
         };
-        let parent = self.nodes.get(parent_key).unwrap();
+        let parent = self
+            .nodes
+            .get(parent_key)
+            .ok_or_else(|| Error::NodeNotFound(parent_key.clone()))?;
         let family_size = parent.children.len();
         let index = child.index();
         let mut sibling_index = if previous { index - 1 } else { index + 1 };
@@ -483,27 +719,65 @@ impl Tree {
         }
         // nodes are 1 indexed in the tree heirarchy
         // the children vec is zero indexed
-        parent.children[sibling_index - 1].clone()
+        Ok(parent.children[sibling_index - 1].clone())
     }
     pub fn nodes_by_recency(&self) -> Vec<&Node> {
         let mut nodes = self.nodes.values().collect::<Vec<&Node>>();
         nodes.sort_unstable_by(|a, b| b.updated.cmp(&a.updated));
         nodes
     }
+    /// Re-read a single node body into the full text, task and tag indexes
+    pub fn reindex_node(&mut self, key: &str) {
+        let body = match self.nodes.get(key) {
+            Some(node) => read_to_string(node.content_path()),
+            None => {
+                self.index.remove(key);
+                self.tasks.remove(key);
+                self.unindex_tags(key);
+                return;
+            }
+        };
+        match body {
+            Ok(body) => self.index_body(key, BodyIndex::new(key, &body)),
+            Err(e) => {
+                error!("unable to index {}: {}", key, e);
+                self.index.remove(key);
+                self.tasks.remove(key);
+                self.index_tags(key, &[]);
+            }
+        }
+    }
+    /// Put the entries of a node body into the full text, task and tag indexes
+    pub(crate) fn index_body(&mut self, key: &str, body: BodyIndex) {
+        self.index.insert_indexed(key, body.search);
+        self.tasks.insert(key.to_string(), body.tasks);
+        self.index_tags(key, &body.tags);
+    }
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        self.index.search(query)
+    }
+    /// Tasks across every node matching `filter`, soonest due first
+    pub fn query_tasks(&self, filter: &TaskFilter) -> Vec<Task> {
+        let mut tasks: Vec<Task> = self
+            .tasks
+            .values()
+            .flatten()
+            .filter(|task| filter.matches(task))
+            .cloned()
+            .collect();
+        sort_tasks(&mut tasks);
+        tasks
+    }
 }
 
-fn rollover_todos_from_yesterday(yesterday: &NodeKey, today: &NodeKey) {
-    let yesterday_body = read_to_string(Path::new(yesterday).join("_.md")).unwrap();
-    let today_file = Path::new(today).join("_.md");
-    let today_body = read_to_string(&today_file).unwrap();
-    write(today_file, move_todos(yesterday_body, today_body)).unwrap();
-}
-fn move_todos(prior: String, current: String) -> String {
-    let todos: String = Regex::new(r"(?m)^( |\t)*- \[\] .*")
-        .unwrap()
-        .find_iter(&prior)
-        .map(|x| x.as_str().to_string())
-        .collect::<Vec<String>>()
-        .join("\n");
-    format!("{}\n{}\n", current, todos)
+fn rollover_todos_from_yesterday(dir: &Path, yesterday: &NodeKey, today: &NodeKey) -> Result<()> {
+    let yesterday_file = dir.join(yesterday).join("_.md");
+    let today_file = dir.join(today).join("_.md");
+    let (prior, current) = migrate_tasks(
+        &read_to_string(&yesterday_file)?,
+        &read_to_string(&today_file)?,
+    );
+    write(yesterday_file, prior)?;
+    write(today_file, current)?;
+    Ok(())
 }
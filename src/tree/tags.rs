@@ -0,0 +1,217 @@
+use super::{Error, Result, Tree};
+use crate::node::markdown::{extract_tags, normalize_tag};
+use crate::node::{Node, NodeKey};
+use crate::nvim::Telescoped;
+use log::*;
+use nvim_rs::Value;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{read_to_string, write};
+
+/// Tags the tree uses to find its root nodes
+const RESERVED_TAGS: [&str; 3] = ["journal", "desk", "archive"];
+
+/// A tag and how many nodes carry it or one of the tags below it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagCount {
+    pub tag: String,
+    pub nodes: usize,
+}
+
+impl Telescoped for TagCount {
+    fn entry(&self) -> Value {
+        Value::from(vec![
+            (Value::from("id"), Value::from(self.tag.as_str())),
+            (
+                Value::from("display"),
+                Value::from(format!("#{} ({})", self.tag, self.nodes)),
+            ),
+            (Value::from("nodes"), Value::from(self.nodes as u64)),
+        ])
+    }
+}
+
+/// `tag` itself or a tag below it, `project/codex` is within `project`
+pub(super) fn within(tag: &str, parent: &str) -> bool {
+    tag == parent
+        || tag
+            .strip_prefix(parent)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+fn valid_tag(tag: &str) -> Result<String> {
+    normalize_tag(tag).ok_or_else(|| Error::Args(format!("{:?} is not a valid tag", tag)))
+}
+
+pub(crate) fn unreserved_tag(tag: &str) -> Result<String> {
+    let tag = valid_tag(tag)?;
+    if RESERVED_TAGS.iter().any(|reserved| within(reserved, &tag)) {
+        return Err(Error::Tree(format!(
+            "#{} is reserved for the codex roots",
+            tag
+        )));
+    }
+    Ok(tag)
+}
+
+/// Replace the inline tags within `from` by the same tags within `to`,
+/// returning None when the body has none
+fn retag_body(body: &str, from: &str, to: &str) -> Option<String> {
+    let mut refs = extract_tags(body)
+        .into_iter()
+        .filter(|found| within(&found.tag, from))
+        .peekable();
+    refs.peek()?;
+    let mut lines: Vec<String> = body.split_inclusive('\n').map(String::from).collect();
+    // replace back to front so earlier columns on a line stay put
+    let mut refs: Vec<_> = refs.collect();
+    refs.reverse();
+    for found in refs {
+        let line = &mut lines[found.line as usize - 1];
+        let start = found.char as usize;
+        let renamed = format!("#{}{}", to, &found.tag[from.len()..]);
+        line.replace_range(start..start + found.len as usize, &renamed);
+    }
+    Some(lines.concat())
+}
+
+impl Tree {
+    /// Tags of a node, those in its `meta.toml` and those `found` in its body
+    pub(crate) fn index_tags(&mut self, key: &str, found: &[String]) {
+        self.unindex_tags(key);
+        let node = match self.nodes.get(key) {
+            Some(node) => node,
+            None => return,
+        };
+        let tags: BTreeSet<String> = node
+            .tags
+            .iter()
+            .filter_map(|tag| normalize_tag(tag))
+            .chain(found.iter().cloned())
+            .collect();
+        for tag in tags {
+            self.tags.entry(tag).or_default().insert(key.to_string());
+        }
+    }
+    /// Drop a node from the tag index
+    pub(crate) fn unindex_tags(&mut self, key: &str) {
+        self.tags.retain(|_, nodes| {
+            nodes.remove(key);
+            !nodes.is_empty()
+        });
+    }
+    /// Every tag in use along with the levels above it, so `#project/codex`
+    /// also lists `project`. Counts include the nodes of tags below.
+    pub fn all_tags(&self) -> Vec<TagCount> {
+        let mut counts: BTreeMap<&str, BTreeSet<&NodeKey>> = BTreeMap::new();
+        for (tag, nodes) in &self.tags {
+            let levels = tag
+                .match_indices('/')
+                .map(|(idx, _)| &tag[..idx])
+                .chain(std::iter::once(tag.as_str()));
+            for level in levels {
+                counts.entry(level).or_default().extend(nodes);
+            }
+        }
+        counts
+            .into_iter()
+            .map(|(tag, nodes)| TagCount {
+                tag: tag.to_string(),
+                nodes: nodes.len(),
+            })
+            .collect()
+    }
+    /// Nodes tagged `tag` or any tag below it, most recently updated first
+    pub fn nodes_by_tag(&self, tag: &str) -> Result<Vec<&Node>> {
+        let tag = valid_tag(tag)?;
+        let keys: BTreeSet<&NodeKey> = self
+            .tags
+            .iter()
+            .filter(|(found, _)| within(found, &tag))
+            .flat_map(|(_, nodes)| nodes)
+            .collect();
+        let mut nodes: Vec<&Node> = keys
+            .into_iter()
+            .filter_map(|key| self.nodes.get(key))
+            .collect();
+        nodes.sort_by_key(|node| Reverse(node.updated));
+        Ok(nodes)
+    }
+    /// Tag a node in its `meta.toml`, returning the tag as stored
+    pub fn add_tag(&mut self, key: &str, tag: &str) -> Result<String> {
+        let tag = unreserved_tag(tag)?;
+        let node = self
+            .nodes
+            .get_mut(key)
+            .ok_or_else(|| Error::NodeNotFound(key.to_string()))?;
+        if node.tags.insert(tag.clone()) {
+            node.tick_update_and_write_meta();
+            self.reindex_node(key);
+        }
+        Ok(tag)
+    }
+    /// Untag a node in its `meta.toml`. Tags written in the body are
+    /// refused, they go away by editing the body.
+    pub fn remove_tag(&mut self, key: &str, tag: &str) -> Result<()> {
+        let tag = unreserved_tag(tag)?;
+        let node = self
+            .nodes
+            .get_mut(key)
+            .ok_or_else(|| Error::NodeNotFound(key.to_string()))?;
+        if node.tags.remove(&tag) {
+            node.tick_update_and_write_meta();
+            self.reindex_node(key);
+            return Ok(());
+        }
+        let inline = self.tags.get(&tag).is_some_and(|nodes| nodes.contains(key));
+        if inline {
+            Err(Error::Tree(format!(
+                "#{} is written in the body of {}",
+                tag, key
+            )))
+        } else {
+            Err(Error::Tree(format!("{} is not tagged #{}", key, tag)))
+        }
+    }
+    /// Rename a tag and every tag below it across the codex, in both
+    /// `meta.toml` and node bodies. `project` to `work` also turns
+    /// `#project/codex` into `#work/codex`. Returns the changed nodes.
+    pub fn rename_tag(&mut self, from: &str, to: &str) -> Result<Vec<NodeKey>> {
+        let (from, to) = (unreserved_tag(from)?, unreserved_tag(to)?);
+        if within(&to, &from) {
+            return Err(Error::Args(format!(
+                "#{} can't be renamed into itself",
+                from
+            )));
+        }
+        let keys: BTreeSet<NodeKey> = self
+            .tags
+            .iter()
+            .filter(|(tag, _)| within(tag, &from))
+            .flat_map(|(_, nodes)| nodes.iter().cloned())
+            .collect();
+        for key in &keys {
+            let node = self.nodes.get_mut(key).unwrap();
+            let renamed: Vec<String> = node
+                .tags
+                .iter()
+                .filter(|tag| within(tag, &from))
+                .cloned()
+                .collect();
+            for tag in &renamed {
+                node.tags.remove(tag);
+                node.tags.insert(format!("{}{}", to, &tag[from.len()..]));
+            }
+            if !renamed.is_empty() {
+                node.tick_update_and_write_meta();
+            }
+            let path = node.content_path();
+            if let Some(body) = retag_body(&read_to_string(&path)?, &from, &to) {
+                write(&path, body)?;
+            }
+            self.reindex_node(key);
+        }
+        debug!("renamed #{} to #{} in {:?}", from, to, keys);
+        Ok(keys.into_iter().collect())
+    }
+}
@@ -0,0 +1,302 @@
+use super::tags::within;
+use super::{Error, Result, Tree};
+use crate::config::valid_template_name;
+use crate::node::markdown::normalize_tag;
+use crate::node::{Node, NodeKey};
+use crate::nvim::Telescoped;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+use log::*;
+use nvim_rs::Value;
+use std::collections::HashMap;
+use std::fs::{read_dir, read_to_string, write};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// Folder at the root of a codex holding `<name>.md` templates
+pub const TEMPLATE_DIR: &str = "templates";
+
+/// A template and the questions it asks when a node is created from it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    pub name: String,
+    pub prompts: Vec<String>,
+}
+
+impl Telescoped for Template {
+    fn entry(&self) -> Value {
+        Value::Map(vec![
+            (
+                Value::String("id".into()),
+                Value::String(self.name.clone().into()),
+            ),
+            (
+                Value::String("display".into()),
+                Value::String(self.name.clone().into()),
+            ),
+            (
+                Value::String("prompts".into()),
+                Value::Array(
+                    self.prompts
+                        .iter()
+                        .map(|prompt| Value::String(prompt.clone().into()))
+                        .collect(),
+                ),
+            ),
+        ])
+    }
+}
+
+/// Values for the placeholders of a template
+pub struct Placeholders<'a> {
+    pub title: &'a str,
+    /// Name of the parent node, empty for a root node
+    pub parent: &'a str,
+    pub now: DateTime<Local>,
+    /// Answers to `{{prompt:...}}` keyed by the question
+    pub answers: &'a HashMap<String, String>,
+}
+
+impl Placeholders<'_> {
+    /// `{{title}}`, `{{parent}}`, `{{date}}`, `{{time}}`, `{{date:<strftime>}}`
+    /// and `{{prompt:<question>}}`, None for anything else
+    fn value(&self, placeholder: &str) -> Option<String> {
+        match placeholder.split_once(':') {
+            Some(("date", format)) => {
+                if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                    return None;
+                }
+                Some(self.now.format(format).to_string())
+            }
+            // an unanswered prompt is left blank
+            Some(("prompt", question)) => Some(
+                self.answers
+                    .get(question.trim())
+                    .cloned()
+                    .unwrap_or_default(),
+            ),
+            Some(_) => None,
+            None => match placeholder {
+                "title" => Some(self.title.to_string()),
+                "parent" => Some(self.parent.to_string()),
+                "date" => Some(self.now.format("%Y-%m-%d").to_string()),
+                "time" => Some(self.now.format("%H:%M").to_string()),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Byte ranges of the `{{...}}` placeholders of a template and the
+/// trimmed text within them
+fn placeholders(template: &str) -> Vec<(usize, usize, &str)> {
+    let mut found = vec![];
+    let mut offset = 0;
+    while let Some(start) = template[offset..].find("{{").map(|start| offset + start) {
+        let end = match template[start + 2..].find("}}") {
+            Some(end) => start + 2 + end + 2,
+            None => break,
+        };
+        found.push((start, end, template[start + 2..end - 2].trim()));
+        offset = end;
+    }
+    found
+}
+
+/// Fill in the placeholders of a template, unknown ones are kept as written
+pub fn render(template: &str, values: &Placeholders) -> String {
+    let mut rendered = String::new();
+    let mut last = 0;
+    for (start, end, placeholder) in placeholders(template) {
+        rendered.push_str(&template[last..start]);
+        match values.value(placeholder) {
+            Some(value) => rendered.push_str(&value),
+            None => rendered.push_str(&template[start..end]),
+        }
+        last = end;
+    }
+    rendered.push_str(&template[last..]);
+    rendered
+}
+
+/// Questions of the `{{prompt:...}}` placeholders of a template, in the
+/// order they first appear
+pub fn prompts(template: &str) -> Vec<String> {
+    let mut prompts: Vec<String> = vec![];
+    for (_, _, placeholder) in placeholders(template) {
+        if let Some(("prompt", question)) = placeholder.split_once(':') {
+            let question = question.trim().to_string();
+            if !prompts.contains(&question) {
+                prompts.push(question);
+            }
+        }
+    }
+    prompts
+}
+
+impl Tree {
+    fn template_path(&self, name: &str) -> Result<PathBuf> {
+        let path = self.dir.join(TEMPLATE_DIR).join(format!("{}.md", name));
+        if !valid_template_name(name) || !path.is_file() {
+            return Err(Error::Args(format!(
+                "no template named {:?} in {}/",
+                name, TEMPLATE_DIR
+            )));
+        }
+        Ok(path)
+    }
+    /// Every template of the codex by name
+    pub fn templates(&self) -> Result<Vec<Template>> {
+        let entries = match read_dir(self.dir.join(TEMPLATE_DIR)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let mut templates = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.strip_suffix(".md"),
+                None => None,
+            };
+            if let Some(name) = name.filter(|name| valid_template_name(name)) {
+                templates.push(Template {
+                    name: name.to_string(),
+                    prompts: prompts(&read_to_string(&path)?),
+                });
+            }
+        }
+        templates.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(templates)
+    }
+    /// Template a node hands to its children, its own or that of its most
+    /// specific tag in `[templates.tags]`
+    fn chosen_template<'a>(&'a self, node: &'a Node) -> Option<&'a String> {
+        if node.template.is_some() {
+            return node.template.as_ref();
+        }
+        self.config
+            .templates
+            .tags
+            .iter()
+            .filter_map(|(tag, template)| Some((normalize_tag(tag)?, template)))
+            .filter(|(tag, _)| {
+                self.tags
+                    .iter()
+                    .any(|(carried, nodes)| within(carried, tag) && nodes.contains(&node.id))
+            })
+            .max_by_key(|(tag, _)| tag.split('/').count())
+            .map(|(_, template)| template)
+    }
+    /// Template a new child of `parent`, or a new root node, starts from
+    pub fn template_for(&self, parent: Option<&str>) -> Option<String> {
+        parent
+            .and_then(|parent| self.nodes.get(parent))
+            .and_then(|parent| self.chosen_template(parent))
+            .or(self.config.templates.default.as_ref())
+            .cloned()
+    }
+    /// Nodes whose children start from `template`, by their own choice or
+    /// by a tag
+    pub fn template_parents(&self, template: &str) -> Vec<&Node> {
+        self.nodes
+            .values()
+            .filter(|node| {
+                self.chosen_template(node)
+                    .is_some_and(|chosen| chosen == template)
+            })
+            .collect()
+    }
+    /// Have new children of a node start from `template`, or stop them
+    /// from doing so with None
+    pub fn set_template(&mut self, key: &str, template: Option<&str>) -> Result<()> {
+        if let Some(template) = template {
+            self.template_path(template)?;
+        }
+        let node = self
+            .nodes
+            .get_mut(key)
+            .ok_or_else(|| Error::NodeNotFound(key.to_string()))?;
+        node.template = template.map(String::from);
+        node.tick_update_and_write_meta();
+        Ok(())
+    }
+    /// Replace the body of a node by a rendered template
+    pub(crate) fn write_template(
+        &self,
+        key: &str,
+        template: &str,
+        answers: &HashMap<String, String>,
+    ) -> Result<()> {
+        let body = read_to_string(self.template_path(template)?)?;
+        let node = self
+            .nodes
+            .get(key)
+            .ok_or_else(|| Error::NodeNotFound(key.to_string()))?;
+        let parent = node
+            .parent
+            .as_ref()
+            .and_then(|parent| self.nodes.get(parent));
+        let values = Placeholders {
+            title: &node.name,
+            parent: parent.map_or("", |parent| parent.name.as_str()),
+            now: Local::now(),
+            answers,
+        };
+        debug!("starting {} from template {:?}", key, template);
+        write(node.content_path(), render(&body, &values))?;
+        Ok(())
+    }
+    /// Start a new child of `parent` from the template of the parent, if
+    /// it has one. A broken template leaves the plain `# name` body.
+    pub(crate) fn apply_template_of(&self, parent: Option<&str>, key: &str) {
+        if let Some(template) = self.template_for(parent) {
+            if let Err(e) = self.write_template(key, &template, &HashMap::new()) {
+                error!(
+                    "unable to start {} from template {:?}: {}",
+                    key, template, e
+                );
+            }
+        }
+    }
+    /// Create a child of `parent` from a template whatever the parent
+    /// would give it, `answers` fill in the template's prompts
+    pub fn create_from_template(
+        &mut self,
+        parent: &str,
+        name: &str,
+        template: &str,
+        answers: &HashMap<String, String>,
+    ) -> Result<NodeKey> {
+        self.template_path(template)?;
+        if !self.nodes.contains_key(parent) {
+            return Err(Error::NodeNotFound(parent.to_string()));
+        }
+        let key = self.create_node(Some(parent), Some(name))?;
+        self.write_template(&key, template, answers)?;
+        self.reindex_node(&key);
+        Ok(key)
+    }
+}
+
+#[test]
+fn test_render_template() {
+    use chrono::TimeZone;
+    let template = "# {{title}}\nunder {{ parent }} on {{date}} at {{time}}, {{date:%A}}\n\
+        {{prompt:Author}} - {{prompt: Year }} - {{prompt:Author}}\n{{unknown}} {{date:%Q}} {{open\n";
+    let answers: HashMap<String, String> = vec![("Author".to_string(), "Coltrane".to_string())]
+        .into_iter()
+        .collect();
+    let values = Placeholders {
+        title: "Giant Steps",
+        parent: "desk",
+        now: Local.with_ymd_and_hms(1960, 1, 27, 9, 5, 0).unwrap(),
+        answers: &answers,
+    };
+    assert_eq!(
+        render(template, &values),
+        "# Giant Steps\nunder desk on 1960-01-27 at 09:05, Wednesday\n\
+        Coltrane -  - Coltrane\n{{unknown}} {{date:%Q}} {{open\n"
+    );
+    assert_eq!(prompts(template), vec!["Author", "Year"]);
+}
@@ -0,0 +1,182 @@
+use super::load::{read_nodes, LoadCache};
+use super::{get_parent, Tree};
+use crate::node::{Node, NodeKey, NodeMeta};
+use crate::nvim::Telescoped;
+use log::*;
+use nvim_rs::Value;
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::path::{Component, Path, PathBuf};
+
+/// A node that was changed on disk outside of the tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeChange {
+    Added(NodeKey),
+    Removed(NodeKey),
+    Updated(NodeKey),
+}
+
+impl Telescoped for TreeChange {
+    fn entry(&self) -> Value {
+        let (change, node) = match self {
+            TreeChange::Added(node) => ("added", node),
+            TreeChange::Removed(node) => ("removed", node),
+            TreeChange::Updated(node) => ("updated", node),
+        };
+        Value::from(vec![
+            (Value::from("change"), Value::from(change)),
+            (Value::from("id"), Value::from(node.as_str())),
+        ])
+    }
+}
+
+/// Node keys are made of `<number>-<name>` directories
+fn is_key_part(part: &str) -> bool {
+    match part.split_once('-') {
+        Some((num, _)) => !num.is_empty() && num.chars().all(|c| c.is_ascii_digit()),
+        None => false,
+    }
+}
+
+impl Tree {
+    /// Key of the node directory holding `path` and whether the path is
+    /// that node's `_.md`. Paths outside of any node, like `.git`, are None.
+    pub fn node_key_for(&self, path: &Path) -> Option<(NodeKey, bool)> {
+        let relative = path.strip_prefix(&self.dir).ok()?;
+        let mut parts = vec![];
+        let mut rest = vec![];
+        for component in relative.components() {
+            let part = match component {
+                Component::Normal(part) => part.to_str()?,
+                _ => return None,
+            };
+            if rest.is_empty() && is_key_part(part) {
+                parts.push(part);
+            } else {
+                rest.push(part);
+            }
+        }
+        if parts.is_empty() {
+            return None;
+        }
+        Some((parts.join("/"), rest == ["_.md"]))
+    }
+    /// Patch the tree for paths changed on disk, returning what changed.
+    /// Parents are refreshed before their children, so a directory moved
+    /// into place by git is loaded whole.
+    pub fn refresh_paths(&mut self, paths: &[PathBuf]) -> Vec<TreeChange> {
+        let mut keys: BTreeMap<NodeKey, bool> = BTreeMap::new();
+        for path in paths {
+            if let Some((key, content)) = self.node_key_for(path) {
+                *keys.entry(key).or_default() |= content;
+            }
+        }
+        let mut keys: Vec<(NodeKey, bool)> = keys.into_iter().collect();
+        keys.sort_by_key(|(key, _)| key.matches('/').count());
+        let mut changes = vec![];
+        for (key, content) in keys {
+            // already loaded along with a new parent
+            if changes.contains(&TreeChange::Added(key.clone())) {
+                continue;
+            }
+            changes.extend(self.refresh_node(&key, content));
+        }
+        changes
+    }
+    /// Bring a single node (and anything below it) in line with the disk
+    pub fn refresh_node(&mut self, key: &str, content: bool) -> Vec<TreeChange> {
+        let on_disk = self.dir.join(key).join("meta.toml").is_file();
+        match (on_disk, self.nodes.contains_key(key)) {
+            (true, true) => self.reload_node(key, content),
+            (true, false) => self.add_subtree(key),
+            (false, true) => self.remove_subtree(key),
+            (false, false) => vec![],
+        }
+    }
+    fn reload_node(&mut self, key: &str, content: bool) -> Vec<TreeChange> {
+        let current = &self.nodes[key];
+        let node = match Node::from_tree(
+            key.to_string(),
+            &self.dir.join(key).join("meta.toml"),
+            current.parent.clone(),
+            current.children.clone(),
+            self.dir.to_str().unwrap(),
+        ) {
+            Ok(node) => node,
+            Err(e) => {
+                // likely caught mid write, the next event will pick it up
+                warn!("unable to reload {}: {}", key, e);
+                return vec![];
+            }
+        };
+        // the backend's own writes, and saves it has already indexed, match
+        // what the tree holds and are not reported back
+        let content = content
+            && match read_to_string(node.content_path()) {
+                Ok(body) => !self.index.holds(key, &body),
+                Err(_) => true,
+            };
+        let changed = NodeMeta::from(&node).to_toml() != NodeMeta::from(current).to_toml();
+        if !changed && !content {
+            return vec![];
+        }
+        self.nodes.insert(key.to_string(), node);
+        self.reindex_node(key);
+        if content {
+            if let Err(e) = self.sync_links(key) {
+                error!("unable to sync links of {}: {}", key, e);
+            }
+        }
+        vec![TreeChange::Updated(key.to_string())]
+    }
+    fn add_subtree(&mut self, key: &str) -> Vec<TreeChange> {
+        let parent = get_parent(&key.to_string());
+        if let Some(parent) = &parent {
+            if !self.nodes.contains_key(parent) {
+                // a new parent loads this node along with it
+                return self.refresh_node(parent, false);
+            }
+        }
+        let (loaded, _) = read_nodes(&self.dir, Some(key), &mut LoadCache::default());
+        if !loaded.contains_key(key) {
+            return vec![];
+        }
+        if let Some(parent) = &parent {
+            let siblings = &mut self.nodes.get_mut(parent).unwrap().children;
+            siblings.push(key.to_string());
+            siblings.sort();
+        }
+        let added: Vec<NodeKey> = loaded.keys().cloned().collect();
+        self.nodes.extend(loaded);
+        for node in &added {
+            self.reindex_node(node);
+        }
+        for node in &added {
+            if let Err(e) = self.sync_links(node) {
+                error!("unable to sync links of {}: {}", node, e);
+            }
+        }
+        debug!("loaded {:?} from disk", added);
+        added.into_iter().map(TreeChange::Added).collect()
+    }
+    fn remove_subtree(&mut self, key: &str) -> Vec<TreeChange> {
+        let prefix = format!("{}/", key);
+        let removed: Vec<NodeKey> = self
+            .nodes
+            .keys()
+            .filter(|k| *k == key || k.starts_with(&prefix))
+            .cloned()
+            .collect();
+        for node in &removed {
+            self.nodes.remove(node);
+            self.reindex_node(node);
+        }
+        if let Some(parent) = get_parent(&key.to_string()) {
+            if let Some(parent) = self.nodes.get_mut(&parent) {
+                parent.children.retain(|child| child != key);
+            }
+        }
+        debug!("dropped {:?}, gone from disk", removed);
+        removed.into_iter().map(TreeChange::Removed).collect()
+    }
+}
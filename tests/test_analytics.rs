@@ -0,0 +1,86 @@
+#![allow(dead_code, unused_imports, unused_variables)]
+use chrono::{Duration, Local};
+use codex::node::NodeMeta;
+use codex::tree::Tree;
+use std::fs::write;
+
+use rstest::rstest;
+use rstest::*;
+mod fixtures;
+use fixtures::*;
+mod utils;
+use utils::*;
+
+fn age(dir: &std::path::Path, key: &str, days: i64) {
+    let path = dir.join(key).join("meta.toml");
+    let mut meta = NodeMeta::from_toml(&path).unwrap();
+    meta.updated = Local::now() - Duration::days(days);
+    write(&path, meta.to_toml()).unwrap();
+}
+
+#[rstest]
+fn link_analytics(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let keys: Vec<String> = ["a", "b", "c", "d", "e"]
+        .iter()
+        .map(|name| tree.create_node(Some("2-desk"), Some(name)).unwrap())
+        .collect();
+    let (a, b, c, d, e) = (&keys[0], &keys[1], &keys[2], &keys[3], &keys[4]);
+    // a - b - c is one cluster, d stands alone and e links to itself
+    write_body(dir.path(), a, "# a\n[[b]]\n");
+    write_body(dir.path(), c, "# c\n[[b]]\n");
+    write_body(dir.path(), e, "# e\n[[#e]]\n");
+    tree.load();
+
+    let orphans: Vec<&str> = tree.orphans().iter().map(|node| node.id.as_str()).collect();
+    assert_eq!(orphans, vec![d.as_str(), e.as_str()]);
+
+    let hubs = tree.hubs(2);
+    assert_eq!(hubs.len(), 2);
+    assert_eq!(
+        (hubs[0].node.as_str(), hubs[0].links_in, hubs[0].links_out),
+        (b.as_str(), 2, 0)
+    );
+
+    assert_eq!(
+        tree.shortest_path(a, c).unwrap(),
+        Some(vec![a.clone(), b.clone(), c.clone()])
+    );
+    assert_eq!(tree.shortest_path(a, a).unwrap(), Some(vec![a.clone()]));
+    assert_eq!(tree.shortest_path(a, d).unwrap(), None);
+    assert_eq!(
+        tree.shortest_path(a, "2-desk/9-x").unwrap_err().code(),
+        "node-not-found"
+    );
+
+    assert_eq!(
+        tree.link_clusters(),
+        vec![vec![a.clone(), b.clone(), c.clone()]]
+    );
+
+    // a and c went untouched for weeks but b was worked on today
+    age(dir.path(), a, 40);
+    age(dir.path(), c, 10);
+    tree.load();
+    let stale = tree.stale_linked_to_today(7).unwrap();
+    let found: Vec<(&str, Vec<String>)> = stale
+        .iter()
+        .map(|stale| (stale.node.as_str(), stale.linked.clone()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![(a.as_str(), vec![b.clone()]), (c.as_str(), vec![b.clone()])]
+    );
+    assert_eq!(tree.stale_linked_to_today(30).unwrap().len(), 1);
+    age(dir.path(), b, 1);
+    tree.load();
+    assert!(tree.stale_linked_to_today(7).unwrap().is_empty());
+    // too far back for a date
+    let err = tree.stale_linked_to_today(1_000_000_000).unwrap_err();
+    assert_eq!(err.code(), "invalid-args");
+    assert_eq!(
+        tree.stale_linked_to_today(u64::MAX).unwrap_err().code(),
+        "invalid-args"
+    );
+}
@@ -0,0 +1,51 @@
+#![allow(dead_code, unused_imports, unused_variables)]
+use codex::tree::Tree;
+use std::fs::write;
+
+use rstest::rstest;
+use rstest::*;
+mod fixtures;
+use fixtures::*;
+mod utils;
+use utils::*;
+
+#[rstest]
+fn reload_config_keeps_the_last_good_one(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let config = dir.path().join("codex.toml");
+    write(
+        &config,
+        "[git]\nbranch = \"trunk\"\n[sync]\npush_on_stop = false\n",
+    )
+    .unwrap();
+    tree.reload_config().unwrap();
+    assert_eq!(tree.config.git.branch, "trunk");
+    assert!(tree.config.sync.pull_on_start && !tree.config.sync.push_on_stop);
+
+    write(
+        &config,
+        "[git]\nbranch = \"trunk\"\n[autosave]\ncommit_minutes = \"often\"\n",
+    )
+    .unwrap();
+    let err = tree.reload_config().unwrap_err();
+    assert_eq!(err.code(), "config");
+    assert!(err.to_string().contains("commit_minutes"));
+    assert_eq!(tree.config.git.branch, "trunk");
+
+    // so does a load, which still reads the nodes
+    write(
+        &config,
+        "[git]\nbranch = \"main\"\n[autosave]\ncommit_minutes = 600000\n",
+    )
+    .unwrap();
+    let err = tree.reload_config().unwrap_err();
+    assert!(err.to_string().contains("commit_minutes"));
+    tree.nodes.clear();
+    tree.load();
+    assert_eq!(tree.config.git.branch, "trunk");
+    assert!(tree.nodes.contains_key("2-desk"));
+    write(&config, "[credentials]\nssh_agent = true\n").unwrap();
+    tree.reload_config().unwrap();
+    assert!(tree.config.credentials.ssh_agent);
+}
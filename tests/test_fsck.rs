@@ -0,0 +1,116 @@
+#![allow(dead_code, unused_imports, unused_variables)]
+use codex::node::NodeMeta;
+use codex::tree::fsck::{check, repair, Problem};
+use codex::tree::Tree;
+
+use rstest::rstest;
+use rstest::*;
+mod fixtures;
+use fixtures::*;
+mod utils;
+use utils::*;
+
+#[rstest]
+fn fresh_codex_is_clean(dir_and_tree: (TempDir, Tree)) {
+    let (dir, tree) = dir_and_tree;
+    assert!(check(dir.path()).is_clean());
+    assert!(tree.check_family().is_clean());
+}
+
+#[rstest]
+fn check_and_repair(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let a = tree.create_node(Some("2-desk"), Some("a")).unwrap();
+    let b = tree.create_node(Some("2-desk"), Some("b")).unwrap();
+    let c = tree.create_node(Some("2-desk"), Some("c")).unwrap();
+    let d = tree.create_node(Some("2-desk"), Some("d")).unwrap();
+    tree.link("c", &a, 1, 0, &c, 0, 0);
+    tree.link("d", &a, 2, 0, &d, 0, 0);
+    tree.link("gone", &a, 3, 0, &b, 0, 0);
+    // links only survive a load while their text is in the body
+    std::fs::write(
+        dir.path().join(&a).join("_.md"),
+        "# a\n[[c]]\n[[d]]\n[[gone]]\n",
+    )
+    .unwrap();
+
+    // b vanishes behind the tree's back, leaving a gap and a dangling link
+    std::fs::remove_dir_all(dir.path().join(&b)).unwrap();
+    std::fs::remove_file(dir.path().join(&c).join("_.md")).unwrap();
+    // a forgets it linked to d, leaving d with an orphaned backlink
+    let a_meta = dir.path().join(&a).join("meta.toml");
+    let mut meta = NodeMeta::from_toml(&a_meta).unwrap();
+    meta.links.retain(|link| link.text != "d");
+    std::fs::write(&a_meta, meta.to_toml()).unwrap();
+    std::fs::write(dir.path().join(&d).join("meta.toml"), "name = ").unwrap();
+
+    let report = check(dir.path());
+    assert_eq!(report.problems.len(), 4);
+    assert!(report
+        .problems
+        .iter()
+        .any(|p| matches!(p, Problem::MissingFiles(_))));
+    assert!(report
+        .problems
+        .iter()
+        .any(|p| matches!(p, Problem::MalformedMeta { node, .. } if node == &d)));
+    assert!(report
+        .problems
+        .iter()
+        .any(|p| matches!(p, Problem::NumberingGap { numbers, .. } if numbers == &vec![1, 3, 4])));
+    assert!(report
+        .problems
+        .iter()
+        .any(|p| matches!(p, Problem::DanglingLink { target, .. } if target == &b)));
+
+    // an unreadable node is left out of the tree instead of panicking
+    tree.load();
+    assert!(!tree.nodes.contains_key(&d));
+
+    let d_meta = dir.path().join(&d).join("meta.toml");
+    let mut meta = NodeMeta::new("d".to_string());
+    meta.backlinks.push(codex::node::NodeLink {
+        node: a.clone(),
+        text: "d".to_string(),
+        timestamp: 1,
+        line: 2,
+        char: 0,
+        is_name_linked: true,
+    });
+    std::fs::write(&d_meta, meta.to_toml()).unwrap();
+    // c loses the backlink for the link a has to it
+    let c_meta = dir.path().join(&c).join("meta.toml");
+    let mut meta = NodeMeta::from_toml(&c_meta).unwrap();
+    meta.backlinks.clear();
+    std::fs::write(&c_meta, meta.to_toml()).unwrap();
+    let report = check(dir.path());
+    assert!(report
+        .problems
+        .iter()
+        .any(|p| matches!(p, Problem::OrphanedBacklink { node, .. } if node == &d)));
+    assert!(report
+        .problems
+        .iter()
+        .any(|p| matches!(p, Problem::MissingBacklink { target, .. } if target == &c)));
+
+    let repaired = repair(dir.path()).unwrap();
+    assert_eq!(repaired.problems.len(), 4);
+    let remaining = check(dir.path());
+    assert_eq!(remaining.problems.len(), 1);
+    assert!(matches!(
+        &remaining.problems[0],
+        Problem::DanglingLink { target, .. } if target == &b
+    ));
+    assert_eq!(
+        nodekeys_in_dir(dir.path()),
+        vec![
+            "1-journal",
+            "2-desk/1-a",
+            "2-desk/2-c",
+            "2-desk/3-d",
+            "2-desk"
+        ]
+    );
+    assert!(dir.path().join("2-desk/2-c/_.md").is_file());
+}
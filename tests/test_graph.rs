@@ -0,0 +1,89 @@
+#![allow(dead_code, unused_imports, unused_variables)]
+use codex::export::graph::{render, GraphFormat};
+use codex::tree::graph::{EdgeKind, Edges, GraphOptions};
+use codex::tree::Tree;
+
+use rstest::rstest;
+use rstest::*;
+mod fixtures;
+use fixtures::*;
+mod utils;
+use utils::*;
+
+fn edges(tree: &Tree, options: &GraphOptions) -> Vec<(String, String, EdgeKind)> {
+    tree.graph(options)
+        .unwrap()
+        .edges
+        .into_iter()
+        .map(|edge| (edge.from, edge.to, edge.kind))
+        .collect()
+}
+
+#[rstest]
+fn graph_of_hierarchy_and_links(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let a = tree.create_node(Some("2-desk"), Some("a")).unwrap();
+    let b = tree.create_node(Some("2-desk"), Some("b")).unwrap();
+    let c = tree.create_node(Some(&a), Some("c")).unwrap();
+    write_body(dir.path(), &a, "# a\n[[b]] #jazz\n");
+    write_body(dir.path(), &c, "# c\n[[b]] [[journal]] #jazz/bebop\n");
+    tree.load();
+    let edge = |from: &str, to: &str, kind| (from.to_string(), to.to_string(), kind);
+    use EdgeKind::*;
+
+    let desk = GraphOptions {
+        root: Some("2-desk".to_string()),
+        ..GraphOptions::default()
+    };
+    let graph = tree.graph(&desk).unwrap();
+    assert_eq!(graph.nodes.len(), 4);
+    assert_eq!(graph.nodes[1].tags, vec!["jazz"]);
+    // the link to the journal leaves the subtree and is dropped
+    assert_eq!(
+        edges(&tree, &desk),
+        vec![
+            edge("2-desk", &a, Hierarchy),
+            edge("2-desk", &b, Hierarchy),
+            edge(&a, &c, Hierarchy),
+            edge(&a, &b, Link),
+            edge(&c, &b, Link),
+        ]
+    );
+
+    let links = GraphOptions {
+        edges: Edges::Links,
+        ..GraphOptions::default()
+    };
+    assert_eq!(
+        edges(&tree, &links),
+        vec![
+            edge(&a, &b, Link),
+            edge(&c, "1-journal", Link),
+            edge(&c, &b, Link)
+        ]
+    );
+
+    let jazz = GraphOptions {
+        edges: Edges::Hierarchy,
+        tag: Some("jazz".to_string()),
+        ..GraphOptions::default()
+    };
+    let graph = tree.graph(&jazz).unwrap();
+    let ids: Vec<&str> = graph.nodes.iter().map(|node| node.id.as_str()).collect();
+    assert_eq!(ids, vec![a.as_str(), c.as_str()]);
+    assert_eq!(edges(&tree, &jazz), vec![edge(&a, &c, Hierarchy)]);
+
+    let dot = render(&graph, GraphFormat::Dot);
+    assert!(dot.starts_with("digraph codex {\n"));
+    assert!(dot.contains(&format!(
+        "\"{}\" -> \"{}\" [kind=hierarchy, style=solid];",
+        a, c
+    )));
+
+    let missing = GraphOptions {
+        root: Some("2-desk/9-x".to_string()),
+        ..GraphOptions::default()
+    };
+    assert_eq!(tree.graph(&missing).unwrap_err().code(), "node-not-found");
+}
@@ -0,0 +1,145 @@
+#![allow(dead_code, unused_imports, unused_variables)]
+use chrono::{DateTime, Local, TimeZone};
+use codex::import::obsidian::import_vault;
+use codex::tree::Tree;
+use std::fs::{create_dir_all, read_to_string, write, File};
+use std::path::Path;
+use std::time::SystemTime;
+
+use rstest::rstest;
+use rstest::*;
+mod fixtures;
+use fixtures::*;
+mod utils;
+use utils::*;
+
+fn note(vault: &Path, path: &str, body: &str) {
+    let path = vault.join(path);
+    create_dir_all(path.parent().unwrap()).unwrap();
+    write(path, body).unwrap();
+}
+
+#[rstest]
+fn import_obsidian_vault(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let outside = TempDir::new().unwrap();
+    let vault = outside.path().join("vault");
+    note(&vault, ".obsidian/app.json", "{}");
+    note(&vault, "attachments/cover.png", "png");
+    note(&vault, "Music/Music.md", "Jazz notes.\n");
+    note(
+        &vault,
+        "Music/Giant Steps.md",
+        "---\ntags: [jazz, Coltrane]\naliases: [GS]\nstatus: done\n---\n# Giant Steps\n\nA [[Coltrane|sax]] tune, see [[Music#Intro]].\n\n![[cover.png]]\n![[Missing]]\n",
+    );
+    note(
+        &vault,
+        "People/Coltrane.md",
+        "Played [[GS]] and [[Nowhere]].\n",
+    );
+    note(&vault, "Inbox.md", "[[people/coltrane.md]] #todo\n");
+    for n in 1..=10 {
+        note(&vault, &format!("Many/n{:02}.md", n), "[[Inbox]]\n");
+    }
+    let modified = Local.with_ymd_and_hms(2021, 3, 4, 5, 6, 7).unwrap();
+    File::options()
+        .write(true)
+        .open(vault.join("Inbox.md"))
+        .unwrap()
+        .set_modified(SystemTime::from(modified))
+        .unwrap();
+
+    let stats = import_vault(&mut tree, &vault, "2-desk").unwrap();
+    assert_eq!(stats.root, "2-desk/1-vault");
+    assert_eq!(
+        (
+            stats.notes,
+            stats.folders,
+            stats.links,
+            stats.attachments,
+            stats.unresolved
+        ),
+        (14, 2, 14, 1, 2)
+    );
+    let root = &tree.nodes["2-desk/1-vault"];
+    assert_eq!(
+        root.children,
+        vec![
+            "2-desk/1-vault/1-Many",
+            "2-desk/1-vault/2-Music",
+            "2-desk/1-vault/3-People",
+            "2-desk/1-vault/4-Inbox"
+        ]
+    );
+    let (music, people, inbox) = (
+        "2-desk/1-vault/2-Music",
+        "2-desk/1-vault/3-People",
+        "2-desk/1-vault/4-Inbox",
+    );
+    let steps = "2-desk/1-vault/2-Music/1-Giant-Steps";
+    let coltrane = "2-desk/1-vault/3-People/1-Coltrane";
+    // the folder note is the body of the folder, the attachments folder has no notes
+    assert_eq!(tree.nodes[music].children, vec![steps]);
+    assert_eq!(
+        read_to_string(dir.path().join(music).join("_.md")).unwrap(),
+        "# Music\n\nJazz notes.\n"
+    );
+    assert_eq!(
+        read_to_string(dir.path().join(steps).join("_.md")).unwrap(),
+        "# Giant Steps\n\n```yaml\nstatus: done\n```\n\nA [[sax]] tune, see [[Music#Intro]].\n\n![cover.png](cover.png)\n![[Missing]]\n"
+    );
+    assert_eq!(
+        read_to_string(dir.path().join(steps).join("cover.png")).unwrap(),
+        "png"
+    );
+    assert_eq!(
+        read_to_string(dir.path().join(coltrane).join("_.md")).unwrap(),
+        "# Coltrane\n\nPlayed [[GS]] and [[Nowhere]].\n"
+    );
+
+    let node = &tree.nodes[steps];
+    let mut tags: Vec<&String> = node.tags.iter().collect();
+    tags.sort();
+    assert_eq!(tags, vec!["coltrane", "jazz"]);
+    assert_eq!(node.aliases, vec!["GS"]);
+    assert_eq!(node.links["sax"].node, coltrane);
+    assert_eq!(node.links["Music#Intro"].node, music);
+    assert!(tree.nodes[coltrane]
+        .backlinks
+        .values()
+        .any(|link| link.node == steps));
+    assert_eq!(tree.nodes[coltrane].links["GS"].node, steps);
+    assert_eq!(tree.nodes[inbox].links["people/coltrane"].node, coltrane);
+    assert_eq!(tree.nodes[inbox].updated, modified);
+    assert!(tree.nodes[inbox].created <= modified);
+    assert_eq!(tree.nodes_by_tag("todo").unwrap()[0].id, inbox);
+
+    // the tenth note renumbered its siblings, every one still links to the inbox
+    assert_eq!(
+        tree.nodes["2-desk/1-vault/1-Many"].children[0],
+        "2-desk/1-vault/1-Many/01-n01"
+    );
+    assert_eq!(tree.nodes[inbox].backlinks.len(), 10);
+
+    // links are in meta.toml and survive a reload
+    tree.load();
+    assert_eq!(tree.nodes[steps].links["sax"].node, coltrane);
+    assert_eq!(tree.nodes[inbox].backlinks.len(), 10);
+    assert_eq!(tree.nodes[inbox].updated, modified);
+    assert_eq!(tree.follow_link(steps, "Music#Intro").unwrap().0, music);
+
+    let missing = outside.path().join("nope");
+    assert_eq!(
+        import_vault(&mut tree, &missing, "2-desk")
+            .unwrap_err()
+            .code(),
+        "invalid-args"
+    );
+    assert_eq!(
+        import_vault(&mut tree, &vault, "2-desk/9-x")
+            .unwrap_err()
+            .code(),
+        "node-not-found"
+    );
+}
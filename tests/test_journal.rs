@@ -0,0 +1,141 @@
+#![allow(dead_code, unused_imports, unused_variables)]
+use chrono::NaiveDate;
+use codex::tree::fsck::check;
+use codex::tree::Tree;
+
+use rstest::rstest;
+use rstest::*;
+mod fixtures;
+use fixtures::*;
+mod utils;
+use utils::*;
+
+fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+#[rstest]
+fn journal_hierarchy_from_config(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    std::fs::write(
+        dir.path().join("codex.toml"),
+        "[journal]\nformat = \"%d %A\"\nyear = \"%Y\"\nmonth = \"%m %B\"\n",
+    )
+    .unwrap();
+    tree.load();
+    let day = tree.journal_day(date(2023, 1, 31)).unwrap();
+    assert_eq!(day, "1-journal/1-2023/1-01-January/1-31-Tuesday");
+    assert_eq!(tree.journal_day(date(2023, 1, 31)).unwrap(), day);
+    write_body(dir.path(), &day, "# 31 Tuesday\n- [] carry me\n- ✅ done\n");
+
+    let next = tree.journal_day(date(2023, 2, 1)).unwrap();
+    assert_eq!(next, "1-journal/1-2023/2-02-February/1-01-Wednesday");
+    assert_eq!(tree.latest_journal(), next);
+    let body = std::fs::read_to_string(dir.path().join(&next).join("_.md")).unwrap();
+    assert!(body.contains("- [] carry me"));
+    assert!(!body.contains("done"));
+
+    // children of a day are not days
+    tree.create_node(Some(&next), Some("meeting")).unwrap();
+    assert_eq!(tree.latest_journal(), next);
+    assert!(check(dir.path()).is_clean());
+}
+
+#[rstest]
+fn flat_journal_rolls_over_tenth_day(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    assert_eq!(tree.latest_journal(), "1-journal");
+    for d in 1..10 {
+        tree.journal_day(date(2023, 3, d)).unwrap();
+    }
+    let ninth = tree.latest_journal();
+    assert_eq!(ninth, "1-journal/9-Thu-Mar-09-2023");
+    write_body(dir.path(), &ninth, "# ninth\n- [] still open\n");
+    let tenth = tree.journal_day(date(2023, 3, 10)).unwrap();
+    assert_eq!(tenth, "1-journal/10-Fri-Mar-10-2023");
+    assert!(tree.nodes.contains_key("1-journal/09-Thu-Mar-09-2023"));
+    let body = std::fs::read_to_string(dir.path().join(&tenth).join("_.md")).unwrap();
+    assert!(body.contains("- [] still open"));
+}
+
+#[rstest]
+fn levels_configured_after_flat_days(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    tree.journal_day(date(2023, 2, 27)).unwrap();
+    let flat = tree.journal_day(date(2023, 2, 28)).unwrap();
+    assert_eq!(flat, "1-journal/2-Tue-Feb-28-2023");
+    write_body(dir.path(), &flat, "# flat\n- [] carry me\n");
+    tree.create_node(Some(&flat), Some("meeting")).unwrap();
+    std::fs::write(
+        dir.path().join("codex.toml"),
+        "[journal]\nformat = \"%d %A\"\nyear = \"%Y\"\nmonth = \"%m %B\"\n",
+    )
+    .unwrap();
+    tree.load();
+    assert_eq!(tree.latest_journal(), flat);
+
+    let day = tree.journal_day(date(2023, 3, 1)).unwrap();
+    assert_eq!(day, "1-journal/3-2023/1-03-March/1-01-Wednesday");
+    assert_eq!(tree.latest_journal(), day);
+    let body = std::fs::read_to_string(dir.path().join(&day).join("_.md")).unwrap();
+    assert!(body.contains("- [] carry me"));
+}
+
+#[rstest]
+fn invalid_journal_config_falls_back(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    std::fs::write(dir.path().join("codex.toml"), "[journal]\nyear = \"%Q\"\n").unwrap();
+    tree.load();
+    let day = tree.journal_day(date(2023, 1, 31)).unwrap();
+    assert_eq!(day, "1-journal/1-Tue-Jan-31-2023");
+}
+
+#[rstest]
+fn todos_carry_across_gaps_once(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let first = tree.journal_day(date(2023, 3, 1)).unwrap();
+    write_body(
+        dir.path(),
+        &first,
+        "# first\n- ✅ project\n  - [] draft\n    - outline\n- [] call\n- ✅ done\n",
+    );
+    // nothing was written on the days in between
+    let fifth = tree.journal_day(date(2023, 3, 5)).unwrap();
+    let body = std::fs::read_to_string(dir.path().join(&fifth).join("_.md")).unwrap();
+    assert_eq!(
+        body,
+        "# Sun Mar 05 2023\n\n- project\n  - [] draft\n    - outline\n- [] call\n"
+    );
+    let original = std::fs::read_to_string(dir.path().join(&first).join("_.md")).unwrap();
+    assert!(original.contains("  - [>] draft\n"));
+    assert!(original.contains("- [>] call\n"));
+    assert_eq!(tree.search("outline").len(), 2);
+
+    // asking for the day again carries nothing twice
+    assert_eq!(tree.journal_day(date(2023, 3, 5)).unwrap(), fifth);
+    let again = std::fs::read_to_string(dir.path().join(&fifth).join("_.md")).unwrap();
+    assert_eq!(again, body);
+    let sixth = tree.journal_day(date(2023, 3, 6)).unwrap();
+    let body = std::fs::read_to_string(dir.path().join(&sixth).join("_.md")).unwrap();
+    assert_eq!(body.matches("- [] call").count(), 1);
+    assert_eq!(body.matches("- [] draft").count(), 1);
+    assert!(!body.contains("done"));
+}
+
+#[rstest]
+fn journal_day_without_a_journal_root(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    // as loaded from a codex missing its journal root, see fsck
+    tree.journal = String::new();
+    assert_eq!(
+        tree.journal_day(date(2023, 1, 31)).unwrap_err().code(),
+        "node-not-found"
+    );
+    assert_eq!(tree.today_node().unwrap_err().code(), "node-not-found");
+}
@@ -0,0 +1,164 @@
+#![allow(dead_code, unused_imports, unused_variables)]
+use codex::node::NodeMeta;
+use codex::tree::load::{LoadStats, CACHE_FILE};
+use codex::tree::Tree;
+use std::fs::{create_dir_all, metadata, remove_file, write, File};
+use std::path::Path;
+use std::time::Instant;
+
+use rstest::rstest;
+use rstest::*;
+mod fixtures;
+use fixtures::*;
+mod utils;
+use utils::*;
+
+fn write_node(dir: &Path, key: &str, name: &str) {
+    create_dir_all(dir.join(key)).unwrap();
+    write(
+        dir.join(key).join("meta.toml"),
+        NodeMeta::new(name.to_string()).to_toml(),
+    )
+    .unwrap();
+    write(
+        dir.join(key).join("_.md"),
+        format!("# {}\n- [ ] review\n", name),
+    )
+    .unwrap();
+}
+
+/// `projects` projects of `notes` notes each under the desk, returns the
+/// number of nodes with the roots
+fn generate_codex(dir: &Path, projects: usize, notes: usize) -> usize {
+    let (pw, nw) = (projects.to_string().len(), notes.to_string().len());
+    for project in 1..=projects {
+        let parent = format!("2-desk/{:0w$}-project-{}", project, project, w = pw);
+        write_node(dir, &parent, &format!("project {}", project));
+        for note in 1..=notes {
+            let key = format!("{}/{:0w$}-note-{}", parent, note, note, w = nw);
+            write_node(dir, &key, &format!("note {}", note));
+        }
+    }
+    projects * (notes + 1) + 2
+}
+
+fn load(dir: &Path) -> (Tree, LoadStats) {
+    let mut tree = Tree::build(dir.to_str().unwrap()).unwrap();
+    let stats = tree.load();
+    (tree, stats)
+}
+
+#[rstest]
+fn load_from_cache(initialdir: TempDir) {
+    let dir = initialdir.path();
+    let total = generate_codex(dir, 3, 12);
+
+    let _ = remove_file(dir.join(CACHE_FILE));
+    let (cold, stats) = load(dir);
+    assert_eq!(cold.nodes.len(), total);
+    assert_eq!(
+        stats,
+        LoadStats {
+            parsed: total,
+            cached: 0
+        }
+    );
+    assert_eq!(cold.nodes["2-desk"].children.len(), 3);
+    assert_eq!(cold.nodes["2-desk/2-project-2"].children.len(), 12);
+    assert_eq!(cold.tasks.values().flatten().count(), total - 2);
+
+    let (warm, stats) = load(dir);
+    assert_eq!(
+        stats,
+        LoadStats {
+            parsed: 0,
+            cached: total
+        }
+    );
+    assert_eq!(warm.nodes.len(), total);
+    assert_eq!(
+        warm.nodes["2-desk/2-project-2/07-note-7"].name,
+        cold.nodes["2-desk/2-project-2/07-note-7"].name
+    );
+
+    assert_eq!(warm.tasks.values().flatten().count(), total - 2);
+    assert_eq!(warm.search("review").len(), total - 2);
+
+    // an unchanged `_.md` is indexed from the cache without being read
+    let body = dir.join("2-desk/2-project-2/03-note-3/_.md");
+    let modified = metadata(&body).unwrap().modified().unwrap();
+    write(&body, "# note 3\n#jazz\n").unwrap();
+    File::options()
+        .write(true)
+        .open(&body)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    let (cached, _) = load(dir);
+    assert!(cached.nodes_by_tag("jazz").unwrap().is_empty());
+    assert_eq!(cached.tasks.values().flatten().count(), total - 2);
+    // and read again once it changes
+    write(&body, "# note 3\n#jazz\n").unwrap();
+    let (reread, _) = load(dir);
+    assert_eq!(reread.nodes_by_tag("jazz").unwrap().len(), 1);
+    assert_eq!(reread.tasks.values().flatten().count(), total - 3);
+
+    // only a changed meta.toml is parsed again
+    write_node(dir, "2-desk/2-project-2/07-note-7", "renamed");
+    let (changed, stats) = load(dir);
+    assert_eq!(
+        stats,
+        LoadStats {
+            parsed: 1,
+            cached: total - 1
+        }
+    );
+    assert_eq!(
+        changed.nodes["2-desk/2-project-2/07-note-7"].name,
+        "renamed"
+    );
+
+    // an unreadable cache is a cold start
+    write(dir.join(CACHE_FILE), "not json").unwrap();
+    let (_, stats) = load(dir);
+    assert_eq!(stats.parsed, total);
+}
+
+/// Benchmark, run with `cargo test --test test_load -- --ignored`
+#[rstest]
+#[ignore]
+fn load_10k_nodes_from_cache(initialdir: TempDir) {
+    let dir = initialdir.path();
+    let total = generate_codex(dir, 100, 99);
+
+    let _ = remove_file(dir.join(CACHE_FILE));
+    let start = Instant::now();
+    let (cold, stats) = load(dir);
+    let cold_load = start.elapsed();
+    assert_eq!(cold.nodes.len(), total);
+    assert_eq!(
+        stats,
+        LoadStats {
+            parsed: total,
+            cached: 0
+        }
+    );
+
+    let start = Instant::now();
+    let (warm, stats) = load(dir);
+    let warm_load = start.elapsed();
+    assert_eq!(
+        stats,
+        LoadStats {
+            parsed: 0,
+            cached: total
+        }
+    );
+    assert_eq!(warm.nodes.len(), total);
+    assert!(
+        warm_load < cold_load,
+        "warm {:?}, cold {:?}",
+        warm_load,
+        cold_load
+    );
+}
@@ -0,0 +1,87 @@
+#![allow(dead_code, unused_imports, unused_variables)]
+use codex::node::NodeMeta;
+use codex::tree::Tree;
+use std::fs::{read_to_string, write};
+
+use rstest::rstest;
+use rstest::*;
+mod fixtures;
+use fixtures::*;
+mod utils;
+use utils::*;
+
+#[rstest]
+fn mentions_turn_into_links(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let jazz = tree.create_node(Some("2-desk"), Some("blue note")).unwrap();
+    let a = tree.create_node(Some("2-desk"), Some("a")).unwrap();
+    let b = tree.create_node(Some("2-desk"), Some("b")).unwrap();
+    let meta_path = dir.path().join(&jazz).join("meta.toml");
+    let mut meta = NodeMeta::from_toml(&meta_path).unwrap();
+    meta.aliases = vec!["the label".to_string()];
+    write(&meta_path, meta.to_toml()).unwrap();
+    write_body(dir.path(), &jazz, "# blue note\nblue note records\n");
+    write_body(
+        dir.path(),
+        &a,
+        "# a\nBlue Note and the label, [[blue note]]\n`blue note`\n",
+    );
+    write_body(dir.path(), &b, "# b\nnothing here\nat blue-note\n");
+    tree.load();
+
+    let mentions = tree.unlinked_mentions(&jazz).unwrap();
+    let found: Vec<(&str, &str, u64, u64)> = mentions
+        .iter()
+        .map(|m| (m.node.as_str(), m.text.as_str(), m.line, m.char))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (a.as_str(), "Blue Note", 2, 0),
+            (a.as_str(), "the label", 2, 14),
+            (b.as_str(), "blue-note", 3, 3),
+        ]
+    );
+    assert_eq!(
+        mentions[0].context,
+        "Blue Note and the label, [[blue note]]"
+    );
+    assert_eq!(tree.nodes[&jazz].backlinks.len(), 1);
+
+    // picked mentions become links, stale picks are ignored
+    let picked = vec![(a.clone(), 2, 0), (a.clone(), 2, 14), (b.clone(), 9, 9)];
+    assert_eq!(tree.link_mentions(&jazz, &picked).unwrap(), 2);
+    let body = read_to_string(dir.path().join(&a).join("_.md")).unwrap();
+    assert_eq!(
+        body,
+        "# a\n[[Blue Note]] and [[the label]], [[blue note]]\n`blue note`\n"
+    );
+    let link = &tree.nodes[&a].links["the label"];
+    assert_eq!(
+        (link.node.as_str(), link.line, link.char),
+        (jazz.as_str(), 0, 0)
+    );
+    assert_eq!(tree.nodes[&jazz].backlinks.len(), 3);
+    let backlink = tree.nodes[&jazz]
+        .backlinks
+        .values()
+        .find(|backlink| backlink.text == "the label")
+        .unwrap();
+    assert_eq!((backlink.line, backlink.char), (2, 18));
+
+    // the links survive a reload of the body
+    tree.sync_links(&a).unwrap();
+    assert_eq!(tree.nodes[&a].links.len(), 3);
+    let left: Vec<String> = tree
+        .unlinked_mentions(&jazz)
+        .unwrap()
+        .into_iter()
+        .map(|m| m.node)
+        .collect();
+    assert_eq!(left, vec![b.clone()]);
+    assert_eq!(
+        tree.unlinked_mentions("2-desk/9-x").unwrap_err().code(),
+        "node-not-found"
+    );
+}
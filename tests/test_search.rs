@@ -0,0 +1,35 @@
+#![allow(dead_code, unused_imports, unused_variables)]
+use codex::tree::Tree;
+
+use rstest::rstest;
+use rstest::*;
+mod fixtures;
+use fixtures::*;
+mod utils;
+use utils::*;
+
+#[rstest]
+fn search_is_built_on_load(dir_and_tree: (TempDir, Tree)) {
+    let (dir, tree) = dir_and_tree;
+    let hits = tree.search("journal");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].node, "1-journal");
+    assert_eq!(hits[0].line, 1);
+    assert_eq!(hits[0].snippet, "# journal");
+}
+
+#[rstest]
+fn search_reindexes_updated_node(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let a = tree.create_node(Some("2-desk"), Some("a")).unwrap();
+    assert!(tree.search("\"giant steps\"").is_empty());
+    let body = tree.nodes.get(&a).unwrap().content_path();
+    std::fs::write(&body, "# a\n\nColtrane played Giant Steps\n").unwrap();
+    tree.reindex_node(&a);
+    let hits = tree.search("\"giant steps\"");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].node, a);
+    assert_eq!(hits[0].line, 3);
+    assert_eq!(tree.search("coltr*")[0].node, a);
+}
@@ -0,0 +1,55 @@
+#![allow(dead_code, unused_imports, unused_variables)]
+use codex::nvim::TreeService;
+use codex::tree::Tree;
+use tokio::runtime::Runtime;
+
+use rstest::rstest;
+use rstest::*;
+mod fixtures;
+use fixtures::*;
+mod utils;
+use utils::*;
+
+#[rstest]
+fn service_applies_commands_in_order(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let tree = dir_and_tree.1;
+    Runtime::new().unwrap().block_on(async move {
+        let service = TreeService::spawn(tree);
+        let creates: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .map(|name| {
+                let service = service.clone();
+                let args = vec!["2-desk".into(), name.to_string().into()];
+                tokio::spawn(async move { service.create(args).await.unwrap() })
+            })
+            .collect();
+        let reads: Vec<_> = (0..8)
+            .map(|_| {
+                let service = service.clone();
+                tokio::spawn(async move { service.read(|tree| tree.nodes.len()).await })
+            })
+            .collect();
+        for read in reads {
+            assert!(read.await.unwrap() >= 2);
+        }
+        let mut created = vec![];
+        for create in creates {
+            created.push(create.await.unwrap());
+        }
+        created.sort();
+        let ids: Vec<&str> = created.iter().map(|key| &key[7..8]).collect();
+        assert_eq!(ids, vec!["1", "2", "3"]);
+
+        let children = service
+            .read(|tree| tree.nodes["2-desk"].children.clone())
+            .await;
+        assert_eq!(children, created);
+        assert!(service
+            .move_node("nope".into(), "2-desk".into(), None)
+            .await
+            .is_err());
+        assert!(service.tick_updated("nope".into()).await.is_err());
+        assert!(service.fsck(false).await.unwrap().is_empty());
+    });
+}
@@ -0,0 +1,97 @@
+#![allow(dead_code, unused_imports, unused_variables)]
+use codex::export::site::{export_site, SiteOptions};
+use codex::tree::Tree;
+use std::fs::{read_to_string, write};
+
+use rstest::rstest;
+use rstest::*;
+mod fixtures;
+use fixtures::*;
+mod utils;
+use utils::*;
+
+#[rstest]
+fn site_of_public_nodes(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let a = tree.create_node(Some("2-desk"), Some("a")).unwrap();
+    let b = tree.create_node(Some(&a), Some("b")).unwrap();
+    let c = tree.create_node(Some(&a), Some("c")).unwrap();
+    let secret = tree.create_node(Some("2-desk"), Some("secret")).unwrap();
+    let shared = tree.create_node(Some(&secret), Some("shared")).unwrap();
+    write_body(
+        dir.path(),
+        &a,
+        "# a\n\n[[b]] and [[secret]] #jazz\n\n`[[code]]`\n",
+    );
+    write_body(dir.path(), &b, "# b\n\n- [x] done\n![cover](cover.png)\n");
+    write_body(dir.path(), &c, "# c <i>\n\nsee [[b]]\n");
+    write_body(dir.path(), &secret, "# secret\n[[b]]\n");
+    write_body(dir.path(), &shared, "# shared #public\n");
+    write(dir.path().join(&b).join("cover.png"), "png").unwrap();
+    tree.load();
+
+    let out = TempDir::new().unwrap();
+    let options = SiteOptions {
+        roots: vec![a.clone()],
+        tags: vec!["public".to_string()],
+    };
+    assert_eq!(export_site(&tree, &options, out.path()).unwrap(), 4);
+    let page = |key: &str| read_to_string(out.path().join(key).join("index.html")).unwrap();
+    assert!(!out.path().join(&secret).join("index.html").exists());
+
+    let a_page = page(&a);
+    // links to published nodes work, the rest are left as text
+    assert!(a_page.contains("<a href=\"../../2-desk/1-a/1-b/index.html\">b</a>"));
+    assert!(a_page.contains("<a class=\"missing\">secret</a>"));
+    assert!(a_page.contains("<code>[[code]]</code>"));
+    assert!(a_page.contains("<h2>Children</h2><ul><li><a href=\"../../2-desk/1-a/1-b/index.html\">b</a></li><li><a href=\"../../2-desk/1-a/2-c/index.html\">c</a></li></ul>"));
+    assert!(a_page.contains("<a href=\"../../tags/index.html#jazz\">#jazz</a>"));
+    // the desk is not published so it is not in the breadcrumbs
+    assert!(a_page.contains("<nav><a href=\"../../index.html\">codex</a> / a</nav>"));
+
+    let b_page = page(&b);
+    assert!(b_page.contains("<nav><a href=\"../../../index.html\">codex</a> / <a href=\"../../../2-desk/1-a/index.html\">a</a> / b</nav>"));
+    assert!(b_page.contains("<input disabled=\"\" type=\"checkbox\" checked=\"\"/>"));
+    // backlinks from unpublished nodes are left out
+    assert!(b_page.contains("<h2>Backlinks</h2><ul><li><a href=\"../../../2-desk/1-a/index.html\">a</a></li><li><a href=\"../../../2-desk/1-a/2-c/index.html\">c</a></li></ul>"));
+    assert!(b_page
+        .contains("<footer><a href=\"../../../2-desk/1-a/2-c/index.html\">c</a> &rarr;</footer>"));
+    assert_eq!(
+        read_to_string(out.path().join(&b).join("cover.png")).unwrap(),
+        "png"
+    );
+    assert!(page(&c)
+        .contains("<footer>&larr; <a href=\"../../../2-desk/1-a/1-b/index.html\">b</a></footer>"));
+    assert!(page(&c).contains("<title>c</title>"));
+
+    let index = read_to_string(out.path().join("index.html")).unwrap();
+    assert!(index.contains("<li><a href=\"2-desk/1-a/index.html\">a</a></li>"));
+    assert!(index.contains("<li><a href=\"2-desk/2-secret/1-shared/index.html\">shared</a></li>"));
+    let tags = read_to_string(out.path().join("tags/index.html")).unwrap();
+    assert!(tags.contains("<section id=\"jazz\"><h2>#jazz</h2><ul><li><a href=\"../2-desk/1-a/index.html\">a</a></li></ul></section>"));
+    assert!(tags.contains("id=\"public\""));
+
+    // keys are percent-encoded in links so odd names stay reachable
+    let csharp = tree.create_node(Some(&a), Some("C#")).unwrap();
+    let why = tree
+        .create_node(Some(&a), Some("why? 100% \"sure\""))
+        .unwrap();
+    write_body(dir.path(), &csharp, "# C#\n[[why? 100% \"sure\"]]\n");
+    tree.load();
+    export_site(&tree, &options, out.path()).unwrap();
+    let csharp_page = page(&csharp);
+    assert!(
+        csharp_page.contains("href=\"../../../2-desk/1-a/4-why%3F-100%25-%22sure%22/index.html\"")
+    );
+    assert!(page(&a).contains("<a href=\"../../2-desk/1-a/3-C%23/index.html\">C#</a>"));
+
+    let missing = SiteOptions {
+        roots: vec!["2-desk/9-x".to_string()],
+        tags: vec![],
+    };
+    assert_eq!(
+        export_site(&tree, &missing, out.path()).unwrap_err().code(),
+        "node-not-found"
+    );
+}
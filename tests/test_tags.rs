@@ -0,0 +1,93 @@
+#![allow(dead_code, unused_imports, unused_variables)]
+use codex::node::NodeMeta;
+use codex::tree::tags::TagCount;
+use codex::tree::{Error, Tree};
+use std::fs::read_to_string;
+
+use rstest::rstest;
+use rstest::*;
+mod fixtures;
+use fixtures::*;
+mod utils;
+use utils::*;
+
+fn tagged(tree: &Tree, tag: &str) -> Vec<String> {
+    let mut keys: Vec<String> = tree
+        .nodes_by_tag(tag)
+        .unwrap()
+        .iter()
+        .map(|node| node.id.clone())
+        .collect();
+    keys.sort();
+    keys
+}
+
+fn count(tag: &str, nodes: usize) -> TagCount {
+    TagCount {
+        tag: tag.to_string(),
+        nodes,
+    }
+}
+
+#[rstest]
+fn tags_index_meta_and_body(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let a = tree.create_node(Some("2-desk"), Some("a")).unwrap();
+    let b = tree.create_node(Some("2-desk"), Some("b")).unwrap();
+    write_body(dir.path(), &a, "# a\nworking on #Project/Codex today\n");
+    tree.reindex_node(&a);
+    assert_eq!(tree.add_tag(&b, "#project/").unwrap(), "project");
+    let meta = NodeMeta::from_toml(&dir.path().join(&b).join("meta.toml")).unwrap();
+    assert_eq!(meta.tags, vec!["project"]);
+
+    // parent tags take in the nodes of the tags below them
+    assert_eq!(tagged(&tree, "project"), vec![a.clone(), b.clone()]);
+    assert_eq!(tagged(&tree, "project/codex"), vec![a.clone()]);
+    assert!(tagged(&tree, "proj").is_empty());
+    let tags = tree.all_tags();
+    assert!(tags.contains(&count("project", 2)));
+    assert!(tags.contains(&count("project/codex", 1)));
+    assert!(tags.contains(&count("journal", 1)));
+
+    // inline tags live in the body, not in meta.toml
+    let err = tree.remove_tag(&a, "project/codex").unwrap_err();
+    assert_eq!(err.code(), "tree");
+    tree.remove_tag(&b, "project").unwrap();
+    assert_eq!(tagged(&tree, "project"), vec![a.clone()]);
+    assert_eq!(tree.add_tag(&b, "work").unwrap(), "work");
+
+    let renamed = tree.rename_tag("project", "work/side").unwrap();
+    assert_eq!(renamed, vec![a.clone()]);
+    let body = read_to_string(dir.path().join(&a).join("_.md")).unwrap();
+    assert_eq!(body, "# a\nworking on #work/side/codex today\n");
+    assert_eq!(tagged(&tree, "work"), vec![a.clone(), b.clone()]);
+    assert!(tree.nodes_by_tag("project").unwrap().is_empty());
+
+    // moved and deleted nodes leave the index
+    let moved = tree.move_node(&b, &a, None).unwrap();
+    assert_eq!(tagged(&tree, "work"), vec![a.clone(), moved.clone()]);
+    tree.delete_node(&a, true).unwrap();
+    assert!(tree
+        .tags
+        .values()
+        .all(|nodes| nodes.iter().all(|key| tree.nodes.contains_key(key))));
+    assert!(tree
+        .all_tags()
+        .iter()
+        .all(|tag| !tag.tag.starts_with("work")));
+
+    assert!(matches!(
+        tree.add_tag(&tree.desk.clone(), "journal"),
+        Err(Error::Tree(_))
+    ));
+    assert!(matches!(tree.add_tag("2-desk", "#"), Err(Error::Args(_))));
+    assert!(matches!(
+        tree.add_tag("2-desk/9-x", "x"),
+        Err(Error::NodeNotFound(_))
+    ));
+    assert!(matches!(
+        tree.rename_tag("work", "work/old"),
+        Err(Error::Args(_))
+    ));
+}
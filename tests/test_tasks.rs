@@ -0,0 +1,66 @@
+#![allow(dead_code, unused_imports, unused_variables)]
+use chrono::NaiveDate;
+use codex::node::tasks::{TaskFilter, TaskState};
+use codex::tree::Tree;
+
+use rstest::rstest;
+use rstest::*;
+mod fixtures;
+use fixtures::*;
+mod utils;
+use utils::*;
+
+#[rstest]
+fn tasks_across_nodes(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let a = tree.create_node(Some("2-desk"), Some("a")).unwrap();
+    let b = tree.create_node(Some("1-journal"), Some("b")).unwrap();
+    write_body(
+        dir.path(),
+        &a,
+        "# a\n- [] later\n- [] rent @due(2026-10-20)\n- ✅ done @due(2026-10-01)\n",
+    );
+    write_body(
+        dir.path(),
+        &b,
+        "# b\n- [] urgent @due(2026-10-20) @priority(high)\n",
+    );
+    tree.load();
+
+    let open = tree.query_tasks(&TaskFilter {
+        state: Some(TaskState::Open),
+        ..TaskFilter::default()
+    });
+    let listed: Vec<(&str, u64)> = open.iter().map(|t| (t.node.as_str(), t.line)).collect();
+    assert_eq!(
+        listed,
+        vec![(b.as_str(), 2), (a.as_str(), 3), (a.as_str(), 2)]
+    );
+
+    let due = tree.query_tasks(&TaskFilter {
+        due_before: NaiveDate::from_ymd_opt(2026, 10, 19),
+        ..TaskFilter::default()
+    });
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].state, TaskState::Done);
+
+    let desk = TaskFilter {
+        state: Some(TaskState::Open),
+        node: Some("2-desk".to_string()),
+        ..TaskFilter::default()
+    };
+    assert_eq!(tree.query_tasks(&desk).len(), 2);
+
+    // edits are picked up when the node is reindexed (on save)
+    write_body(dir.path(), &a, "# a\n- ✅ later\n");
+    tree.reindex_node(&a);
+    assert!(tree.query_tasks(&desk).is_empty());
+
+    // tasks follow a node when it moves
+    let moved = tree.move_node(&b, "2-desk", None).unwrap();
+    let tasks = tree.query_tasks(&desk);
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].node, moved);
+    assert_eq!(tasks[0].priority, Some(1));
+}
@@ -0,0 +1,147 @@
+#![allow(dead_code, unused_imports, unused_variables)]
+use chrono::Local;
+use codex::tree::Tree;
+use std::collections::HashMap;
+use std::fs::{create_dir_all, read_to_string, write};
+use std::path::Path;
+
+use rstest::rstest;
+use rstest::*;
+mod fixtures;
+use fixtures::*;
+mod utils;
+use utils::*;
+
+fn template(dir: &Path, name: &str, body: &str) {
+    create_dir_all(dir.join("templates")).unwrap();
+    write(dir.join("templates").join(format!("{}.md", name)), body).unwrap();
+}
+
+fn body(dir: &Path, key: &str) -> String {
+    read_to_string(dir.join(key).join("_.md")).unwrap()
+}
+
+#[rstest]
+fn create_nodes_from_templates(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let article = "# {{title}}\n\nin {{parent}} on {{date}}\nby {{prompt:Author}}\n";
+    template(dir.path(), "article", article);
+    template(dir.path(), "idea", "# {{title}}\n\n#idea\n");
+    let articles = tree.create_node(Some("2-desk"), Some("Articles")).unwrap();
+    let ideas = tree.create_node(Some("2-desk"), Some("Ideas")).unwrap();
+    assert_eq!(
+        tree.set_template("2-desk/9-x", Some("idea"))
+            .unwrap_err()
+            .code(),
+        "node-not-found"
+    );
+    assert_eq!(
+        tree.set_template(&articles, Some("nope"))
+            .unwrap_err()
+            .code(),
+        "invalid-args"
+    );
+    assert_eq!(
+        tree.set_template(&articles, Some("../x"))
+            .unwrap_err()
+            .code(),
+        "invalid-args"
+    );
+    tree.set_template(&articles, Some("article")).unwrap();
+    tree.add_tag(&ideas, "notes/ideas").unwrap();
+    write(
+        dir.path().join("codex.toml"),
+        "[templates.tags]\nnotes = \"article\"\n\"notes/ideas\" = \"idea\"\n",
+    )
+    .unwrap();
+    tree.load();
+    let names: Vec<String> = tree
+        .templates()
+        .unwrap()
+        .into_iter()
+        .map(|t| t.name)
+        .collect();
+    assert_eq!(names, vec!["article", "idea"]);
+    assert_eq!(tree.templates().unwrap()[0].prompts, vec!["Author"]);
+
+    // a child picks up its parent's template, prompts are left blank
+    let today = Local::now().format("%Y-%m-%d").to_string();
+    let article = tree
+        .create_node(Some(&articles), Some("Giant Steps"))
+        .unwrap();
+    assert_eq!(
+        body(dir.path(), &article),
+        format!("# Giant Steps\n\nin Articles on {}\nby \n", today)
+    );
+    // the most specific tag wins, tags in the template are indexed
+    let idea = tree.create_node(Some(&ideas), Some("Modes")).unwrap();
+    assert_eq!(body(dir.path(), &idea), "# Modes\n\n#idea\n");
+    assert!(tree
+        .nodes_by_tag("idea")
+        .unwrap()
+        .iter()
+        .any(|node| node.id == idea));
+    // other nodes keep the plain title
+    let plain = tree.create_node(Some("2-desk"), Some("Plain")).unwrap();
+    assert_eq!(body(dir.path(), &plain), "# Plain\n");
+
+    let mut parents: Vec<&str> = tree
+        .template_parents("article")
+        .iter()
+        .map(|node| node.id.as_str())
+        .collect();
+    parents.sort();
+    assert_eq!(parents, vec![articles.as_str()]);
+    assert_eq!(tree.template_parents("idea")[0].id, ideas);
+
+    // a template picked on creation wins over the parent's
+    let answers: HashMap<String, String> = vec![("Author".to_string(), "Coltrane".to_string())]
+        .into_iter()
+        .collect();
+    let picked = tree
+        .create_from_template(&ideas, "Naima", "article", &answers)
+        .unwrap();
+    assert_eq!(
+        body(dir.path(), &picked),
+        format!("# Naima\n\nin Ideas on {}\nby Coltrane\n", today)
+    );
+    let missing = tree.create_from_template(&ideas, "Lost", "nope", &answers);
+    assert_eq!(missing.unwrap_err().code(), "invalid-args");
+    assert_eq!(tree.nodes[&ideas].children.len(), 2);
+
+    // the template is kept in meta.toml and can be unset
+    tree.load();
+    assert_eq!(tree.nodes[&articles].template.as_deref(), Some("article"));
+    tree.set_template(&articles, None).unwrap();
+    tree.load();
+    assert_eq!(tree.nodes[&articles].template, None);
+    let untemplated = tree
+        .create_node(Some(&articles), Some("Blue Train"))
+        .unwrap();
+    assert_eq!(body(dir.path(), &untemplated), "# Blue Train\n");
+}
+
+#[rstest]
+fn default_template_and_broken_templates(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    write(
+        dir.path().join("codex.toml"),
+        "[templates]\ndefault = \"note\"\n",
+    )
+    .unwrap();
+    tree.load();
+    // a missing default template leaves the plain title
+    let plain = tree.create_node(Some("2-desk"), Some("Plain")).unwrap();
+    assert_eq!(body(dir.path(), &plain), "# Plain\n");
+    template(dir.path(), "note", "# {{title}} {{date:%Q}} {{unknown}}\n");
+    let root = tree.create_node(None, Some("Notes")).unwrap();
+    assert_eq!(body(dir.path(), &root), "# Notes {{date:%Q}} {{unknown}}\n");
+    let child = tree.create_node(Some("2-desk"), Some("Child")).unwrap();
+    assert_eq!(
+        body(dir.path(), &child),
+        "# Child {{date:%Q}} {{unknown}}\n"
+    );
+    assert!(tree.template_parents("note").is_empty());
+}
@@ -6,8 +6,9 @@
     unused_assignments,
     unused_mut
 )]
-use codex::node::{init_codex_repo, NodeLink};
-use codex::tree::Tree;
+use codex::node::{init_codex_repo, NodeLink, NodeMeta};
+use codex::tree::{Error, Tree};
+use std::path::Path;
 
 use rstest::rstest;
 use rstest::*;
@@ -80,7 +81,14 @@ fn create_and_link_nodes(dir_and_tree: (TempDir, Tree)) {
     dbg!(&backlink.to_toml());
     dbg!(&c_to_a_backlink);
     dbg!(&c_to_a_backlink.to_toml());
-    assert!(cnode_meta_toml.contains(&backlink.to_toml()));
+    assert!(cnode_meta_toml.contains("[[backlinks]]"));
+    assert!(meta_has_backlink_at(
+        cnode.metadata_path(),
+        &b,
+        &link_id,
+        0,
+        0
+    ));
 }
 
 #[rstest]
@@ -103,14 +111,14 @@ fn next_sibling(dir_and_tree: (TempDir, Tree)) {
     let b = tree.create_node(Some(&a), Some("b")).unwrap();
     let c = tree.create_node(Some("2-desk"), Some("c")).unwrap();
     let d = tree.create_node(Some("2-desk"), Some("d")).unwrap();
-    assert_eq!(tree.next_sibling(&b, true), b);
-    assert_eq!(tree.next_sibling(&b, false), b);
-    assert_eq!(tree.next_sibling(&a, true), d);
-    assert_eq!(tree.next_sibling(&a, false), c);
-    assert_eq!(tree.next_sibling(&c, true), a);
-    assert_eq!(tree.next_sibling(&c, false), d);
-    assert_eq!(tree.next_sibling(&d, true), c);
-    assert_eq!(tree.next_sibling(&d, false), a);
+    assert_eq!(tree.next_sibling(&b, true).unwrap(), b);
+    assert_eq!(tree.next_sibling(&b, false).unwrap(), b);
+    assert_eq!(tree.next_sibling(&a, true).unwrap(), d);
+    assert_eq!(tree.next_sibling(&a, false).unwrap(), c);
+    assert_eq!(tree.next_sibling(&c, true).unwrap(), a);
+    assert_eq!(tree.next_sibling(&c, false).unwrap(), d);
+    assert_eq!(tree.next_sibling(&d, true).unwrap(), c);
+    assert_eq!(tree.next_sibling(&d, false).unwrap(), a);
 }
 
 #[rstest]
@@ -175,5 +183,390 @@ fn sibling_count_order_of_magnitude_rollover(dir_and_tree: (TempDir, Tree)) {
     dbg!(&backlink.to_toml());
     dbg!(&two_to_one_backlink);
     dbg!(&two_to_one_backlink.to_toml());
-    assert!(cnode_meta_toml.contains(&backlink.to_toml()));
+    assert!(cnode_meta_toml.contains("[[backlinks]]"));
+    assert!(meta_has_backlink_at(
+        twonode.metadata_path(),
+        child,
+        &link_id,
+        0,
+        0
+    ));
+}
+
+#[rstest]
+fn link_text_with_legacy_delimiter(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let a = tree.create_node(Some("2-desk"), Some("a")).unwrap();
+    let b = tree.create_node(Some("2-desk"), Some("b")).unwrap();
+    let text = "this |,| that \"quoted\"";
+    write_body(dir.path(), &a, &format!("# a\n\n [[{}]]\n", text));
+    tree.link(text, &a, 3, 1, &b, 0, 0);
+    let anode = tree.nodes.get(&a).unwrap();
+    let link = anode.links.get(text).unwrap().clone();
+    assert!(meta_has_link(
+        anode.metadata_path(),
+        &text.to_string(),
+        &link
+    ));
+    tree.load();
+    let reloaded = tree.nodes.get(&a).unwrap().links.get(text).unwrap();
+    assert_eq!(reloaded, &link);
+}
+
+#[rstest]
+fn migrate_v1_meta(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let meta_path = dir.path().join("2-desk").join("meta.toml");
+    std::fs::write(
+        &meta_path,
+        r#"name = "desk"
+tags = ["desk"]
+links = ["x |,| y|,|1650000000|,|1-journal|,|4|,|2|,|text_ref"]
+backlinks = ["journal|,|1650000001|,|1-journal|,|1|,|0|,|name_ref"]
+created = "2022-04-15 10:00:00 +0000"
+updated = "2022-04-15 10:00:00 +0000"
+updates = 1
+internal = []
+"#,
+    )
+    .unwrap();
+    write_body(dir.path(), "2-desk", "# desk\n[[x |,| y]]\n");
+    tree.load();
+    let desk = tree.nodes.get("2-desk").unwrap();
+    let link = desk.links.get("x |,| y").unwrap();
+    assert_eq!(link.node, "1-journal");
+    assert_eq!((link.line, link.char), (4, 2));
+    let backlink = desk
+        .backlinks
+        .get(&("journal".to_string(), 1650000001))
+        .unwrap();
+    assert!(backlink.is_name_linked);
+    let rewritten = std::fs::read_to_string(&meta_path).unwrap();
+    assert!(rewritten.contains("version = 2"));
+    assert!(rewritten.contains("[[links]]"));
+    assert!(!rewritten.contains("|,|1650000000"));
+}
+
+#[rstest]
+fn move_node_across_parents(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let a = tree.create_node(Some("2-desk"), Some("a")).unwrap();
+    let x = tree.create_node(Some(&a), Some("x")).unwrap();
+    let b = tree.create_node(Some("2-desk"), Some("b")).unwrap();
+    write_body(dir.path(), &x, "[[b]]\n");
+    write_body(dir.path(), &b, "# b\n[[x]]\n");
+    tree.link("b", &x, 1, 0, &b, 0, 0);
+    tree.link("x", &b, 2, 0, &x, 0, 0);
+
+    let moved = tree.move_node(&a, &b, None).unwrap();
+    assert_eq!(moved, "2-desk/1-b/1-a");
+    let x = "2-desk/1-b/1-a/1-x";
+    let b = "2-desk/1-b";
+    assert_eq!(
+        nodekeys_in_dir(dir.path()),
+        vec![
+            "1-journal",
+            "2-desk/1-b/1-a/1-x",
+            "2-desk/1-b/1-a",
+            "2-desk/1-b",
+            "2-desk"
+        ]
+    );
+    assert_eq!(
+        tree.nodes.get("2-desk").unwrap().children,
+        vec![b.to_string()]
+    );
+    assert_eq!(tree.nodes.get(b).unwrap().children, vec![moved.clone()]);
+    assert_eq!(tree.nodes.get(&moved).unwrap().parent.as_deref(), Some(b));
+    assert_eq!(tree.nodes.get(x).unwrap().links.get("b").unwrap().node, b);
+    assert_eq!(tree.nodes.get(b).unwrap().links.get("x").unwrap().node, x);
+    assert!(tree
+        .nodes
+        .get(b)
+        .unwrap()
+        .backlinks
+        .values()
+        .all(|l| l.node == x));
+    assert!(tree
+        .nodes
+        .get(x)
+        .unwrap()
+        .backlinks
+        .values()
+        .all(|l| l.node == b));
+
+    let repo = git2::Repository::open(dir.path()).unwrap();
+    let index = repo.index().unwrap();
+    assert!(index
+        .get_path(Path::new("2-desk/1-b/1-a/_.md"), 0)
+        .is_some());
+    assert!(index.get_path(Path::new("2-desk/1-a/_.md"), 0).is_none());
+
+    tree.load();
+    assert!(tree.nodes.contains_key(x));
+    assert_eq!(tree.nodes.get(x).unwrap().links.get("b").unwrap().node, b);
+}
+
+#[rstest]
+fn move_node_reorders_siblings(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let first = tree.create_node(Some("2-desk"), Some("a")).unwrap();
+    let second = tree.create_node(Some("2-desk"), Some("a")).unwrap();
+    std::fs::write(tree.nodes.get(&second).unwrap().content_path(), "second\n").unwrap();
+    assert!(tree.move_node("2-desk", "2-desk", None).is_err());
+    assert!(tree.move_node("2-desk", &first, None).is_err());
+    assert!(tree.move_node(&first, &second, None).is_ok());
+    let moved = tree.move_node("2-desk/1-a/1-a", "2-desk", Some(1)).unwrap();
+    assert_eq!(moved, "2-desk/1-a");
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("2-desk/2-a/_.md")).unwrap(),
+        "second\n"
+    );
+    assert_eq!(
+        tree.nodes.get("2-desk").unwrap().children,
+        vec!["2-desk/1-a".to_string(), "2-desk/2-a".to_string()]
+    );
+}
+
+#[rstest]
+fn delete_node_reports_dangling_links(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let a = tree.create_node(Some("2-desk"), Some("a")).unwrap();
+    let child = tree.create_node(Some(&a), Some("child")).unwrap();
+    let b = tree.create_node(Some("2-desk"), Some("b")).unwrap();
+    tree.link("child", &b, 4, 2, &child, 0, 0);
+    tree.link("b", &a, 1, 0, &b, 0, 0);
+
+    assert!(tree.delete_node(&a, false).is_err());
+    assert!(tree.delete_node("2-desk", true).is_err());
+    let removal = tree.delete_node(&a, true).unwrap();
+    assert_eq!(removal.removed, vec![a.clone(), child.clone()]);
+    assert_eq!(removal.dangling.len(), 1);
+    let dangling = &removal.dangling[0];
+    assert_eq!(dangling.node, "2-desk/1-b");
+    assert_eq!(dangling.target, child);
+    assert_eq!((dangling.line, dangling.char), (4, 2));
+
+    assert_eq!(
+        nodekeys_in_dir(dir.path()),
+        vec!["1-journal", "2-desk/1-b", "2-desk"]
+    );
+    // b took the key a had, its link to child is gone
+    let bnode = tree.nodes.get("2-desk/1-b").unwrap();
+    assert!(bnode.backlinks.is_empty());
+    assert!(!bnode.links.contains_key("child"));
+    let meta = NodeMeta::from_toml(&bnode.metadata_path()).unwrap();
+    assert!(meta.links.is_empty());
+    assert_eq!(
+        tree.nodes.get("2-desk").unwrap().children,
+        vec!["2-desk/1-b".to_string()]
+    );
+}
+
+#[rstest]
+fn archive_node_creates_archive_root(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let a = tree.create_node(Some("2-desk"), Some("a")).unwrap();
+    tree.create_node(Some(&a), Some("child")).unwrap();
+    assert!(tree.archive_node(&a, false).is_err());
+    let archived = tree.archive_node(&a, true).unwrap();
+    assert_eq!(archived, "3-archive/1-a");
+    assert_eq!(tree.archive.as_deref(), Some("3-archive"));
+    assert!(tree.nodes.contains_key("3-archive/1-a/1-child"));
+    assert!(tree.archive_node(&archived, true).is_err());
+    tree.load();
+    assert_eq!(tree.archive.as_deref(), Some("3-archive"));
+    assert!(tree
+        .nodes
+        .get("3-archive")
+        .unwrap()
+        .tags
+        .contains("archive"));
+}
+
+#[rstest]
+fn links_follow_markdown_bodies(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let a = tree.create_node(Some("2-desk"), Some("a")).unwrap();
+    let b = tree.create_node(Some("2-desk"), Some("blue note")).unwrap();
+    let c = tree.create_node(Some("1-journal"), Some("c")).unwrap();
+    let c2 = tree.create_node(Some("2-desk"), Some("c")).unwrap();
+
+    // typed by hand, resolved by name on load, `c` is ambiguous
+    write_body(dir.path(), &a, "# a\nsee [[Blue Note]] and [[c]]\n");
+    tree.load();
+    let link = tree.nodes[&a].links.get("Blue Note").unwrap().clone();
+    assert_eq!(link.node, b);
+    assert!(!tree.nodes[&a].links.contains_key("c"));
+    let backlink = tree.nodes[&b].backlinks.values().next().unwrap().clone();
+    assert_eq!(
+        (backlink.node.as_str(), backlink.line, backlink.char),
+        (a.as_str(), 2, 4)
+    );
+    assert!(meta_has_backlink(
+        tree.nodes[&b].metadata_path(),
+        &b,
+        &backlink
+    ));
+
+    // a link picked from the client keeps its target as the text moves
+    tree.link("elsewhere", &a, 2, 0, &c2, 0, 0);
+    write_body(dir.path(), &a, "# a\n\nsee [[Blue Note]]\n[[elsewhere]]\n");
+    assert!(tree.sync_links(&a).unwrap());
+    assert_eq!(tree.nodes[&a].links.get("elsewhere").unwrap().node, c2);
+    let backlink = tree.nodes[&b].backlinks.values().next().unwrap();
+    assert_eq!((backlink.line, backlink.char), (3, 4));
+    assert_eq!(tree.nodes[&b].backlinks.len(), 1);
+    assert!(!tree.sync_links(&a).unwrap());
+
+    // deleting the text removes the link and the backlink
+    write_body(dir.path(), &a, "# a\n[[elsewhere]]\n");
+    tree.sync_links(&a).unwrap();
+    assert!(!tree.nodes[&a].links.contains_key("Blue Note"));
+    assert!(tree.nodes[&b].backlinks.is_empty());
+    tree.load();
+    assert_eq!(tree.nodes[&a].links.len(), 1);
+    assert!(tree.nodes[&b].backlinks.is_empty());
+    assert_eq!(tree.nodes[&c2].backlinks.len(), 1);
+}
+
+#[rstest]
+fn follow_heading_and_block_links(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let a = tree.create_node(Some("2-desk"), Some("a")).unwrap();
+    let b = tree.create_node(Some("2-desk"), Some("b")).unwrap();
+    write_body(dir.path(), &b, "# b\n## Giant Steps\ncoltrane ^abc123\n");
+    write_body(
+        dir.path(),
+        &a,
+        "# a\n[[b#Giant Steps]] [[b^abc123]] [[#a]]\n",
+    );
+    tree.load();
+    assert_eq!(tree.nodes[&a].links["b#Giant Steps"].node, b);
+    assert_eq!(tree.nodes[&a].links["#a"].node, a);
+    assert_eq!(
+        tree.follow_link(&a, "b#Giant Steps"),
+        Some((b.clone(), 2, 0))
+    );
+    assert_eq!(tree.follow_link(&a, "b^abc123"), Some((b.clone(), 3, 0)));
+
+    // jumps track edits to the target without touching the link
+    write_body(
+        dir.path(),
+        &b,
+        "# b\nnew intro\n\n  coltrane ^abc123\n## giant-steps\n",
+    );
+    assert_eq!(
+        tree.follow_link(&a, "b#Giant Steps"),
+        Some((b.clone(), 5, 0))
+    );
+    assert_eq!(tree.follow_link(&a, "b^abc123"), Some((b.clone(), 4, 2)));
+    // unsaved link text resolves by name
+    assert_eq!(tree.follow_link(&a, "b"), Some((b.clone(), 0, 0)));
+    assert_eq!(tree.follow_link(&a, "nothing#here"), None);
+
+    // a marker ending the text is part of the name
+    let csharp = tree.create_node(Some("2-desk"), Some("C#")).unwrap();
+    write_body(
+        dir.path(),
+        &csharp,
+        "# C#
+## Intro
+",
+    );
+    write_body(
+        dir.path(),
+        &a,
+        "# a
+[[C#]] [[C# # Intro]]
+",
+    );
+    tree.load();
+    assert_eq!(tree.nodes[&a].links["C#"].node, csharp);
+    assert_eq!(tree.follow_link(&a, "C#"), Some((csharp.clone(), 0, 0)));
+    assert_eq!(
+        tree.follow_link(&a, "C# # Intro"),
+        Some((csharp.clone(), 2, 0))
+    );
+}
+
+#[rstest]
+fn tree_errors_carry_codes(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let a = tree.create_node(Some("2-desk"), Some("a")).unwrap();
+    let missing = tree.next_sibling("2-desk/9-nope", false).unwrap_err();
+    assert_eq!(missing.code(), "node-not-found");
+    let moved = tree.move_node("2-desk", &a, None).unwrap_err();
+    assert_eq!(moved.code(), "tree");
+    let created = tree
+        .node_creation(vec![nvim_rs::Value::from(1)])
+        .unwrap_err();
+    assert_eq!(created.code(), "invalid-args");
+    assert_eq!(
+        created.to_value().as_str().unwrap(),
+        "invalid-args: invalid args to node_creation: [None]"
+    );
+}
+
+#[rstest]
+fn resolve_names_and_aliases(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let codex = tree.create_node(Some("2-desk"), Some("Codex")).unwrap();
+    let cafe = tree
+        .create_node(Some("2-desk"), Some("Café Notes"))
+        .unwrap();
+    let meta_path = dir.path().join(&codex).join("meta.toml");
+    let mut meta = NodeMeta::from_toml(&meta_path).unwrap();
+    meta.aliases = vec!["The Notebook".to_string()];
+    std::fs::write(&meta_path, meta.to_toml()).unwrap();
+    write_body(dir.path(), &codex, "# codex\n");
+    write_body(
+        dir.path(),
+        "2-desk",
+        "# desk\n[[Codex]] [[codex]] [[the notebook]] [[cafe\u{301} notes]]\n",
+    );
+    tree.load();
+    assert_eq!(tree.nodes[&codex].aliases, vec!["The Notebook"]);
+
+    let links = &tree.nodes["2-desk"].links;
+    for text in ["Codex", "codex", "the notebook"].iter() {
+        assert_eq!(links[*text].node, codex);
+        assert!(links[*text].is_name_linked);
+    }
+    assert_eq!(links["cafe\u{301} notes"].node, cafe);
+    assert_eq!(tree.resolve_name("THE-NOTEBOOK").unwrap(), codex);
+    assert_eq!(tree.resolve_link_text("desk / codex#Intro").unwrap(), codex);
+
+    // a second node going by the same name makes it ambiguous
+    let other = tree.create_node(Some(&codex), Some("notebook")).unwrap();
+    let meta_path = dir.path().join(&other).join("meta.toml");
+    let mut meta = NodeMeta::from_toml(&meta_path).unwrap();
+    meta.aliases = vec!["the notebook".to_string()];
+    std::fs::write(&meta_path, meta.to_toml()).unwrap();
+    tree.load();
+    match tree.resolve_name("The Notebook") {
+        Err(Error::Ambiguous(name, nodes)) => {
+            assert_eq!(name, "The Notebook");
+            assert_eq!(nodes, vec![codex.clone(), other.clone()]);
+        }
+        resolved => panic!("expected an ambiguous name, got {:?}", resolved),
+    }
+    assert_eq!(
+        tree.follow_link("2-desk", "the notebook"),
+        Some((codex.clone(), 0, 0))
+    );
+    assert_eq!(
+        tree.resolve_name("nothing").unwrap_err().code(),
+        "node-not-found"
+    );
 }
@@ -0,0 +1,88 @@
+#![allow(dead_code, unused_imports, unused_variables)]
+use codex::export::obsidian::{export_vault, Layout, VaultOptions};
+use codex::import::obsidian::import_vault;
+use codex::tree::Tree;
+use std::fs::{read_to_string, write};
+
+use rstest::rstest;
+use rstest::*;
+mod fixtures;
+use fixtures::*;
+mod utils;
+use utils::*;
+
+#[rstest]
+fn export_obsidian_vault(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let steps = tree
+        .create_node(Some("2-desk"), Some("Giant Steps"))
+        .unwrap();
+    let coltrane = tree.create_node(Some(&steps), Some("Coltrane")).unwrap();
+    let body = "# Giant Steps\n\nBy [[Coltrane]], see [[coltrane#Life]].\n\n![cover](cover.png)\n";
+    write_body(dir.path(), &steps, body);
+    write(dir.path().join(&steps).join("cover.png"), "png").unwrap();
+    tree.add_tag(&steps, "jazz").unwrap();
+    tree.load();
+    // a second Coltrane, Obsidian finds notes by name so it gets a number
+    let other = tree.create_node(Some("2-desk"), Some("Coltrane")).unwrap();
+    let body = "# Coltrane\n\n[[Giant Steps^abc123]] [[#Coltrane]] [[Nowhere]]\n";
+    write_body(dir.path(), &other, body);
+    tree.load();
+
+    let outside = TempDir::new().unwrap();
+    let out = outside.path().join("export");
+    assert_eq!(
+        export_vault(&tree, &VaultOptions::default(), &out).unwrap(),
+        5
+    );
+    let note = |path: &str| read_to_string(out.join(path)).unwrap();
+    assert!(out.join("journal.md").is_file());
+    assert!(out.join("desk/desk.md").is_file());
+    let steps_note = note("desk/Giant Steps/Giant Steps.md");
+    assert!(steps_note.starts_with("---\ntags:\n- jazz\ncreated: "));
+    assert!(steps_note.contains("\nupdates: 1\n---\n# Giant Steps\n"));
+    assert!(steps_note.contains("By [[Coltrane]], see [[Coltrane#Life|coltrane]]."));
+    assert_eq!(note("desk/Giant Steps/cover.png"), "png");
+    assert!(note("desk/Giant Steps/Coltrane.md").ends_with("---\n# Coltrane\n"));
+    assert!(
+        note("desk/Coltrane 2.md").ends_with("[[Giant Steps#^abc123]] [[#Coltrane]] [[Nowhere]]\n")
+    );
+
+    let flat = outside.path().join("flat");
+    let options = VaultOptions {
+        layout: Layout::Flat,
+        root: Some(steps.clone()),
+    };
+    assert_eq!(export_vault(&tree, &options, &flat).unwrap(), 2);
+    let flat_note = read_to_string(flat.join("Coltrane.md")).unwrap();
+    assert!(flat_note.starts_with("---\nparent: '[[Giant Steps]]'\n"));
+    assert!(read_to_string(flat.join("Giant Steps.md"))
+        .unwrap()
+        .contains("\nupdates: 1\n"));
+    assert!(flat.join("cover.png").is_file());
+
+    // the export imports back with its links and times
+    let stats = import_vault(&mut tree, &out, "2-desk").unwrap();
+    assert_eq!((stats.notes, stats.links), (5, 4));
+    let imported_steps = "2-desk/3-export/1-desk/1-Giant-Steps";
+    let imported = &tree.nodes[imported_steps];
+    assert_eq!(
+        imported.links["Coltrane"].node,
+        format!("{}/1-Coltrane", imported_steps)
+    );
+    assert_eq!(imported.tags, tree.nodes[&steps].tags);
+    assert_eq!(
+        imported.created.format("%FT%T").to_string(),
+        tree.nodes[&steps].created.format("%FT%T").to_string()
+    );
+
+    let missing = VaultOptions {
+        layout: Layout::Nested,
+        root: Some("2-desk/9-x".to_string()),
+    };
+    assert_eq!(
+        export_vault(&tree, &missing, &out).unwrap_err().code(),
+        "node-not-found"
+    );
+}
@@ -0,0 +1,101 @@
+#![allow(dead_code, unused_imports, unused_variables)]
+use codex::node::tasks::TaskFilter;
+use codex::node::NodeMeta;
+use codex::tree::watch::TreeChange::*;
+use codex::tree::Tree;
+use std::fs::{create_dir_all, remove_dir_all, rename, write};
+use std::path::Path;
+
+use rstest::rstest;
+use rstest::*;
+mod fixtures;
+use fixtures::*;
+mod utils;
+use utils::*;
+
+fn write_node(dir: &Path, key: &str, name: &str, body: &str) {
+    create_dir_all(dir.join(key)).unwrap();
+    write(
+        dir.join(key).join("meta.toml"),
+        NodeMeta::new(name.to_string()).to_toml(),
+    )
+    .unwrap();
+    write_body(dir, key, body);
+}
+
+#[rstest]
+fn refresh_follows_the_disk(dir_and_tree: (TempDir, Tree)) {
+    let dir = dir_and_tree.0;
+    let mut tree = dir_and_tree.1;
+    let a = tree.create_node(Some("2-desk"), Some("a")).unwrap();
+    assert_eq!(a, "2-desk/1-a");
+
+    // a directory pulled in from elsewhere loads whole, links and all
+    write_node(dir.path(), "2-desk/2-b", "b", "# b\nsee [[a]]\n");
+    write_node(dir.path(), "2-desk/2-b/1-c", "c", "# c\n");
+    let changes = tree.refresh_paths(&[
+        dir.path().join("2-desk/2-b/1-c/_.md"),
+        dir.path().join("2-desk/2-b"),
+    ]);
+    assert_eq!(
+        changes,
+        vec![
+            Added("2-desk/2-b".to_string()),
+            Added("2-desk/2-b/1-c".to_string())
+        ]
+    );
+    assert_eq!(
+        tree.nodes["2-desk"].children,
+        vec!["2-desk/1-a", "2-desk/2-b"]
+    );
+    assert_eq!(tree.nodes["2-desk/2-b"].children, vec!["2-desk/2-b/1-c"]);
+    assert_eq!(tree.nodes[&a].backlinks.len(), 1);
+
+    // body edits are reindexed, untouched nodes are not reported
+    write_body(dir.path(), &a, "# a\n- [ ] water the plants\n");
+    let changes = tree.refresh_paths(&[
+        dir.path().join(&a).join("_.md"),
+        dir.path().join("2-desk/2-b/meta.toml"),
+    ]);
+    assert_eq!(changes, vec![Updated(a.clone())]);
+    assert_eq!(tree.query_tasks(&TaskFilter::default()).len(), 1);
+
+    // the tree's own writes come back from the watcher as no change
+    let c = tree.create_node(Some(&a), Some("c")).unwrap();
+    tree.add_tag(&c, "jazz").unwrap();
+    tree.nodes.get_mut(&a).unwrap().tick_update_and_write_meta();
+    let changes = tree.refresh_paths(&[
+        dir.path().join(&c).join("_.md"),
+        dir.path().join(&c).join("meta.toml"),
+        dir.path().join(&a).join("meta.toml"),
+        dir.path().join(&a).join("_.md"),
+    ]);
+    assert!(changes.is_empty());
+
+    // renames show up as a removal and an addition
+    rename(dir.path().join("2-desk/2-b"), dir.path().join("2-desk/3-b")).unwrap();
+    let changes =
+        tree.refresh_paths(&[dir.path().join("2-desk/2-b"), dir.path().join("2-desk/3-b")]);
+    assert_eq!(
+        changes,
+        vec![
+            Removed("2-desk/2-b".to_string()),
+            Removed("2-desk/2-b/1-c".to_string()),
+            Added("2-desk/3-b".to_string()),
+            Added("2-desk/3-b/1-c".to_string())
+        ]
+    );
+    assert_eq!(
+        tree.nodes["2-desk"].children,
+        vec!["2-desk/1-a", "2-desk/3-b"]
+    );
+
+    remove_dir_all(dir.path().join("2-desk/3-b")).unwrap();
+    let changes = tree.refresh_paths(&[dir.path().join("2-desk/3-b/_.md")]);
+    assert_eq!(changes.len(), 2);
+    assert_eq!(tree.nodes["2-desk"].children, vec!["2-desk/1-a"]);
+    assert!(tree.nodes.keys().all(|key| !key.contains("b")));
+
+    assert_eq!(tree.node_key_for(&dir.path().join(".git/refs/1-x")), None);
+    assert_eq!(tree.node_key_for(&dir.path().join("codex.toml")), None);
+}
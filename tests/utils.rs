@@ -1,4 +1,4 @@
-use codex::node::{NodeLink, NodeMeta};
+use codex::node::{NodeLink, NodeMeta, META_VERSION};
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
@@ -33,11 +33,33 @@ pub fn nodekeys_in_dir<P: AsRef<Path>>(path: P) -> Vec<String> {
         .collect()
 }
 
+pub fn write_body<P: AsRef<Path>>(path: P, key: &str, body: &str) {
+    std::fs::write(path.as_ref().join(key).join("_.md"), body).unwrap();
+}
+
 pub fn meta_has_link<P: AsRef<Path>>(path: P, id: &String, link: &NodeLink) -> bool {
-    let meta = NodeMeta::from_toml(&path.as_ref());
-    meta.links.contains(&link.to_toml())
+    let meta = NodeMeta::from_toml(path.as_ref()).unwrap();
+    meta.links.contains(link)
 }
 pub fn meta_has_backlink<P: AsRef<Path>>(path: P, id: &String, backlink: &NodeLink) -> bool {
-    let meta = NodeMeta::from_toml(&path.as_ref());
-    meta.backlinks.contains(&backlink.to_toml())
+    let meta = NodeMeta::from_toml(path.as_ref()).unwrap();
+    meta.backlinks.contains(backlink)
+}
+/// Whether the v2 `[[backlinks]]` of a meta.toml hold one from `node`
+/// with `text` at `line` and `char`
+pub fn meta_has_backlink_at<P: AsRef<Path>>(
+    path: P,
+    node: &str,
+    text: &str,
+    line: u64,
+    char: u64,
+) -> bool {
+    let meta = NodeMeta::from_toml(path.as_ref()).unwrap();
+    meta.version == META_VERSION
+        && meta.backlinks.iter().any(|backlink| {
+            backlink.node == node
+                && backlink.text == text
+                && backlink.line == line
+                && backlink.char == char
+        })
 }
@@ -1,4 +1,5 @@
 local M = {}
+-- better set as remote under [git] in ~/.config/codex/codex.toml
 M.git_remote = "git@githost.net:user/codex.git"
 -- g.mapleader = ' '
 -- map('i', 'jk', '<esc>', opt)